@@ -1,13 +1,24 @@
 //! This module contains [Context] and related types.
 
 mod audioloop;
+mod classes;
+mod mixer;
+mod registrations;
+mod resample;
+mod sample;
+mod snapshot;
 
 use std::ffi::{c_void, CStr, CString};
 use std::fmt;
 use std::marker::PhantomData;
 use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -18,15 +29,32 @@ use zengarden_raw::{
     ZGReceiverMessagePair,
 };
 use zengarden_raw::{
-    zg_context_register_external_object, zg_context_register_receiver, zg_context_send_message,
-    ZGGraph, ZGObject,
+    zg_context_get_num_graphs, zg_context_get_object_labels, zg_context_is_external_object,
+    zg_context_register_external_object, zg_context_register_memorymapped_abstraction,
+    zg_context_register_receiver, zg_context_send_message,
+    zg_context_unregister_memorymapped_abstraction, zg_mem_free, ZGGraph, ZGObject,
 };
 
 use crate::graph::Graph;
 use crate::message::Message;
 use crate::object::external::MessageObject;
+use crate::plugin::{self, Plugin};
+use crate::table::Table;
 
-pub use audioloop::{AudioLoop, AudioLoopF32, AudioLoopI16, Error as AudioLoopError};
+pub use audioloop::{
+    AudioLoop, AudioLoopF32, AudioLoopF64, AudioLoopI16, AudioLoopI24, AudioLoopI24Packed,
+    AudioLoopI32, AudioLoopU8, Error as AudioLoopError, Layout, SampleLoop,
+};
+pub use classes::ObjectClass;
+pub use mixer::{ContextMixer, Error as MixerError, MixerControl};
+pub use resample::ResamplingLoop;
+pub use sample::{Dither, I24Packed, Sample, I24};
+pub use snapshot::{ObjectState, Snapshot};
+
+use registrations::Registrations;
+
+/// [Context::swap_graphs] stops waiting for the crossfade after this many times its duration.
+pub const SWAP_TIMEOUT_FACTOR: u32 = 4;
 
 /// [Context] represents a Pure Data context. There can be multiple contexts, each with its own
 /// configuration (i.e. sample rate, block size, etc.) and audio loop. Contexts aren't supposed to
@@ -35,35 +63,42 @@ pub use audioloop::{AudioLoop, AudioLoopF32, AudioLoopI16, Error as AudioLoopErr
 #[derive(Debug, Clone)]
 pub struct Context<D: Dispatcher, L: AudioLoop> {
     pub(crate) raw_context: Arc<RwLock<*mut PdContext>>,
-    config: Config,
+    config: Arc<RwLock<Config>>,
     audio_loop: Arc<Mutex<L>>,
+    // input frame of the fallback of [Context::process], kept so the audio thread doesn't allocate
+    in_frame: Arc<Mutex<Vec<L::SampleType>>>,
+    plugins: Arc<Mutex<Vec<Plugin>>>,
+    registrations: Arc<Mutex<Registrations>>,
     _dispatcher: PhantomData<D>,
 }
 
 impl<D: Dispatcher, L: AudioLoop> Context<D, L> {
-    /// [Context] initializer.
+    /// [Context] initializer. The config is checked with [Config::validate].
     pub fn new(config: Config) -> Result<Self, Error> {
+        config.validate()?;
+
         // Thread-safity for the user data is implemented on the ZenGarden's side. But in case of
         // threading issues this should be the first place to look.
+        let raw_context = Self::init_raw_context(
+            &config,
+            Box::into_raw(Box::new(D::UserData::default())) as *mut c_void,
+        )?;
         let result = Self {
-            raw_context: Self::init_raw_context(
-                &config,
-                Box::into_raw(Box::new(D::UserData::default())) as *mut c_void,
-            )?,
+            raw_context: Arc::new(RwLock::new(raw_context)),
             audio_loop: Default::default(),
-            config: config.clone(),
+            in_frame: Default::default(),
+            plugins: Default::default(),
+            registrations: Default::default(),
+            config: Arc::new(RwLock::new(config.clone())),
             _dispatcher: Default::default(),
         };
 
-        result.init_buffers(config.blocksize, config.input_ch_num, config.output_ch_num);
+        result.init_audio_loop(&config);
 
         Ok(result)
     }
 
-    fn init_raw_context(
-        config: &Config,
-        user_data: *mut c_void,
-    ) -> Result<Arc<RwLock<*mut PdContext>>, Error> {
+    fn init_raw_context(config: &Config, user_data: *mut c_void) -> Result<*mut PdContext, Error> {
         let raw_context = unsafe {
             zg_context_new(
                 config.input_ch_num as i32,
@@ -79,7 +114,7 @@ impl<D: Dispatcher, L: AudioLoop> Context<D, L> {
             return Err(Error::Initializing);
         }
 
-        Ok(Arc::new(RwLock::new(raw_context)))
+        Ok(raw_context)
     }
 
     unsafe extern "C" fn raw_callback(
@@ -89,14 +124,16 @@ impl<D: Dispatcher, L: AudioLoop> Context<D, L> {
     ) -> *mut c_void {
         let data = (udata as *mut D::UserData).as_mut().unwrap();
 
-        match msg_t {
+        // the dispatcher is user code, which must not unwind into ZenGarden
+        panic::catch_unwind(AssertUnwindSafe(|| match msg_t {
             ZGCallbackFunction::ZG_PRINT_STD | ZGCallbackFunction::ZG_PRINT_ERR => {
                 Self::print_dispatcher(msg_t, data, ptr)
             }
             ZGCallbackFunction::ZG_PD_DSP => Self::switch_dsp_dispatcher(data, ptr),
             ZGCallbackFunction::ZG_RECEIVER_MESSAGE => Self::receiver_message_dispatcher(data, ptr),
             ZGCallbackFunction::ZG_CANNOT_FIND_OBJECT => Self::obj_not_found_dispatcher(data, ptr),
-        }
+        }))
+        .unwrap_or(ptr::null::<c_void>() as *mut _)
     }
 
     unsafe fn print_dispatcher(
@@ -104,7 +141,7 @@ impl<D: Dispatcher, L: AudioLoop> Context<D, L> {
         udata: &mut D::UserData,
         str_ptr: *mut c_void,
     ) -> *mut c_void {
-        let msg: String = CString::from_raw(str_ptr as *mut c_char)
+        let msg: String = CStr::from_ptr(str_ptr as *const c_char)
             .to_string_lossy()
             .into();
         match msg_t {
@@ -140,7 +177,7 @@ impl<D: Dispatcher, L: AudioLoop> Context<D, L> {
         udata: &mut D::UserData,
         raw_name: *mut c_void,
     ) -> *mut c_void {
-        let name: String = CString::from_raw(raw_name as *mut c_char)
+        let name: String = CStr::from_ptr(raw_name as *const c_char)
             .to_string_lossy()
             .into();
         match D::cannot_find_obj(name, udata) {
@@ -151,11 +188,14 @@ impl<D: Dispatcher, L: AudioLoop> Context<D, L> {
         }
     }
 
-    fn init_buffers(&self, blocksize: u16, in_ch_num: u16, out_ch_num: u16) {
-        self.audio_loop
-            .lock()
-            .unwrap()
-            .init_buffers(blocksize, in_ch_num, out_ch_num);
+    fn init_audio_loop(&self, config: &Config) {
+        let mut audio_loop = self.audio_loop.lock().unwrap();
+        audio_loop.init_buffers(config.blocksize, config.input_ch_num, config.output_ch_num);
+        audio_loop.init_sample_rates(config.sample_rate, config.device_sample_rate());
+
+        let mut in_frame = self.in_frame.lock().unwrap();
+        in_frame.clear();
+        in_frame.reserve(config.input_ch_num as usize);
     }
 
     /// Borrow user data.
@@ -190,6 +230,61 @@ impl<D: Dispatcher, L: AudioLoop> Context<D, L> {
             .map(ToOwned::to_owned)
     }
 
+    /// Process a buffer of frames in the given layout, e.g. the per-channel slices of a plugin
+    /// host laid out one after another. `input` holds the frames of the input channels and
+    /// `output` the same number of frames of the output channels.
+    ///
+    /// If the audio loop supports the layout directly (i.e. [Layout::Planar] with [AudioLoopF32]
+    /// and [Layout::Interleaved] with [AudioLoopI16]) and the buffers are exactly one block long,
+    /// they are passed to ZenGarden without copying and without the block of latency of
+    /// [Context::next_frame]. Otherwise the frames are processed one by one with
+    /// [Context::next_frame]. Don't mix the two on the same context, as the block buffered in
+    /// the audio loop would be skipped.
+    pub fn process(
+        &self,
+        layout: Layout,
+        input: &[L::SampleType],
+        output: &mut [L::SampleType],
+    ) -> Result<(), AudioLoopError> {
+        let (in_ch_num, out_ch_num) = {
+            // not cloned, so the audio thread doesn't allocate
+            let config = self.config.read().unwrap();
+            (config.input_ch_num as usize, config.output_ch_num as usize)
+        };
+        let frames = match out_ch_num {
+            0 => input.len() / in_ch_num.max(1),
+            _ => output.len() / out_ch_num,
+        };
+        if input.len() != frames * in_ch_num || output.len() != frames * out_ch_num {
+            return Err(AudioLoopError::WrongBufferSize);
+        }
+
+        let raw_context = self.raw_context.read().unwrap();
+        let mut audio_loop = self.audio_loop.lock().unwrap();
+        if audio_loop.process_block(*raw_context, layout, input, output) {
+            return Ok(());
+        }
+
+        let mut in_frame = self.in_frame.lock().unwrap();
+        for frame in 0..frames {
+            in_frame.clear();
+            in_frame
+                .extend((0..in_ch_num).map(|ch| input[layout.index(frame, ch, frames, in_ch_num)]));
+            let out_frame = audio_loop.next_frame(*raw_context, &in_frame)?;
+            for (ch, sample) in out_frame.iter().enumerate() {
+                output[layout.index(frame, ch, frames, out_ch_num)] = *sample;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the output latency: a block at the context sample rate, plus the latency added by
+    /// the audio loop (e.g. [ResamplingLoop]).
+    pub fn latency(&self) -> Duration {
+        self.config.read().unwrap().block_duration() + self.audio_loop.lock().unwrap().latency()
+    }
+
     /// Send a message to a receiver.
     pub fn send_message(&self, receiver: &str, message: Message) {
         unsafe {
@@ -211,6 +306,8 @@ impl<D: Dispatcher, L: AudioLoop> Context<D, L> {
                 .expect(&format!("Can't initialize CString from {}", receiver));
             zg_context_register_receiver(*self.raw_context.read().unwrap(), raw_name.as_ptr());
         }
+        let mut registrations = self.registrations.lock().unwrap();
+        registrations.receivers.insert(receiver.to_owned());
     }
 
     /// Unregister a receiver for this context.
@@ -220,11 +317,16 @@ impl<D: Dispatcher, L: AudioLoop> Context<D, L> {
                 .expect(&format!("Can't initialize CString from {}", receiver));
             zg_context_unregister_receiver(*self.raw_context.read().unwrap(), raw_name.as_ptr());
         }
+        self.registrations
+            .lock()
+            .unwrap()
+            .receivers
+            .remove(receiver);
     }
 
-    /// Borrow config.
-    pub fn config(&self) -> &Config {
-        &self.config
+    /// Get the config. It's a copy, which isn't updated by [Context::reconfigure].
+    pub fn config(&self) -> Config {
+        self.config.read().unwrap().clone()
     }
 
     /// Register an external such that the context can instantiate instances of it. If an object
@@ -232,10 +334,9 @@ impl<D: Dispatcher, L: AudioLoop> Context<D, L> {
     pub fn register_external(
         &self,
         name: &str,
-        factory: unsafe extern "C" fn(*mut ZGMessage, *mut ZGGraph) -> *mut ZGObject
+        factory: unsafe extern "C" fn(*mut ZGMessage, *mut ZGGraph) -> *mut ZGObject,
     ) {
         unsafe {
-
             let raw_str = CString::new(name).expect("Can't initialize external name");
             zg_context_register_external_object(
                 *self.raw_context.read().unwrap(),
@@ -243,15 +344,246 @@ impl<D: Dispatcher, L: AudioLoop> Context<D, L> {
                 Some(factory),
             );
         }
+        let mut registrations = self.registrations.lock().unwrap();
+        registrations.externals.insert(name.to_owned(), factory);
+    }
+
+    /// Returns the object classes the context can instantiate, i.e. the built-in ones and the
+    /// registered externals, sorted by name. Aliases such as **"f"** for **"float"** are listed
+    /// as separate classes. Abstractions aren't included.
+    ///
+    /// Inlets and outlets are found by creating an instance of each class in a scratch graph.
+    pub fn object_classes(&self) -> Vec<ObjectClass> {
+        let raw_context = *self.raw_context.read().unwrap();
+        let names: Vec<String> = unsafe {
+            let mut n = 0;
+            let labels = zg_context_get_object_labels(raw_context, &mut n);
+            if labels.is_null() {
+                Vec::new()
+            } else {
+                let names = std::slice::from_raw_parts(labels, n as usize)
+                    .iter()
+                    .map(|label| CStr::from_ptr(*label).to_string_lossy().into_owned())
+                    .collect();
+                // the labels themselves belong to the context
+                zg_mem_free(labels as *mut c_void);
+                names
+            }
+        };
+
+        let scratch = Graph::new_empty(self);
+        names
+            .into_iter()
+            .map(|name| {
+                let raw_name = CString::new(name.as_str()).expect("Can't initialize class name");
+                let external =
+                    unsafe { zg_context_is_external_object(raw_context, raw_name.as_ptr()) };
+                ObjectClass::probe(&scratch, name, external)
+            })
+            .collect()
+    }
+
+    /// Get the global table with the given name. Returns `None` if there's no such table in any
+    /// graph attached to the context.
+    pub fn table(&self, name: &str) -> Option<Table> {
+        Table::for_name(self, name)
+    }
+
+    /// Swap the `old` graph for the `new` one, crossfading their `[dac~]` outputs with an
+    /// equal-power curve over `crossfade_ms`. A hard [Graph::detach]/[Graph::attach] swap clicks.
+    ///
+    /// `new` is attached silent (unless it's already attached) and both graphs run for the
+    /// crossfade duration. Then `old` is detached and its gain is restored, so it can be attached
+    /// again later. Both graphs should be top-level graphs of this context. With `crossfade_ms` of
+    /// 0 the graphs are swapped at once.
+    ///
+    /// If `old` isn't attached, `new` is attached at full gain right away.
+    ///
+    /// This blocks until the audio loop has processed the crossfade, so it must not be called
+    /// from the thread which calls [Context::next_frame]. If the audio loop doesn't run, it gives
+    /// up waiting after [SWAP_TIMEOUT_FACTOR] times the crossfade duration (plus a few blocks) and
+    /// detaches `old` without the fade being heard.
+    pub fn swap_graphs(&self, old: &Graph<'_>, new: &Graph<'_>, crossfade_ms: f32) {
+        if !old.is_attached() {
+            new.with_context_locked(|| {
+                if !new.is_attached() {
+                    new.fade_output(0.0, true);
+                    new.attach();
+                }
+            });
+            return;
+        }
+
+        new.with_context_locked(|| {
+            if !new.is_attached() {
+                new.fade_output(0.0, false);
+                new.attach();
+            }
+            new.fade_output(crossfade_ms, true);
+            old.fade_output(crossfade_ms, false);
+        });
+
+        let block = self.config.read().unwrap().block_duration();
+        let crossfade = Duration::from_secs_f32(crossfade_ms.max(0.0) / 1000.0);
+        let deadline = Instant::now() + crossfade * SWAP_TIMEOUT_FACTOR + block * 4;
+        while old.is_fading_output() && Instant::now() < deadline {
+            thread::sleep(block);
+        }
+
+        old.with_context_locked(|| {
+            if old.is_fading_output() {
+                // gave up waiting, so the fade-in is finished at once as well
+                new.fade_output(0.0, true);
+            }
+            old.detach();
+            old.fade_output(0.0, true);
+        });
+    }
+
+    /// Apply a new configuration, e.g. when the audio device sample rate or block size changes.
+    ///
+    /// ZenGarden can't change the configuration of a running context, so the internal context is
+    /// recreated with the new one. The registered receivers, externals and abstractions are
+    /// registered again, every graph created with [Graph::from_file], [Graph::from_str] or
+    /// [Graph::new_empty] is reloaded from the netlist it was created from (and attached again if
+    /// it was attached), and the table contents are copied to the tables with the same name in the
+    /// reloaded graph. The [Graph]s keep working and refer to the reloaded graphs from then on,
+    /// but edits made since they were loaded are lost, as are named `[value]`s and any other
+    /// object state (keep them with [Context::snapshot] and [Context::restore]).
+    ///
+    /// The old graphs and context are kept until the context is dropped, so the
+    /// [Object](crate::object::Object)s and subgraphs taken from the graphs before stay valid, but
+    /// they refer to the old graphs, which aren't processed any more.
+    /// [ObjectId](crate::graph::ObjectId)s are invalidated. A graph created with
+    /// [Graph::from_raw] isn't reloaded, so the context can't be reconfigured while such a graph
+    /// is attached.
+    ///
+    /// The audio loop is blocked until the graphs are reloaded and its buffers are initialized
+    /// for the new configuration. If the config is invalid (see [Config::validate]), a graph which
+    /// isn't tracked is attached, or a graph can't be reloaded, the context is left unchanged.
+    pub fn reconfigure(&self, config: Config) -> Result<(), Error> {
+        config.validate()?;
+        let mut raw_context = self.raw_context.write().unwrap();
+        let mut registrations = self.registrations.lock().unwrap();
+
+        unsafe {
+            // the old context deletes its attached graphs, which the untracked ones own themselves
+            if zg_context_get_num_graphs(*raw_context) as usize != registrations.attached_graphs() {
+                return Err(Error::UntrackedGraph);
+            }
+
+            let user_data = zg_context_get_userinfo(*raw_context);
+            let new_context = Self::init_raw_context(&config, user_data)?;
+            if !registrations.move_into(new_context) {
+                zg_context_delete(new_context);
+                return Err(Error::Reloading);
+            }
+            registrations.retire_context(*raw_context);
+            *raw_context = new_context;
+        }
+
+        *self.config.write().unwrap() = config.clone();
+        self.init_audio_loop(&config);
+        Ok(())
+    }
+
+    /// Take a [Snapshot] of the named `[value]`s, the tables, and the `[float]`s, `[tgl]`s and
+    /// message boxes of the graphs created with [Graph::from_file], [Graph::from_str] or
+    /// [Graph::new_empty], including the ones in their subgraphs and abstractions. Each graph is
+    /// captured with the context locked, so its state is consistent between blocks.
+    pub fn snapshot(&self) -> Snapshot {
+        let _raw_context = self.raw_context.read().unwrap();
+        let graphs = self.registrations.lock().unwrap().graphs();
+
+        let mut snapshot = Snapshot::default();
+        for raw in graphs {
+            let graph = Graph::child(raw.load(Ordering::Acquire));
+            graph.with_context_locked(|| snapshot.capture(&graph));
+        }
+        snapshot
+    }
+
+    /// Restore a [Snapshot] taken with [Context::snapshot]. The graphs are matched by the order
+    /// they were created in and the objects by their position in the graphs, so a snapshot can be
+    /// recalled as long as the patches haven't changed. Objects which can't be matched are left
+    /// unchanged, as are the tables and `[value]`s missing from the snapshot. Restoring doesn't
+    /// send anything from the objects, like Pd's `set` method.
+    pub fn restore(&self, snapshot: &Snapshot) {
+        let _raw_context = self.raw_context.read().unwrap();
+        let graphs = self.registrations.lock().unwrap().graphs();
+
+        for (index, raw) in graphs.iter().enumerate() {
+            let graph = Graph::child(raw.load(Ordering::Acquire));
+            graph.with_context_locked(|| snapshot.restore(index, &graph));
+        }
+    }
+
+    /// Remember the graph, so it's reloaded by [Context::reconfigure].
+    pub(crate) fn track_graph(&self, raw: &Arc<AtomicPtr<ZGGraph>>, netlist: Option<CString>) {
+        if !raw.load(Ordering::Acquire).is_null() {
+            let mut registrations = self.registrations.lock().unwrap();
+            registrations.track_graph(raw, netlist);
+        }
+    }
+
+    /// Register an abstraction from memory, so it can be instantiated by name without being
+    /// looked up on disk. This allows bundling abstractions in the binary.
+    pub fn register_abstraction(&self, name: &str, netlist: &str) {
+        unsafe {
+            let raw_name = CString::new(name).expect("Can't initialize abstraction name");
+            let raw_netlist =
+                CString::new(netlist).expect("Can't build CString from abstraction netlist");
+            zg_context_register_memorymapped_abstraction(
+                *self.raw_context.read().unwrap(),
+                raw_name.as_ptr(),
+                raw_netlist.as_ptr(),
+            );
+            let mut registrations = self.registrations.lock().unwrap();
+            registrations
+                .abstractions
+                .insert(name.to_owned(), raw_netlist);
+        }
+    }
+
+    /// Unregister an abstraction previously registered with [Context::register_abstraction].
+    pub fn unregister_abstraction(&self, name: &str) {
+        unsafe {
+            let raw_name = CString::new(name).expect("Can't initialize abstraction name");
+            zg_context_unregister_memorymapped_abstraction(
+                *self.raw_context.read().unwrap(),
+                raw_name.as_ptr(),
+            );
+        }
+        self.registrations.lock().unwrap().abstractions.remove(name);
+    }
+
+    /// Load a plugin and register all its externals. The plugin library is kept loaded as long as
+    /// the context lives.
+    pub fn load_plugin<P: AsRef<Path>>(&self, path: P) -> Result<(), plugin::Error> {
+        let plugin = Plugin::load(path)?;
+        plugin.registry().register_into(self);
+        self.plugins.lock().unwrap().push(plugin);
+        Ok(())
+    }
+
+    /// Load all plugins from the given directory and register their externals.
+    pub fn load_plugins<P: AsRef<Path>>(&self, dir: P) -> Result<(), plugin::Error> {
+        for plugin in Plugin::load_dir(dir)? {
+            plugin.registry().register_into(self);
+            self.plugins.lock().unwrap().push(plugin);
+        }
+
+        Ok(())
     }
 }
 
 impl<D: Dispatcher, L: AudioLoop> Drop for Context<D, L> {
     fn drop(&mut self) {
         unsafe {
-            // we drop only the latest instance
-            if let Some(raw) = Arc::get_mut(&mut self.raw_context) {
-                zg_context_delete(*(raw.write().unwrap()));
+            // we drop only the latest instance, the tables only keep weak references
+            if Arc::strong_count(&self.raw_context) == 1 {
+                self.registrations.lock().unwrap().delete_retired();
+                zg_context_delete(*self.raw_context.write().unwrap());
             }
         }
     }
@@ -289,7 +621,10 @@ pub trait Dispatcher: fmt::Debug + Clone {
 }
 
 /// Context configuration.
+///
+/// The missing fields of a deserialized config are taken from [Config::default].
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Config {
     /// The number of input channels.
     pub input_ch_num: u16,
@@ -299,6 +634,11 @@ pub struct Config {
     pub blocksize: u16,
     /// The sample rate.
     pub sample_rate: u32,
+    /// The sample rate of the audio device, if it's different from the context one. The context
+    /// frames are converted by [ResamplingLoop], other audio loops ignore it.
+    pub device_sample_rate: Option<u32>,
+    /// Directories in which abstractions are looked up, after the patch directory.
+    pub search_paths: Vec<PathBuf>,
 }
 
 impl Default for Config {
@@ -308,6 +648,8 @@ impl Default for Config {
             output_ch_num: 2,
             blocksize: 64,
             sample_rate: 44100,
+            device_sample_rate: None,
+            search_paths: Vec::new(),
         }
     }
 }
@@ -336,6 +678,44 @@ impl Config {
         self.sample_rate = sr;
         self
     }
+
+    /// Set the audio device sample rate.
+    pub fn with_device_sample_rate(mut self, sr: u32) -> Self {
+        self.device_sample_rate = Some(sr);
+        self
+    }
+
+    /// Returns the audio device sample rate, which is the context sample rate unless set
+    /// otherwise.
+    pub fn device_sample_rate(&self) -> u32 {
+        self.device_sample_rate.unwrap_or(self.sample_rate)
+    }
+
+    /// Check that ZenGarden can run a context with the config: the block size is a power of two,
+    /// there's at least one channel and the sample rates aren't zero.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !self.blocksize.is_power_of_two() {
+            return Err(ConfigError::BlockSize(self.blocksize));
+        }
+        if self.input_ch_num == 0 && self.output_ch_num == 0 {
+            return Err(ConfigError::NoChannels);
+        }
+        if self.sample_rate == 0 || self.device_sample_rate == Some(0) {
+            return Err(ConfigError::SampleRate);
+        }
+
+        Ok(())
+    }
+
+    fn block_duration(&self) -> Duration {
+        Duration::from_secs_f64(f64::from(self.blocksize) / f64::from(self.sample_rate))
+    }
+
+    /// Append an abstraction search path.
+    pub fn with_search_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.search_paths.push(path.into());
+        self
+    }
 }
 
 /// [Context] errors.
@@ -344,16 +724,43 @@ pub enum Error {
     /// Error initializing.
     #[error("Can't initalize context")]
     Initializing,
+    /// A graph can't be reloaded into the reconfigured context.
+    #[error("Can't reload the graphs into the reconfigured context")]
+    Reloading,
+    /// A graph created with [Graph::from_raw] is attached, so the context can't be reconfigured.
+    #[error("Can't reconfigure the context while an untracked graph is attached")]
+    UntrackedGraph,
+    /// The config is invalid.
+    #[error(transparent)]
+    InvalidConfig(#[from] ConfigError),
+}
+
+/// [Config::validate] errors.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ConfigError {
+    /// The block size isn't a power of two.
+    #[error("The block size should be a power of two, but it's {0}")]
+    BlockSize(u16),
+    /// There are neither input nor output channels.
+    #[error("There should be at least one input or output channel")]
+    NoChannels,
+    /// A sample rate is zero.
+    #[error("The sample rate can't be zero")]
+    SampleRate,
 }
 
 #[cfg(test)]
 mod tests {
 
     use std::fs;
+    use std::sync::atomic::{AtomicBool, Ordering};
 
-    use zengarden_raw::{zg_context_new_graph_from_file, zg_graph_attach};
+    use zengarden_raw::{
+        zg_context_new_empty_graph, zg_context_new_graph_from_file, zg_graph_attach,
+    };
 
     use crate::message::MessageElement;
+    use crate::object::{InletType, OutletType};
 
     use super::*;
 
@@ -364,7 +771,38 @@ mod tests {
         assert_eq!(config.input_ch_num, 2);
         assert_eq!(config.output_ch_num, 2);
         assert_eq!(config.sample_rate, 44100);
+        assert_eq!(config.device_sample_rate(), 44100);
         assert_eq!(config.blocksize, 64);
+        assert!(config.search_paths.is_empty());
+    }
+
+    #[test]
+    fn config_validation() {
+        assert_eq!(Config::default().validate(), Ok(()));
+        assert_eq!(
+            Config::default().with_block_size(0).validate(),
+            Err(ConfigError::BlockSize(0))
+        );
+        assert_eq!(
+            Config::default().with_block_size(48).validate(),
+            Err(ConfigError::BlockSize(48))
+        );
+        assert_eq!(
+            Config::default()
+                .with_in_ch_num(0)
+                .with_out_ch_num(0)
+                .validate(),
+            Err(ConfigError::NoChannels)
+        );
+        assert_eq!(Config::default().with_in_ch_num(0).validate(), Ok(()));
+        assert_eq!(
+            Config::default().with_device_sample_rate(0).validate(),
+            Err(ConfigError::SampleRate)
+        );
+        assert!(matches!(
+            Context::<DummyDispatcher, AudioLoopF32>::new(Config::default().with_block_size(100)),
+            Err(Error::InvalidConfig(ConfigError::BlockSize(100)))
+        ));
     }
 
     #[test]
@@ -382,13 +820,13 @@ mod tests {
     fn context_next_frame_f32() {
         let context = init_test_context::<DummyDispatcher, AudioLoopF32>("loop_with_input.pd");
 
-        let input = 0..context.config.blocksize * context.config.input_ch_num * 2;
+        let input = 0..context.config().blocksize * context.config().input_ch_num * 2;
 
         let expected: Vec<f32> = input
             .clone()
             .enumerate()
             .map(|(n, val)| {
-                let mul = [2_f32, 3.0][n % context.config.input_ch_num as usize];
+                let mul = [2_f32, 3.0][n % context.config().input_ch_num as usize];
                 val as f32 * mul
             })
             .collect();
@@ -396,13 +834,14 @@ mod tests {
         let result: Vec<f32> = input
             .map(|n| n as f32)
             .collect::<Vec<f32>>()
-            .chunks(context.config.input_ch_num as usize)
+            .chunks(context.config().input_ch_num as usize)
             .map(|val| context.next_frame(val).unwrap().to_owned())
             .flatten()
             .collect();
 
         // there's a one block delay, so we compare slices of a single block only
-        let actual_blocksize = (context.config.blocksize * context.config.input_ch_num) as usize;
+        let actual_blocksize =
+            (context.config().blocksize * context.config().input_ch_num) as usize;
         assert_eq!(expected[..actual_blocksize], result[actual_blocksize..]);
     }
 
@@ -410,29 +849,104 @@ mod tests {
     fn context_next_frame_i16() {
         let context = init_test_context::<DummyDispatcher, AudioLoopI16>("loop_with_input.pd");
 
-        let input = 0_..(context.config.blocksize * context.config.input_ch_num * 2) as i16;
+        let input = 0_..(context.config().blocksize * context.config().input_ch_num * 2) as i16;
 
         let expected: Vec<i16> = input
             .clone()
             .enumerate()
             .map(|(n, val)| {
-                let mul = [2_i16, 3][n % context.config.input_ch_num as usize];
+                let mul = [2_i16, 3][n % context.config().input_ch_num as usize];
                 val * mul
             })
             .collect();
 
         let result: Vec<i16> = input
             .collect::<Vec<i16>>()
-            .chunks(context.config.input_ch_num as usize)
+            .chunks(context.config().input_ch_num as usize)
             .map(|val| context.next_frame(val).unwrap().to_owned())
             .flatten()
             .collect();
 
         // there's a one block delay, so we compare slices of a single block only
-        let actual_blocksize = (context.config.blocksize * context.config.input_ch_num) as usize;
+        let actual_blocksize =
+            (context.config().blocksize * context.config().input_ch_num) as usize;
         assert_eq!(expected[..actual_blocksize], result[actual_blocksize..]);
     }
 
+    #[test]
+    fn context_next_frame_i24() {
+        let context = init_test_context::<DummyDispatcher, AudioLoopI24>("loop_with_input.pd");
+        let frames = context.config().blocksize as i32 * 2;
+
+        let result: Vec<(I24, I24)> = (0..frames)
+            .map(|n| {
+                let frame = context
+                    .next_frame(&[I24(n * 1000), I24(-n * 1000)])
+                    .unwrap();
+                (frame[0], frame[1])
+            })
+            .collect();
+
+        // there's a one block delay, and the output is dithered by one step
+        for (n, (left, right)) in result[context.config().blocksize as usize..]
+            .iter()
+            .enumerate()
+        {
+            let n = n as i32;
+            assert!((left.0 - n * 2000).abs() <= 1);
+            assert!((right.0 + n * 3000).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn context_process_planar() {
+        let context = init_test_context::<DummyDispatcher, AudioLoopF32>("loop_with_input.pd");
+        let blocksize = context.config().blocksize as usize;
+
+        let input: Vec<f32> = (0..blocksize * 2).map(|n| n as f32).collect();
+        let expected: Vec<f32> = input
+            .iter()
+            .enumerate()
+            .map(|(n, val)| val * [2.0, 3.0][n / blocksize])
+            .collect();
+
+        // a single planar block is processed right away
+        let mut output = vec![0.0; blocksize * 2];
+        context
+            .process(Layout::Planar, &input, &mut output)
+            .unwrap();
+        assert_eq!(output, expected);
+
+        let mut output = vec![0.0; blocksize];
+        assert!(matches!(
+            context.process(Layout::Planar, &input, &mut output),
+            Err(AudioLoopError::WrongBufferSize)
+        ));
+    }
+
+    #[test]
+    fn context_process_interleaved() {
+        let context = init_test_context::<DummyDispatcher, AudioLoopF32>("loop_with_input.pd");
+        let blocksize = context.config().blocksize as usize;
+
+        let input: Vec<f32> = (0..blocksize * 2).map(|n| n as f32).collect();
+        let expected: Vec<f32> = input
+            .iter()
+            .enumerate()
+            .map(|(n, val)| val * [2.0, 3.0][n % 2])
+            .collect();
+
+        // interleaved frames go through the audio loop, with a block of delay
+        let mut output = vec![0.0; blocksize * 2];
+        context
+            .process(Layout::Interleaved, &input, &mut output)
+            .unwrap();
+        context
+            .process(Layout::Interleaved, &input, &mut output)
+            .unwrap();
+        assert_eq!(output, expected);
+    }
+
     #[test]
     fn context_send_message() {
         let context = init_test_context::<TestDispatcher, AudioLoopF32>("send_message.pd");
@@ -444,13 +958,247 @@ mod tests {
         context.send_message("test-send-message-r", message);
 
         // as all messages are scheduled in ZenGarden, we should process an audio block
-        for _ in 0..context.config.blocksize + 1 {
+        for _ in 0..context.config().blocksize + 1 {
             context.next_frame(&[0.0, 0.0]).unwrap();
         }
 
         assert_eq!(context.user_data_mut().0, format!("{}.{}", receiver, "baz"));
     }
 
+    #[test]
+    fn object_classes() {
+        let context = Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
+        context.register_external("test-external", null_factory);
+        let classes = context.object_classes();
+        let class = |name: &str| classes.iter().find(|class| class.name == name).unwrap();
+
+        let osc = class("osc~");
+        assert_eq!(osc.inlets, vec![InletType::Dsp, InletType::Dsp]);
+        assert_eq!(osc.outlets, vec![OutletType::Dsp]);
+        assert_eq!(osc.arguments, Some("[frequency]"));
+        assert!(!osc.external);
+
+        let float = class("f");
+        assert_eq!(float.inlets, vec![InletType::Message, InletType::Message]);
+        assert_eq!(float.outlets, vec![OutletType::Message]);
+
+        let delwrite = class("delwrite~");
+        assert_eq!(delwrite.inlets, vec![InletType::Dsp]);
+        assert!(delwrite.outlets.is_empty());
+
+        let external = class("test-external");
+        assert!(external.external);
+        assert_eq!(external.arguments, None);
+        assert!(external.inlets.is_empty());
+
+        assert!(classes.iter().all(|class| class.name != "pd"));
+        assert!(classes.windows(2).all(|w| w[0].name < w[1].name));
+    }
+
+    #[test]
+    fn swap_graphs() {
+        let context = Context::<DummyDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
+        let patch = |value| {
+            format!(
+                "#N canvas 0 0 450 300 12;\n#X obj 10 10 sig~ {};\n#X obj 10 40 dac~;\n\
+                 #X connect 0 0 1 0;\n",
+                value
+            )
+        };
+        let old = Graph::from_str(context.clone(), &patch(1));
+        let new = Graph::from_str(context.clone(), &patch(2));
+        old.attach();
+
+        // the audio loop runs on its own thread while the graphs are swapped
+        let running = Arc::new(AtomicBool::new(true));
+        let audio = {
+            let running = running.clone();
+            let context = AudioThread(context.clone());
+            thread::spawn(move || {
+                let context = context;
+                let mut output = Vec::new();
+                while running.load(Ordering::Relaxed) {
+                    output.push(context.0.next_frame(&[0.0, 0.0]).unwrap()[0]);
+                }
+                output
+            })
+        };
+
+        context.swap_graphs(&old, &new, 50.0);
+        running.store(false, Ordering::Relaxed);
+        let output = audio.join().unwrap();
+
+        assert!(!old.is_attached());
+        assert!(new.is_attached());
+        assert!(output.contains(&1.0));
+        // both graphs are heard during the crossfade, at most at the equal-power peak
+        assert!(output.iter().any(|&sample| sample > 2.01));
+        assert!(output.iter().all(|&sample| sample <= 5_f32.sqrt() + 1e-4));
+
+        let mut last = 0.0;
+        for _ in 0..context.config().blocksize * 2 {
+            last = context.next_frame(&[0.0, 0.0]).unwrap()[0];
+        }
+        assert_eq!(last, 2.0);
+    }
+
+    #[test]
+    fn swap_graphs_without_audio_loop() {
+        let context = Context::<DummyDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
+        let patch = |value| {
+            format!(
+                "#N canvas 0 0 450 300 12;\n#X obj 10 10 sig~ {};\n#X obj 10 40 dac~;\n\
+                 #X connect 0 0 1 0;\n",
+                value
+            )
+        };
+        let old = Graph::from_str(context.clone(), &patch(1));
+        let new = Graph::from_str(context.clone(), &patch(2));
+        let other = Graph::from_str(context.clone(), &patch(3));
+
+        // a detached graph is replaced at once
+        context.swap_graphs(&old, &new, 50.0);
+        assert!(!old.is_attached());
+        assert!(new.is_attached());
+
+        // nothing processes the crossfade, so this gives up after the deadline
+        context.swap_graphs(&new, &other, 50.0);
+        assert!(!new.is_attached());
+        assert!(other.is_attached());
+
+        let mut last = 0.0;
+        for _ in 0..context.config().blocksize * 2 {
+            last = context.next_frame(&[0.0, 0.0]).unwrap()[0];
+        }
+        assert_eq!(last, 3.0);
+    }
+
+    #[test]
+    fn reconfigure() {
+        let context = Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
+        let graph = Graph::from_str(
+            context.clone(),
+            "#N canvas 0 0 450 300 12;\n#X obj 10 10 sig~ 1;\n#X obj 10 40 dac~;\n\
+             #X obj 10 70 r reconfigure-r;\n#X obj 10 100 s reconfigure-s;\n\
+             #X obj 10 130 table reconfigure-t 4;\n#X connect 0 0 1 0;\n#X connect 2 0 3 0;\n",
+        );
+        graph.attach();
+        context.register_receiver("reconfigure-s");
+        let table = context.table("reconfigure-t").unwrap();
+        table.set_buffer(&[1.0, 2.0, 3.0, 4.0, 5.0]).unwrap();
+        let dac = graph.objects()[1];
+        let config = Config::default()
+            .with_block_size(16)
+            .with_sample_rate(48000);
+
+        // the old context would delete the attached graph, which isn't reloaded
+        let untracked = Graph::from_raw(unsafe {
+            zg_context_new_empty_graph(*context.raw_context.read().unwrap())
+        });
+        untracked.attach();
+        assert!(matches!(
+            context.reconfigure(config.clone()),
+            Err(Error::UntrackedGraph)
+        ));
+        assert_eq!(context.config().blocksize, 64);
+        untracked.detach();
+
+        context.reconfigure(config).unwrap();
+        // the objects taken before refer to the old graph, which is kept
+        assert_eq!(dac.creation_text(), "dac~");
+
+        assert_eq!(context.config().blocksize, 16);
+        assert_eq!(context.config().sample_rate, 48000);
+        assert!(graph.is_attached());
+        assert_eq!(graph.objects().len(), 5);
+        assert_eq!(
+            context.table("reconfigure-t").unwrap().to_vec(),
+            vec![1.0, 2.0, 3.0, 4.0, 5.0]
+        );
+
+        let message = Message::builder()
+            .with_element(MessageElement::Symbol("baz".to_string()))
+            .build();
+        context.send_message("reconfigure-r", message);
+        let mut last = 0.0;
+        for _ in 0..17 {
+            last = context.next_frame(&[0.0, 0.0]).unwrap()[0];
+        }
+        assert_eq!(last, 1.0);
+        assert_eq!(context.user_data().0, "reconfigure-s.baz");
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        let context = Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
+        let graph = Graph::from_str(
+            context.clone(),
+            "#N canvas 0 0 450 300 12;\n#X obj 10 10 float 1;\n#X obj 10 40 tgl;\n\
+             #X obj 10 70 value snapshot-v;\n#X msg 10 100 foo;\n\
+             #X obj 10 130 table snapshot-t 2;\n#N canvas 0 0 450 300 sub 0;\n\
+             #X obj 10 10 float 2;\n#X restore 10 160 pd sub;\n",
+        );
+        let objects = graph.objects();
+        let (float, toggle, value, message) = (objects[0], objects[1], objects[2], objects[3]);
+        // the graph isn't attached, so the table isn't registered with the context
+        let table = graph.tables().remove(0);
+        assert_eq!(table.name(), "snapshot-t");
+        assert_eq!(value.value_name().as_deref(), Some("snapshot-v"));
+        assert!(float.value_name().is_none());
+
+        assert_eq!(float.state().as_deref(), Some("1"));
+        assert_eq!(toggle.state().as_deref(), Some("0"));
+        assert!(objects[4].state().is_none());
+        float.set_state("0.25");
+        toggle.set_state("3");
+        value.set_state("-4");
+        message.set_state("bar \\, 2 \\; snapshot-r 3");
+        assert_eq!(
+            message.state().as_deref(),
+            Some("bar \\, 2 \\; snapshot-r 3")
+        );
+        table.set_buffer(&[1.0, 2.0]).unwrap();
+
+        let snapshot = context.snapshot();
+        assert_eq!(snapshot.values.get("snapshot-v"), Some(&-4.0));
+        assert_eq!(snapshot.tables.get("snapshot-t"), Some(&vec![1.0, 2.0]));
+        assert_eq!(snapshot.graphs.len(), 1);
+        assert_eq!(snapshot.graphs[0].len(), 4);
+        assert_eq!(
+            snapshot.graphs[0][3],
+            ObjectState {
+                path: vec![5, 0],
+                class: "float".to_string(),
+                state: "2".to_string(),
+            }
+        );
+
+        float.set_state("0");
+        toggle.set_state("0");
+        value.set_state("0");
+        message.set_state("baz");
+        table.set_buffer(&[0.0, 0.0]).unwrap();
+        let json = serde_json::to_string(&snapshot).unwrap();
+        context.restore(&serde_json::from_str(&json).unwrap());
+
+        assert_eq!(float.state().as_deref(), Some("0.25"));
+        assert_eq!(toggle.state().as_deref(), Some("3"));
+        assert_eq!(value.state().as_deref(), Some("-4"));
+        assert_eq!(
+            message.state().as_deref(),
+            Some("bar \\, 2 \\; snapshot-r 3")
+        );
+        assert_eq!(table.to_vec(), vec![1.0, 2.0]);
+    }
+
+    struct AudioThread(Context<DummyDispatcher, AudioLoopF32>);
+
+    unsafe impl Send for AudioThread {}
+
+    unsafe extern "C" fn null_factory(_: *mut ZGMessage, _: *mut ZGGraph) -> *mut ZGObject {
+        ptr::null_mut()
+    }
+
     fn init_test_context<D: Dispatcher, L: AudioLoop>(file: &str) -> Context<D, L> {
         let context = Context::<D, L>::new(Config::default()).unwrap();
         let patch_dir_path = fs::canonicalize("./test/").unwrap();
@@ -499,6 +1247,8 @@ mod tests {
 
         assert!(result.is_null());
         assert_eq!(expected, context.user_data().0);
+
+        let _ = CString::from_raw(msg as *mut c_char);
     }
 
     unsafe fn test_print_err(
@@ -514,6 +1264,8 @@ mod tests {
         );
         assert!(result.is_null());
         assert_eq!(expected, context.user_data().0);
+
+        let _ = CString::from_raw(msg as *mut c_char);
     }
 
     unsafe fn test_switch_dsp(
@@ -570,6 +1322,8 @@ mod tests {
 
         assert_eq!(result_str.to_string_lossy(), expected.clone());
         assert_eq!(expected, context.user_data().0);
+
+        let _ = CString::from_raw(name as *mut c_char);
     }
 
     #[derive(Debug, Clone)]
@@ -1,25 +1,74 @@
 use std::fmt;
+use std::time::Duration;
 
 use thiserror::Error;
 use zengarden_raw::{zg_context_process, zg_context_process_s, PdContext};
 
+use super::sample::{Dither, I24Packed, Sample, I24};
+
 /// Audio loop.
 pub trait AudioLoop: fmt::Debug + Default + Clone {
     /// Audio buffer sample type.
-    type SampleType: Copy;
+    type SampleType: Copy + fmt::Debug;
 
     /// Initialize buffers. May behave as re-initializer.
     fn init_buffers(&mut self, blocksize: u16, in_ch_num: u16, out_ch_num: u16);
 
+    /// Set the sample rates of the context and of the audio device. Called after
+    /// [AudioLoop::init_buffers]. Loops which don't convert the sample rate ignore it.
+    fn init_sample_rates(&mut self, _context_rate: u32, _device_rate: u32) {}
+
+    /// Returns the latency the loop adds on top of the block of latency of the context.
+    fn latency(&self) -> Duration {
+        Duration::default()
+    }
+
     /// Returns next frame of [`Self::SampleType`].
     fn next_frame(
         &mut self,
         raw_context: *mut PdContext,
         in_frame: &[Self::SampleType],
     ) -> Result<&[Self::SampleType], Error>;
+
+    /// Process a single block passing the buffers to ZenGarden directly, without the block of
+    /// latency of [AudioLoop::next_frame]. Returns `false` if the loop can't do it with the layout
+    /// or the buffers aren't exactly one block long, in which case the caller falls back to
+    /// [AudioLoop::next_frame].
+    fn process_block(
+        &mut self,
+        _raw_context: *mut PdContext,
+        _layout: Layout,
+        _input: &[Self::SampleType],
+        _output: &mut [Self::SampleType],
+    ) -> bool {
+        false
+    }
+}
+
+/// Layout of the samples of a multichannel buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Frames one after another, e.g. `L R L R L R`.
+    Interleaved,
+    /// Channels one after another, e.g. `L L L R R R`.
+    Planar,
+}
+
+impl Layout {
+    /// Returns the index of the sample of the channel in the frame of a buffer of `frames` frames
+    /// with `channels` channels.
+    pub fn index(self, frame: usize, channel: usize, frames: usize, channels: usize) -> usize {
+        match self {
+            Layout::Interleaved => frame * channels + channel,
+            Layout::Planar => channel * frames + frame,
+        }
+    }
 }
 
 /// [AudioLoop] implementation for 32-bit float sampled buffer.
+///
+/// ZenGarden processes planar blocks of floats, so [AudioLoop::process_block] passes
+/// [Layout::Planar] buffers through without copying.
 #[derive(Debug, Default, Clone)]
 pub struct AudioLoopF32 {
     frame_offset: usize,
@@ -41,6 +90,11 @@ impl AudioLoopF32 {
         }
     }
 
+    fn process_slices(raw_context: *mut PdContext, input: &[f32], output: &mut [f32]) {
+        // ZenGarden only copies from the input buffer
+        unsafe { zg_context_process(raw_context, input.as_ptr() as *mut _, output.as_mut_ptr()) }
+    }
+
     fn update_input(&mut self, in_frame: &[f32]) {
         for n in 0..in_frame.len() {
             let pos = n * self.blocksize + self.frame_offset;
@@ -88,9 +142,30 @@ impl AudioLoop for AudioLoopF32 {
 
         Ok(&self.out_frame)
     }
+
+    fn process_block(
+        &mut self,
+        raw_context: *mut PdContext,
+        layout: Layout,
+        input: &[Self::SampleType],
+        output: &mut [Self::SampleType],
+    ) -> bool {
+        if layout != Layout::Planar
+            || input.len() != self.in_buf.len()
+            || output.len() != self.out_buf.len()
+        {
+            return false;
+        }
+
+        Self::process_slices(raw_context, input, output);
+        true
+    }
 }
 
 /// [AudioLoop] implementation for 16-bit integer sampled buffer.
+///
+/// ZenGarden converts interleaved blocks of shorts, so [AudioLoop::process_block] passes
+/// [Layout::Interleaved] buffers through without copying.
 #[derive(Debug, Default, Clone)]
 pub struct AudioLoopI16 {
     frame_offset: usize,
@@ -112,6 +187,11 @@ impl AudioLoopI16 {
         }
     }
 
+    fn process_slices(raw_context: *mut PdContext, input: &[i16], output: &mut [i16]) {
+        // ZenGarden only reads from the input buffer
+        unsafe { zg_context_process_s(raw_context, input.as_ptr() as *mut _, output.as_mut_ptr()) }
+    }
+
     fn update_input(&mut self, in_frame: &[i16]) {
         let start = self.frame_offset * in_frame.len();
         let end = start + in_frame.len();
@@ -157,6 +237,84 @@ impl AudioLoop for AudioLoopI16 {
 
         Ok(&self.out_frame)
     }
+
+    fn process_block(
+        &mut self,
+        raw_context: *mut PdContext,
+        layout: Layout,
+        input: &[Self::SampleType],
+        output: &mut [Self::SampleType],
+    ) -> bool {
+        if layout != Layout::Interleaved
+            || input.len() != self.in_buf.len()
+            || output.len() != self.out_buf.len()
+        {
+            return false;
+        }
+
+        Self::process_slices(raw_context, input, output);
+        true
+    }
+}
+
+/// [AudioLoop] for sample formats other than 32-bit float. Frames are converted to and from
+/// [Sample] around an [AudioLoop] of 32-bit floats, [AudioLoopF32] by default.
+#[derive(Debug, Default, Clone)]
+pub struct SampleLoop<S: Sample, L: AudioLoop = AudioLoopF32> {
+    inner: L,
+    dither: Dither,
+    in_frame: Vec<f32>,
+    out_frame: Vec<S>,
+}
+
+/// [AudioLoop] for 32-bit integer samples.
+pub type AudioLoopI32 = SampleLoop<i32>;
+/// [AudioLoop] for 24-bit samples in 32-bit integers.
+pub type AudioLoopI24 = SampleLoop<I24>;
+/// [AudioLoop] for packed 3-byte 24-bit samples.
+pub type AudioLoopI24Packed = SampleLoop<I24Packed>;
+/// [AudioLoop] for unsigned 8-bit samples.
+pub type AudioLoopU8 = SampleLoop<u8>;
+/// [AudioLoop] for 64-bit float samples.
+pub type AudioLoopF64 = SampleLoop<f64>;
+
+impl<S: Sample, L: AudioLoop<SampleType = f32>> AudioLoop for SampleLoop<S, L> {
+    type SampleType = S;
+
+    fn init_buffers(&mut self, blocksize: u16, in_ch_num: u16, out_ch_num: u16) {
+        self.inner.init_buffers(blocksize, in_ch_num, out_ch_num);
+        self.in_frame = vec![0.0; in_ch_num as usize];
+        self.out_frame = vec![S::default(); out_ch_num as usize];
+    }
+
+    fn init_sample_rates(&mut self, context_rate: u32, device_rate: u32) {
+        self.inner.init_sample_rates(context_rate, device_rate);
+    }
+
+    fn latency(&self) -> Duration {
+        self.inner.latency()
+    }
+
+    fn next_frame(
+        &mut self,
+        raw_context: *mut PdContext,
+        in_frame: &[Self::SampleType],
+    ) -> Result<&[Self::SampleType], Error> {
+        if in_frame.len() != self.in_frame.len() {
+            return Err(Error::WrongInFrameSize);
+        }
+
+        for (sample, input) in self.in_frame.iter_mut().zip(in_frame) {
+            *sample = input.to_f32();
+        }
+
+        let frame = self.inner.next_frame(raw_context, &self.in_frame)?;
+        for (sample, output) in self.out_frame.iter_mut().zip(frame) {
+            *sample = S::from_f32(*output, &mut self.dither);
+        }
+
+        Ok(&self.out_frame)
+    }
 }
 
 /// Audio loop error.
@@ -165,4 +323,7 @@ pub enum Error {
     /// The size of the input frame isn't equal to the number of the input channels.
     #[error("Wrong input frame size. The size should be equal to the number of input channels.")]
     WrongInFrameSize,
+    /// The buffer sizes don't match the numbers of channels or each other.
+    #[error("Wrong buffer size. The buffers should hold the same number of frames.")]
+    WrongBufferSize,
 }
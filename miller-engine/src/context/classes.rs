@@ -0,0 +1,117 @@
+use crate::graph::Graph;
+use crate::object::{InletType, ObjectPosition, OutletType};
+
+/// Object class which a [Context](super::Context) can instantiate, returned by
+/// [Context::object_classes](super::Context::object_classes).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectClass {
+    /// Class name, i.e. the first token of the object text, e.g. **"osc~"**.
+    pub name: String,
+    /// Inlet types. Some classes change the number of inlets with their arguments (e.g.
+    /// **"pack"**), in which case these are the inlets of an instance created without them.
+    pub inlets: Vec<InletType>,
+    /// Outlet types, with the same caveat as [ObjectClass::inlets].
+    pub outlets: Vec<OutletType>,
+    /// Short description of the creation arguments in Pd's notation, e.g. **"[frequency]"**.
+    /// Optional arguments are in square brackets. `None` for externals.
+    pub arguments: Option<&'static str>,
+    /// Whether the class is a registered external rather than a built-in one.
+    pub external: bool,
+}
+
+impl ObjectClass {
+    /// Instantiate the class in the scratch graph to find out its inlets and outlets. Classes
+    /// which can't be instantiated are reported without any.
+    pub(super) fn probe(graph: &Graph<'_>, name: String, external: bool) -> Self {
+        let (inlets, outlets) =
+            match graph.new_object(&probe_text(&name), ObjectPosition::from((0.0, 0.0))) {
+                Some(object) => {
+                    let inlets = (0..object.num_inlets())
+                        .map(|i| object.inlet_type(i))
+                        .collect();
+                    let outlets = (0..object.num_outlets())
+                        .map(|i| object.outlet_type(i))
+                        .collect();
+                    object.remove();
+                    (inlets, outlets)
+                }
+                None => Default::default(),
+            };
+
+        Self {
+            arguments: if external { None } else { arguments(&name) },
+            name,
+            inlets,
+            outlets,
+            external,
+        }
+    }
+}
+
+/// Object text used to probe the class. Classes which refer to a named buffer, table or
+/// send/receive pair complain when they are created without a name.
+fn probe_text(name: &str) -> String {
+    match name {
+        "catch~" | "r~" | "receive~" | "s~" | "send~" | "table" | "throw~" | "v" | "value"
+        | "vd~" => format!("{} miller-probe", name),
+        "delread~" | "delwrite~" => format!("{} miller-probe 0", name),
+        "msg" => "msg bang".to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// Arguments of the built-in classes.
+fn arguments(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "abs" | "atan" | "bang" | "b" | "bng" | "cos" | "cputime" | "dbtopow" | "dbtorms"
+        | "exp" | "ftom" | "loadbang" | "mtof" | "notein" | "openpanel" | "powtodb" | "rmstodb"
+        | "samplerate~" | "sin" | "soundfiler" | "sqrt" | "stripnote" | "tan" | "timer"
+        | "until" | "wrap" => "",
+        "+" | "-" | "*" | "/" | "%" | "div" | "mod" | "pow" | "log" | "max" | "min" | "=="
+        | "!=" | ">" | ">=" | "<" | "<=" | "&&" | "||" | "atan2" => "[right operand]",
+        "change" | "float" | "f" | "int" | "i" | "nbx" | "hsl" | "vsl" => "[initial value]",
+        "clip" => "[low] [high]",
+        "declare" => "[-path <dir>] [-stdpath <dir>]",
+        "delay" | "del" | "metro" => "[interval in ms]",
+        "inlet" | "outlet" | "inlet~" | "outlet~" => "",
+        "line" => "[initial value] [grain in ms]",
+        "list" => "[append|prepend|split|trim|length] [atoms...]",
+        "makefilename" => "<format>",
+        "msg" => "<message>",
+        "moses" => "[split point]",
+        "pack" | "unpack" | "trigger" | "t" => "[f|s|b|l|a...]",
+        "pipe" => "[atoms...] [delay in ms]",
+        "poly" => "[voices] [steal]",
+        "print" | "print~" => "[name]",
+        "random" => "[range]",
+        "receive" | "r" | "send" | "s" => "[name]",
+        "route" | "select" | "sel" => "[atoms...]",
+        "spigot" => "[open]",
+        "swap" => "[right value]",
+        "switch~" => "[block size] [overlap]",
+        "symbol" => "[initial symbol]",
+        "table" => "<name> [size]",
+        "tabread" | "tabwrite" | "tabplay~" | "tabread~" | "tabread4~" | "tabwrite~" => {
+            "[table name]"
+        }
+        "text" => "[comment]",
+        "toggle" | "tgl" => "[nonzero value]",
+        "value" | "v" => "<name>",
+        "adc~" | "dac~" => "[channels...]",
+        "+~" | "-~" | "*~" | "/~" | "min~" => "[right operand]",
+        "bp~" => "[center frequency] [q]",
+        "bang~" | "cos~" | "log~" | "noise~" | "rsqrt~" | "q8_rsqrt~" | "sqrt~" | "q8_sqrt~"
+        | "rfft~" | "rifft~" | "samphold~" | "snapshot~" | "wrap~" | "vline~" => "",
+        "catch~" | "throw~" | "receive~" | "r~" | "send~" | "s~" => "<name>",
+        "clip~" => "[low] [high]",
+        "delread~" => "<delay name> [delay in ms]",
+        "delwrite~" => "<delay name> <buffer length in ms>",
+        "env~" => "[window size] [period]",
+        "hip~" | "lop~" => "[cutoff frequency]",
+        "line~" => "[initial value]",
+        "osc~" | "phasor~" => "[frequency]",
+        "sig~" => "[initial value]",
+        "vd~" => "<delay name>",
+        _ => return None,
+    })
+}
@@ -0,0 +1,400 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use super::{AudioLoop, AudioLoopError, Context, Dispatcher};
+
+/// Runs several independent [Context]s in one audio stream and mixes their outputs into the
+/// device channels.
+///
+/// Each context is driven frame by frame with [Context::next_frame] and processes a block whenever
+/// its own buffer runs out, so the contexts may have different block sizes and numbers of
+/// channels. They must have the same device sample rate though (see [super::ResamplingLoop]).
+/// The device input is fed to the inputs with the same index of every context, and every output
+/// of a context is routed to a device channel (by default the one with the same index) with the
+/// context gain applied.
+///
+/// A muted context isn't heard. If any context is soloed, only the soloed ones are heard. Contexts
+/// which aren't heard are processed anyway, so they stay in time with the rest. The gain, mute and
+/// solo of a context can be changed while the mixer runs in the audio callback with its
+/// [MixerControl].
+///
+/// The output of a context is delayed by its block size, and the mixer doesn't compensate for
+/// that: contexts with different block sizes are out of alignment by the difference of their
+/// block sizes.
+#[derive(Debug)]
+pub struct ContextMixer<D: Dispatcher, L: AudioLoop> {
+    in_ch_num: usize,
+    out_frame: Vec<f32>,
+    strips: Vec<Strip<D, L>>,
+}
+
+#[derive(Debug)]
+struct Strip<D: Dispatcher, L: AudioLoop> {
+    context: Context<D, L>,
+    in_frame: Vec<f32>,
+    // device channel of each context output
+    routes: Vec<Option<usize>>,
+    control: MixerControl,
+}
+
+/// Controls the gain, mute and solo of a context in a [ContextMixer]. It's a cheap handle, which
+/// can be cloned and sent to another thread, so the mix can be changed while the mixer is owned by
+/// the audio callback.
+#[derive(Debug, Clone)]
+pub struct MixerControl(Arc<Controls>);
+
+#[derive(Debug)]
+struct Controls {
+    // f32 bits
+    gain: AtomicU32,
+    muted: AtomicBool,
+    soloed: AtomicBool,
+}
+
+impl MixerControl {
+    fn new() -> Self {
+        Self(Arc::new(Controls {
+            gain: AtomicU32::new(1_f32.to_bits()),
+            muted: AtomicBool::new(false),
+            soloed: AtomicBool::new(false),
+        }))
+    }
+
+    /// Get the gain of the context.
+    pub fn gain(&self) -> f32 {
+        f32::from_bits(self.0.gain.load(Ordering::Relaxed))
+    }
+
+    /// Set the gain of the context.
+    pub fn set_gain(&self, gain: f32) {
+        self.0.gain.store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the context is muted.
+    pub fn is_muted(&self) -> bool {
+        self.0.muted.load(Ordering::Relaxed)
+    }
+
+    /// Mute or unmute the context.
+    pub fn set_muted(&self, muted: bool) {
+        self.0.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the context is soloed.
+    pub fn is_soloed(&self) -> bool {
+        self.0.soloed.load(Ordering::Relaxed)
+    }
+
+    /// Solo or unsolo the context.
+    pub fn set_soloed(&self, soloed: bool) {
+        self.0.soloed.store(soloed, Ordering::Relaxed);
+    }
+}
+
+impl<D: Dispatcher, L: AudioLoop<SampleType = f32>> ContextMixer<D, L> {
+    /// Create a mixer for a device with the given numbers of channels.
+    pub fn new(in_ch_num: u16, out_ch_num: u16) -> Self {
+        Self {
+            in_ch_num: in_ch_num as usize,
+            out_frame: vec![0.0; out_ch_num as usize],
+            strips: Vec::new(),
+        }
+    }
+
+    /// Add a context to the mix at unity gain. Returns its index, which is used to address the
+    /// context in the other methods.
+    pub fn add(&mut self, context: Context<D, L>) -> Result<usize, Error> {
+        if let Some(strip) = self.strips.first() {
+            let expected = strip.context.config().device_sample_rate();
+            let found = context.config().device_sample_rate();
+            if found != expected {
+                return Err(Error::SampleRateMismatch { expected, found });
+            }
+        }
+
+        let out_ch_num = self.out_frame.len();
+        let (in_frame, routes) = {
+            let config = context.config();
+            let routes = (0..config.output_ch_num as usize)
+                .map(|ch| if ch < out_ch_num { Some(ch) } else { None })
+                .collect();
+            (vec![0.0; config.input_ch_num as usize], routes)
+        };
+        self.strips.push(Strip {
+            in_frame,
+            routes,
+            control: MixerControl::new(),
+            context,
+        });
+
+        Ok(self.strips.len() - 1)
+    }
+
+    /// Remove the context from the mix. The indices of the contexts added after it are shifted
+    /// down by one.
+    pub fn remove(&mut self, index: usize) -> Result<Context<D, L>, Error> {
+        if index < self.strips.len() {
+            Ok(self.strips.remove(index).context)
+        } else {
+            Err(Error::NoSuchContext(index))
+        }
+    }
+
+    /// Get the context at the index.
+    pub fn context(&self, index: usize) -> Option<&Context<D, L>> {
+        self.strips.get(index).map(|strip| &strip.context)
+    }
+
+    /// Returns the number of contexts in the mix.
+    pub fn len(&self) -> usize {
+        self.strips.len()
+    }
+
+    /// Returns `true` if there are no contexts in the mix.
+    pub fn is_empty(&self) -> bool {
+        self.strips.is_empty()
+    }
+
+    /// Get the control of the context, which stays valid after the mixer is moved to the audio
+    /// thread.
+    pub fn control(&self, index: usize) -> Option<MixerControl> {
+        self.strips.get(index).map(|strip| strip.control.clone())
+    }
+
+    /// Get the gain of the context.
+    pub fn gain(&self, index: usize) -> Option<f32> {
+        self.strips.get(index).map(|strip| strip.control.gain())
+    }
+
+    /// Set the gain of the context.
+    pub fn set_gain(&self, index: usize, gain: f32) -> Result<(), Error> {
+        self.strip(index)?.control.set_gain(gain);
+        Ok(())
+    }
+
+    /// Returns `true` if the context is muted.
+    pub fn is_muted(&self, index: usize) -> Option<bool> {
+        self.strips.get(index).map(|strip| strip.control.is_muted())
+    }
+
+    /// Mute or unmute the context.
+    pub fn set_muted(&self, index: usize, muted: bool) -> Result<(), Error> {
+        self.strip(index)?.control.set_muted(muted);
+        Ok(())
+    }
+
+    /// Returns `true` if the context is soloed.
+    pub fn is_soloed(&self, index: usize) -> Option<bool> {
+        self.strips
+            .get(index)
+            .map(|strip| strip.control.is_soloed())
+    }
+
+    /// Solo or unsolo the context.
+    pub fn set_soloed(&self, index: usize, soloed: bool) -> Result<(), Error> {
+        self.strip(index)?.control.set_soloed(soloed);
+        Ok(())
+    }
+
+    /// Route an output channel of the context to a device channel, or disconnect it with `None`.
+    pub fn route(
+        &mut self,
+        index: usize,
+        context_ch: usize,
+        device_ch: Option<usize>,
+    ) -> Result<(), Error> {
+        if let Some(ch) = device_ch.filter(|&ch| ch >= self.out_frame.len()) {
+            return Err(Error::NoSuchChannel(ch));
+        }
+
+        let route = self
+            .strip_mut(index)?
+            .routes
+            .get_mut(context_ch)
+            .ok_or(Error::NoSuchChannel(context_ch))?;
+        *route = device_ch;
+        Ok(())
+    }
+
+    /// Get the next frame of the mix. The `in_frame` argument is a device input frame, which
+    /// should be equal in size to the number of the device input channels.
+    pub fn next_frame(&mut self, in_frame: &[f32]) -> Result<&[f32], Error> {
+        if in_frame.len() != self.in_ch_num {
+            return Err(AudioLoopError::WrongInFrameSize.into());
+        }
+
+        let solo = self.strips.iter().any(|strip| strip.control.is_soloed());
+        self.out_frame.iter_mut().for_each(|sample| *sample = 0.0);
+
+        for strip in &mut self.strips {
+            let len = strip.in_frame.len().min(in_frame.len());
+            strip.in_frame[..len].copy_from_slice(&in_frame[..len]);
+            let frame = strip.context.next_frame(&strip.in_frame)?;
+
+            if strip.control.is_muted() || (solo && !strip.control.is_soloed()) {
+                continue;
+            }
+
+            let gain = strip.control.gain();
+            for (sample, route) in frame.iter().zip(&strip.routes) {
+                if let Some(ch) = route {
+                    self.out_frame[*ch] += sample * gain;
+                }
+            }
+        }
+
+        Ok(&self.out_frame)
+    }
+
+    fn strip(&self, index: usize) -> Result<&Strip<D, L>, Error> {
+        self.strips.get(index).ok_or(Error::NoSuchContext(index))
+    }
+
+    fn strip_mut(&mut self, index: usize) -> Result<&mut Strip<D, L>, Error> {
+        self.strips
+            .get_mut(index)
+            .ok_or(Error::NoSuchContext(index))
+    }
+}
+
+/// [ContextMixer] errors.
+#[derive(Debug, Clone, Error)]
+pub enum Error {
+    /// There's no context at the index.
+    #[error("There's no context at index {0}")]
+    NoSuchContext(usize),
+    /// The channel doesn't exist.
+    #[error("Channel {0} doesn't exist")]
+    NoSuchChannel(usize),
+    /// The context device sample rate differs from the sample rate of the mix.
+    #[error("The context sample rate is {found}, but the mix runs at {expected}")]
+    SampleRateMismatch {
+        /// Sample rate of the mix.
+        expected: u32,
+        /// Sample rate of the context.
+        found: u32,
+    },
+    /// A context failed to process the frame.
+    #[error(transparent)]
+    AudioLoop(#[from] AudioLoopError),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::{AudioLoopF32, Config};
+    use crate::graph::Graph;
+
+    use super::*;
+
+    fn patch(value: f32) -> String {
+        format!(
+            "#N canvas 0 0 450 300 12;\n#X obj 10 10 sig~ {};\n#X obj 10 40 dac~;\n\
+             #X connect 0 0 1 0;\n",
+            value
+        )
+    }
+
+    #[test]
+    fn mix() {
+        let mut mixer = ContextMixer::<TestDispatcher, AudioLoopF32>::new(2, 2);
+        let first = Context::new(Config::default()).unwrap();
+        let second = Context::new(Config::default().with_block_size(16)).unwrap();
+        let first_graph = Graph::from_str(first.clone(), &patch(1.0));
+        let second_graph = Graph::from_str(second.clone(), &patch(4.0));
+        first_graph.attach();
+        second_graph.attach();
+
+        assert_eq!(mixer.add(first).unwrap(), 0);
+        assert_eq!(mixer.add(second).unwrap(), 1);
+        mixer.set_gain(1, 0.5).unwrap();
+
+        let next = |mixer: &mut ContextMixer<_, _>| {
+            // skip the block of latency of the context with the largest block size
+            for _ in 0..128 {
+                mixer.next_frame(&[0.0, 0.0]).unwrap();
+            }
+            mixer.next_frame(&[0.0, 0.0]).unwrap().to_vec()
+        };
+
+        assert_eq!(next(&mut mixer), vec![3.0, 0.0]);
+
+        mixer.set_muted(0, true).unwrap();
+        assert_eq!(next(&mut mixer), vec![2.0, 0.0]);
+
+        mixer.set_muted(0, false).unwrap();
+        mixer.set_soloed(0, true).unwrap();
+        assert_eq!(next(&mut mixer), vec![1.0, 0.0]);
+
+        mixer.set_soloed(0, false).unwrap();
+        mixer.route(1, 0, Some(1)).unwrap();
+        assert_eq!(next(&mut mixer), vec![1.0, 2.0]);
+
+        assert!(matches!(
+            mixer.route(1, 0, Some(2)),
+            Err(Error::NoSuchChannel(2))
+        ));
+        assert!(matches!(
+            mixer.set_gain(2, 1.0),
+            Err(Error::NoSuchContext(2))
+        ));
+        assert!(matches!(
+            mixer.add(Context::new(Config::default().with_sample_rate(48000)).unwrap()),
+            Err(Error::SampleRateMismatch {
+                expected: 44100,
+                found: 48000
+            })
+        ));
+        assert!(matches!(
+            mixer.next_frame(&[0.0]),
+            Err(Error::AudioLoop(AudioLoopError::WrongInFrameSize))
+        ));
+    }
+
+    #[test]
+    fn control() {
+        let mut mixer = ContextMixer::<TestDispatcher, AudioLoopF32>::new(2, 2);
+        let context = Context::new(Config::default()).unwrap();
+        let graph = Graph::from_str(context.clone(), &patch(1.0));
+        graph.attach();
+        mixer.add(context).unwrap();
+
+        let control = mixer.control(0).unwrap();
+        assert!(mixer.control(1).is_none());
+
+        let next = |mixer: &mut ContextMixer<_, _>| {
+            for _ in 0..64 {
+                mixer.next_frame(&[0.0, 0.0]).unwrap();
+            }
+            mixer.next_frame(&[0.0, 0.0]).unwrap()[0]
+        };
+        // the mix is controlled from another thread than the one that runs the mixer
+        let set = |change: fn(&MixerControl)| {
+            let control = control.clone();
+            std::thread::spawn(move || change(&control)).join().unwrap();
+        };
+
+        set(|control| control.set_gain(0.25));
+        assert_eq!(next(&mut mixer), 0.25);
+        assert_eq!(mixer.gain(0), Some(0.25));
+
+        set(|control| control.set_muted(true));
+        assert_eq!(next(&mut mixer), 0.0);
+        assert_eq!(mixer.is_muted(0), Some(true));
+
+        set(|control| {
+            control.set_muted(false);
+            control.set_soloed(true);
+        });
+        assert_eq!(next(&mut mixer), 0.25);
+        assert_eq!(mixer.is_soloed(0), Some(true));
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestDispatcher;
+
+    impl Dispatcher for TestDispatcher {
+        type UserData = ();
+    }
+}
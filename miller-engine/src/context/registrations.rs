@@ -0,0 +1,159 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::CString;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Arc, Weak};
+
+use zengarden_raw::{
+    zg_context_delete, zg_context_new_empty_graph, zg_context_new_graph_from_string,
+    zg_context_register_external_object, zg_context_register_memorymapped_abstraction,
+    zg_context_register_receiver, zg_graph_attach, zg_graph_delete, zg_graph_is_attached,
+    zg_graph_unattach, PdContext, ZGGraph, ZGMessage, ZGObject,
+};
+
+use crate::graph::Graph;
+
+pub(super) type ExternalFactory =
+    unsafe extern "C" fn(*mut ZGMessage, *mut ZGGraph) -> *mut ZGObject;
+
+/// Everything registered with a context, which [Context::reconfigure](super::Context::reconfigure)
+/// carries over to the new ZenGarden context.
+#[derive(Debug, Default)]
+pub(super) struct Registrations {
+    pub(super) receivers: BTreeSet<String>,
+    pub(super) externals: BTreeMap<String, ExternalFactory>,
+    pub(super) abstractions: BTreeMap<String, CString>,
+    graphs: Vec<GraphSource>,
+    // replaced by a reconfiguration, but kept until the context is dropped, so the objects and
+    // subgraphs taken from them don't dangle
+    retired_graphs: Vec<*mut ZGGraph>,
+    retired_contexts: Vec<*mut PdContext>,
+}
+
+/// A top-level graph and the netlist it was loaded from.
+#[derive(Debug)]
+struct GraphSource {
+    raw: Weak<AtomicPtr<ZGGraph>>,
+    // `None` for graphs created empty
+    netlist: Option<CString>,
+}
+
+impl Registrations {
+    pub(super) fn track_graph(&mut self, raw: &Arc<AtomicPtr<ZGGraph>>, netlist: Option<CString>) {
+        // forget the graphs which have been dropped
+        self.graphs.retain(|source| source.raw.strong_count() > 0);
+        self.graphs.push(GraphSource {
+            raw: Arc::downgrade(raw),
+            netlist,
+        });
+    }
+
+    /// Returns the top-level graphs which are still alive, in the order they were created.
+    pub(super) fn graphs(&mut self) -> Vec<Arc<AtomicPtr<ZGGraph>>> {
+        self.graphs.retain(|source| source.raw.strong_count() > 0);
+        self.graphs
+            .iter()
+            .filter_map(|source| source.raw.upgrade())
+            .collect()
+    }
+
+    /// Returns the number of the tracked graphs which are attached.
+    pub(super) fn attached_graphs(&mut self) -> usize {
+        self.graphs()
+            .iter()
+            .filter(|raw| unsafe { zg_graph_is_attached(raw.load(Ordering::Acquire)) })
+            .count()
+    }
+
+    /// Keep the replaced context until [Registrations::delete_retired].
+    pub(super) fn retire_context(&mut self, context: *mut PdContext) {
+        self.retired_contexts.push(context);
+    }
+
+    /// Delete the graphs and contexts replaced by the reconfigurations.
+    ///
+    /// # Safety
+    ///
+    /// Nothing taken from the retired graphs may be used afterwards.
+    pub(super) unsafe fn delete_retired(&mut self) {
+        self.retired_graphs
+            .drain(..)
+            .for_each(|graph| zg_graph_delete(graph));
+        self.retired_contexts
+            .drain(..)
+            .for_each(|context| zg_context_delete(context));
+    }
+
+    /// Register the receivers, externals and abstractions with the new context and load the
+    /// graphs into it. The old graphs are replaced with the new ones, which are attached if the
+    /// old ones were, and the contents of their tables are copied over. The old graphs are detached
+    /// and retired. Returns `false` if a graph can't be loaded, in which case the old graphs are
+    /// kept.
+    ///
+    /// # Safety
+    ///
+    /// Both contexts must be locked against processing, and `new_context` must be a fresh context.
+    pub(super) unsafe fn move_into(&mut self, new_context: *mut PdContext) -> bool {
+        self.graphs.retain(|source| source.raw.strong_count() > 0);
+
+        for (name, factory) in &self.externals {
+            let name = CString::new(name.as_str()).expect("Can't initialize external name");
+            zg_context_register_external_object(new_context, name.as_ptr(), Some(*factory));
+        }
+        for (name, netlist) in &self.abstractions {
+            let name = CString::new(name.as_str()).expect("Can't initialize abstraction name");
+            zg_context_register_memorymapped_abstraction(
+                new_context,
+                name.as_ptr(),
+                netlist.as_ptr(),
+            );
+        }
+        for receiver in &self.receivers {
+            let receiver = CString::new(receiver.as_str()).expect("Can't initialize receiver name");
+            zg_context_register_receiver(new_context, receiver.as_ptr());
+        }
+
+        let mut loaded = Vec::with_capacity(self.graphs.len());
+        for source in &self.graphs {
+            let new = match &source.netlist {
+                Some(netlist) => zg_context_new_graph_from_string(new_context, netlist.as_ptr()),
+                None => zg_context_new_empty_graph(new_context),
+            };
+            if new.is_null() {
+                loaded.into_iter().for_each(|new| zg_graph_delete(new));
+                return false;
+            }
+            loaded.push(new);
+        }
+
+        for (source, new) in self.graphs.iter().zip(loaded) {
+            let raw = match source.raw.upgrade() {
+                Some(raw) => raw,
+                None => {
+                    zg_graph_delete(new);
+                    continue;
+                }
+            };
+            let old = raw.load(Ordering::Acquire);
+
+            // the tables are valid as long as the graph handles live
+            let (old_graph, new_graph) = (Graph::child(old), Graph::child(new));
+            let old_tables = old_graph.tables();
+            for table in new_graph.tables() {
+                let name = table.name();
+                if let Some(old_table) = old_tables.iter().find(|old| old.name() == name) {
+                    // the table has just been found in the graph
+                    let _ = table.set_buffer(&old_table.to_vec());
+                }
+            }
+
+            if zg_graph_is_attached(old) {
+                zg_graph_unattach(old);
+                zg_graph_attach(new);
+            }
+            self.retired_graphs.push(old);
+            raw.store(new, Ordering::Release);
+        }
+
+        true
+    }
+}
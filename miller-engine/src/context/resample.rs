@@ -0,0 +1,288 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::time::Duration;
+
+use zengarden_raw::PdContext;
+
+use super::audioloop::{AudioLoop, Error};
+
+/// Number of filter phases between two input samples. The coefficients between the phases are
+/// interpolated linearly.
+const PHASES: usize = 256;
+/// Number of sinc zero crossings on each side of the filter when the signal isn't band-limited
+/// further.
+const ZERO_CROSSINGS: usize = 16;
+/// Passband as a fraction of the lower Nyquist frequency, leaving room for the transition band.
+const PASSBAND: f64 = 0.95;
+/// Context input frames queued in advance, which absorb the jitter between the input and the
+/// output conversion.
+const INPUT_PREFILL: usize = 2;
+
+/// [AudioLoop] which runs the context at [Config::sample_rate](super::Config::sample_rate) and
+/// converts its input and output to and from
+/// [Config::device_sample_rate()](super::Config::device_sample_rate()), i.e. the frames passed to
+/// and returned from [Context::next_frame](super::Context::next_frame) are at the device rate.
+///
+/// The conversion is done with a polyphase windowed-sinc resampler, which also works for ratios
+/// like 44.1 to 48 kHz. It adds [AudioLoop::latency] of about 16 samples at the lower rate.
+/// When the rates are the same, frames are passed through unchanged.
+#[derive(Debug, Default, Clone)]
+pub struct ResamplingLoop<L: AudioLoop> {
+    inner: L,
+    context_rate: u32,
+    device_rate: u32,
+    in_ch_num: usize,
+    out_ch_num: usize,
+    // device to context rate
+    input: Resampler,
+    // context to device rate
+    output: Resampler,
+    // interleaved context input frames
+    in_queue: VecDeque<f32>,
+    in_frame: Vec<f32>,
+    out_frame: Vec<f32>,
+}
+
+impl<L: AudioLoop<SampleType = f32>> ResamplingLoop<L> {
+    fn bypassed(&self) -> bool {
+        self.context_rate == self.device_rate || self.context_rate == 0 || self.device_rate == 0
+    }
+
+    fn init_resamplers(&mut self) {
+        if self.bypassed() {
+            return;
+        }
+
+        self.input = Resampler::new(self.device_rate, self.context_rate, self.in_ch_num);
+        self.output = Resampler::new(self.context_rate, self.device_rate, self.out_ch_num);
+        self.in_queue = vec![0.0; self.in_ch_num * INPUT_PREFILL].into();
+        self.in_frame = vec![0.0; self.in_ch_num];
+        self.out_frame = vec![0.0; self.out_ch_num];
+    }
+}
+
+impl<L: AudioLoop<SampleType = f32>> AudioLoop for ResamplingLoop<L> {
+    type SampleType = f32;
+
+    fn init_buffers(&mut self, blocksize: u16, in_ch_num: u16, out_ch_num: u16) {
+        self.inner.init_buffers(blocksize, in_ch_num, out_ch_num);
+        self.in_ch_num = in_ch_num as usize;
+        self.out_ch_num = out_ch_num as usize;
+        self.init_resamplers();
+    }
+
+    fn init_sample_rates(&mut self, context_rate: u32, device_rate: u32) {
+        self.context_rate = context_rate;
+        self.device_rate = device_rate;
+        self.init_resamplers();
+    }
+
+    fn latency(&self) -> Duration {
+        if self.bypassed() {
+            return self.inner.latency();
+        }
+
+        // the output filter delays the signal by half its length at the context rate
+        let delay = self.output.taps / 2;
+        self.inner.latency() + Duration::from_secs_f64(delay as f64 / self.context_rate as f64)
+    }
+
+    fn next_frame(
+        &mut self,
+        raw_context: *mut PdContext,
+        in_frame: &[Self::SampleType],
+    ) -> Result<&[Self::SampleType], Error> {
+        if in_frame.len() != self.in_ch_num {
+            return Err(Error::WrongInFrameSize);
+        }
+
+        if self.bypassed() {
+            return self.inner.next_frame(raw_context, in_frame);
+        }
+
+        self.input.push(in_frame);
+        while self.input.pop(&mut self.in_frame) {
+            self.in_queue.extend(&self.in_frame);
+        }
+
+        while !self.output.pop(&mut self.out_frame) {
+            for sample in &mut self.in_frame {
+                // the queue may run dry for a frame because of rounding
+                *sample = self.in_queue.pop_front().unwrap_or(0.0);
+            }
+            let frame = self.inner.next_frame(raw_context, &self.in_frame)?;
+            self.output.push(frame);
+        }
+
+        Ok(&self.out_frame)
+    }
+}
+
+/// Converts interleaved frames from one sample rate to another.
+#[derive(Debug, Default, Clone)]
+struct Resampler {
+    channels: usize,
+    // filter length, in input frames
+    taps: usize,
+    // input frames per output frame
+    step: f64,
+    // time of the next output frame, in input frames after the center of the history
+    time: f64,
+    // (PHASES + 1) rows of `taps` coefficients
+    filter: Vec<f32>,
+    // the latest `taps` input frames, interleaved
+    history: Vec<f32>,
+}
+
+impl Resampler {
+    fn new(from: u32, to: u32, channels: usize) -> Self {
+        // when downsampling, the cutoff is lowered to the output Nyquist frequency
+        let cutoff = PASSBAND * (to as f64 / from as f64).min(1.0);
+        let taps = 2 * (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+        let center = (taps / 2 - 1) as f64;
+
+        let mut filter = Vec::with_capacity((PHASES + 1) * taps);
+        for phase in 0..=PHASES {
+            let offset = center + phase as f64 / PHASES as f64;
+            let row: Vec<f64> = (0..taps)
+                .map(|tap| {
+                    let x = tap as f64 - offset;
+                    cutoff
+                        * sinc(cutoff * x)
+                        * blackman_harris((x + taps as f64 / 2.0) / taps as f64)
+                })
+                .collect();
+            // normalize each phase to unity gain at DC
+            let sum: f64 = row.iter().sum();
+            filter.extend(row.iter().map(|coef| (coef / sum) as f32));
+        }
+
+        Self {
+            channels,
+            taps,
+            step: from as f64 / to as f64,
+            time: 1.0,
+            filter,
+            history: vec![0.0; taps * channels],
+        }
+    }
+
+    /// Add an input frame.
+    fn push(&mut self, frame: &[f32]) {
+        self.history.drain(..self.channels);
+        self.history.extend_from_slice(&frame[..self.channels]);
+        self.time -= 1.0;
+    }
+
+    /// Write the next output frame. Returns `false` if another input frame is needed first.
+    fn pop(&mut self, frame: &mut [f32]) -> bool {
+        if self.time >= 1.0 {
+            return false;
+        }
+
+        let position = self.time * PHASES as f64;
+        let phase = (position as usize).min(PHASES - 1);
+        let fraction = (position - phase as f64) as f32;
+        let row = &self.filter[phase * self.taps..(phase + 2) * self.taps];
+
+        for (ch, sample) in frame.iter_mut().enumerate().take(self.channels) {
+            *sample = (0..self.taps)
+                .map(|tap| {
+                    let coef = row[tap] + (row[tap + self.taps] - row[tap]) * fraction;
+                    coef * self.history[tap * self.channels + ch]
+                })
+                .sum();
+        }
+
+        self.time += self.step;
+        true
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// 4-term Blackman-Harris window over `[0, 1]`.
+fn blackman_harris(x: f64) -> f64 {
+    if !(0.0..=1.0).contains(&x) {
+        return 0.0;
+    }
+
+    let phase = 2.0 * PI * x;
+    0.35875 - 0.48829 * phase.cos() + 0.14128 * (2.0 * phase).cos() - 0.01168 * (3.0 * phase).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::{AudioLoopF32, Config, Context, Dispatcher};
+    use crate::graph::Graph;
+
+    use super::*;
+
+    #[test]
+    fn resample_sine() {
+        let (from, to) = (44100, 48000);
+        let frequency = 1000.0;
+        let mut resampler = Resampler::new(from, to, 1);
+        let delay = (resampler.taps / 2) as f64;
+
+        let mut input = (0..).map(|n| (2.0 * PI * frequency * n as f64 / from as f64).sin());
+        let mut output = Vec::new();
+        let mut frame = [0.0];
+        while output.len() < 4800 {
+            if resampler.pop(&mut frame) {
+                output.push(frame[0]);
+            } else {
+                resampler.push(&[input.next().unwrap() as f32]);
+            }
+        }
+
+        // compare with the ideal sine once the filter is filled up
+        for (n, sample) in output.iter().enumerate().skip(100) {
+            let time = n as f64 * from as f64 / to as f64 - delay;
+            let expected = (2.0 * PI * frequency * time / from as f64).sin();
+            assert!(
+                (*sample as f64 - expected).abs() < 1e-4,
+                "{}: {}",
+                n,
+                sample
+            );
+        }
+    }
+
+    #[test]
+    fn resampling_loop() {
+        let config = Config::default()
+            .with_sample_rate(44100)
+            .with_device_sample_rate(48000);
+        let context = Context::<TestDispatcher, ResamplingLoop<AudioLoopF32>>::new(config).unwrap();
+        let graph = Graph::from_str(
+            context.clone(),
+            "#N canvas 0 0 450 300 12;\n#X obj 10 10 sig~ 1;\n#X obj 10 40 dac~;\n\
+             #X connect 0 0 1 0;\n",
+        );
+        graph.attach();
+
+        let mut last = 0.0;
+        for _ in 0..4800 {
+            last = context.next_frame(&[0.0, 0.0]).unwrap()[0];
+        }
+        assert!((last - 1.0).abs() < 1e-4);
+
+        let block = Duration::from_secs_f64(64.0 / 44100.0);
+        assert!(context.latency() > block);
+        assert!(context.latency() < block * 2);
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestDispatcher;
+
+    impl Dispatcher for TestDispatcher {
+        type UserData = ();
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt;
+
+/// Sample format which audio loops convert to and from the 32-bit floats ZenGarden processes.
+///
+/// Integer formats are scaled so that the full scale is `[-1, 1)`, and floats outside of it are
+/// clipped. Down-conversion to integer formats with fewer bits than the float mantissa is
+/// dithered. Float formats are neither clipped nor dithered.
+pub trait Sample: Copy + Default + fmt::Debug + Send + 'static {
+    /// Convert a float sample, taking dither noise from `dither` if the format needs it.
+    fn from_f32(sample: f32, dither: &mut Dither) -> Self;
+
+    /// Convert to a float sample.
+    fn to_f32(self) -> f32;
+}
+
+/// 24-bit sample in the lower bits of a 32-bit integer (sign-extended).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct I24(pub i32);
+
+/// Packed 3-byte little-endian 24-bit sample.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct I24Packed(pub [u8; 3]);
+
+impl From<I24> for I24Packed {
+    fn from(sample: I24) -> Self {
+        let [b0, b1, b2, _] = sample.0.to_le_bytes();
+        I24Packed([b0, b1, b2])
+    }
+}
+
+impl From<I24Packed> for I24 {
+    fn from(sample: I24Packed) -> Self {
+        let [b0, b1, b2] = sample.0;
+        let sign = if b2 & 0x80 != 0 { 0xff } else { 0 };
+        I24(i32::from_le_bytes([b0, b1, b2, sign]))
+    }
+}
+
+/// Triangular (TPDF) dither noise of ±1 least significant bit, which decorrelates the
+/// quantization error from the signal.
+#[derive(Debug, Clone)]
+pub struct Dither(u32);
+
+impl Default for Dither {
+    fn default() -> Self {
+        Dither(0x9e37_79b9)
+    }
+}
+
+impl Dither {
+    /// Returns the next noise value, in least significant bits.
+    pub fn noise(&mut self) -> f64 {
+        self.uniform() + self.uniform() - 1.0
+    }
+
+    // xorshift32, uniform in [0, 1)
+    fn uniform(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        f64::from(self.0) / (f64::from(u32::MAX) + 1.0)
+    }
+}
+
+/// Scale the sample to an integer range of `bits` bits, offset by `offset`, clipping it to the
+/// range.
+fn quantize(sample: f32, bits: u32, offset: f64, dither: Option<&mut Dither>) -> f64 {
+    let scale = f64::from(1_u32 << (bits - 1));
+    let noise = dither.map(Dither::noise).unwrap_or(0.0);
+    (f64::from(sample) * scale + noise)
+        .round()
+        .max(-scale)
+        .min(scale - 1.0)
+        + offset
+}
+
+fn normalize(sample: f64, bits: u32) -> f32 {
+    (sample / f64::from(1_u32 << (bits - 1))) as f32
+}
+
+impl Sample for f32 {
+    fn from_f32(sample: f32, _: &mut Dither) -> Self {
+        sample
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+}
+
+impl Sample for f64 {
+    fn from_f32(sample: f32, _: &mut Dither) -> Self {
+        f64::from(sample)
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+}
+
+impl Sample for i32 {
+    // the float mantissa has fewer bits, so there's nothing to dither
+    fn from_f32(sample: f32, _: &mut Dither) -> Self {
+        quantize(sample, 32, 0.0, None) as i32
+    }
+
+    fn to_f32(self) -> f32 {
+        normalize(f64::from(self), 32)
+    }
+}
+
+impl Sample for I24 {
+    fn from_f32(sample: f32, dither: &mut Dither) -> Self {
+        I24(quantize(sample, 24, 0.0, Some(dither)) as i32)
+    }
+
+    fn to_f32(self) -> f32 {
+        normalize(f64::from(self.0), 24)
+    }
+}
+
+impl Sample for I24Packed {
+    fn from_f32(sample: f32, dither: &mut Dither) -> Self {
+        I24::from_f32(sample, dither).into()
+    }
+
+    fn to_f32(self) -> f32 {
+        I24::from(self).to_f32()
+    }
+}
+
+impl Sample for i16 {
+    fn from_f32(sample: f32, dither: &mut Dither) -> Self {
+        quantize(sample, 16, 0.0, Some(dither)) as i16
+    }
+
+    fn to_f32(self) -> f32 {
+        normalize(f64::from(self), 16)
+    }
+}
+
+impl Sample for u16 {
+    fn from_f32(sample: f32, dither: &mut Dither) -> Self {
+        quantize(sample, 16, 32768.0, Some(dither)) as u16
+    }
+
+    fn to_f32(self) -> f32 {
+        normalize(f64::from(self) - 32768.0, 16)
+    }
+}
+
+impl Sample for u8 {
+    fn from_f32(sample: f32, dither: &mut Dither) -> Self {
+        quantize(sample, 8, 128.0, Some(dither)) as u8
+    }
+
+    fn to_f32(self) -> f32 {
+        normalize(f64::from(self) - 128.0, 8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer_scaling() {
+        let mut dither = Dither::default();
+
+        assert_eq!(i32::from_f32(0.5, &mut dither), 1 << 30);
+        assert_eq!(i32::from_f32(-1.0, &mut dither), i32::MIN);
+        assert_eq!((1_i32 << 30).to_f32(), 0.5);
+        assert_eq!(I24(-(1 << 22)).to_f32(), -0.5);
+        assert_eq!(0_u8.to_f32(), -1.0);
+        assert_eq!(128_u8.to_f32(), 0.0);
+        assert_eq!(32768_u16.to_f32(), 0.0);
+        assert_eq!(f64::from_f32(0.25, &mut dither), 0.25);
+    }
+
+    #[test]
+    fn clipping() {
+        let mut dither = Dither::default();
+
+        assert_eq!(i32::from_f32(1.0, &mut dither), i32::MAX);
+        assert_eq!(i32::from_f32(-2.0, &mut dither), i32::MIN);
+        assert_eq!(I24::from_f32(1.5, &mut dither), I24((1 << 23) - 1));
+        assert_eq!(I24::from_f32(-1.5, &mut dither), I24(-(1 << 23)));
+        assert_eq!(i16::from_f32(7.0, &mut dither), i16::MAX);
+        assert_eq!(u8::from_f32(1.5, &mut dither), 255);
+        assert_eq!(u8::from_f32(-1.5, &mut dither), 0);
+        assert_eq!(f64::from_f32(2.0, &mut dither), 2.0);
+    }
+
+    #[test]
+    fn dithering() {
+        let mut dither = Dither::default();
+        let noise: Vec<f64> = (0..10000).map(|_| dither.noise()).collect();
+        assert!(noise.iter().all(|n| n.abs() < 1.0));
+        let mean = noise.iter().sum::<f64>() / noise.len() as f64;
+        assert!(mean.abs() < 0.02);
+
+        // the error stays within one step, and averages out
+        let sample = 0.3;
+        let quantized: Vec<f64> = (0..10000)
+            .map(|_| f64::from(i16::from_f32(sample, &mut dither).to_f32()))
+            .collect();
+        let step = 1.0 / 32768.0;
+        assert!(quantized
+            .iter()
+            .all(|q| (q - f64::from(sample)).abs() <= step * 1.5));
+        let mean = quantized.iter().sum::<f64>() / quantized.len() as f64;
+        assert!((mean - f64::from(sample)).abs() < step * 0.1);
+    }
+
+    #[test]
+    fn packed_24_bit() {
+        for value in &[0, 1, -1, (1 << 23) - 1, -(1 << 23), 0x12_3456, -0x12_3456] {
+            let packed = I24Packed::from(I24(*value));
+            assert_eq!(I24::from(packed), I24(*value));
+        }
+        assert_eq!(
+            I24Packed::from(I24(0x12_3456)),
+            I24Packed([0x56, 0x34, 0x12])
+        );
+        assert_eq!(I24Packed::from(I24(-1)), I24Packed([0xff, 0xff, 0xff]));
+    }
+}
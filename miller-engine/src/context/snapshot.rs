@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use zengarden_raw::ZGGraph;
+
+use crate::graph::Graph;
+use crate::object::external::ObjectType;
+use crate::object::Object;
+
+/// State of the running patches, taken with [Context::snapshot](super::Context::snapshot) and
+/// recalled with [Context::restore](super::Context::restore), e.g. to switch between presets or to
+/// resume a performance. It can be saved as TOML or JSON with serde.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Snapshot {
+    /// Named `[value]`s.
+    pub values: BTreeMap<String, f32>,
+    /// Table contents by table name.
+    pub tables: BTreeMap<String, Vec<f32>>,
+    /// States of the `[float]`s, `[tgl]`s and message boxes of each top-level graph, in the order
+    /// the graphs were created.
+    pub graphs: Vec<Vec<ObjectState>>,
+}
+
+/// State of an object in a [Snapshot].
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ObjectState {
+    /// Indices of the subgraphs the object is in, followed by the index of the object, as in
+    /// [Graph::objects].
+    pub path: Vec<usize>,
+    /// Class name of the object, see [Object::class_name].
+    pub class: String,
+    /// The state, see [Object::state].
+    pub state: String,
+}
+
+impl Snapshot {
+    /// Add the state of a top-level graph.
+    pub(super) fn capture(&mut self, graph: &Graph<'_>) {
+        let mut states = Vec::new();
+        self.capture_objects(graph, &mut Vec::new(), &mut states);
+        for table in graph.tables() {
+            self.tables.insert(table.name(), table.to_vec());
+        }
+        self.graphs.push(states);
+    }
+
+    fn capture_objects(
+        &mut self,
+        graph: &Graph<'_>,
+        path: &mut Vec<usize>,
+        states: &mut Vec<ObjectState>,
+    ) {
+        for (index, object) in graph.objects().into_iter().enumerate() {
+            path.push(index);
+            if object.object_type() == ObjectType::ObjectPd {
+                self.capture_objects(&subgraph(object), path, states);
+            } else if let Some(state) = object.state() {
+                match object.value_name() {
+                    Some(name) => {
+                        if let Ok(value) = state.parse() {
+                            self.values.insert(name, value);
+                        }
+                    }
+                    None => states.push(ObjectState {
+                        path: path.clone(),
+                        class: object.class_name(),
+                        state,
+                    }),
+                }
+            }
+            path.pop();
+        }
+    }
+
+    /// Restore the state of the top-level graph with the index. Objects which aren't found at
+    /// their path or whose class has changed are skipped.
+    pub(super) fn restore(&self, index: usize, graph: &Graph<'_>) {
+        for state in self.graphs.get(index).into_iter().flatten() {
+            if let Some(object) = find(graph, &state.path) {
+                if object.class_name() == state.class {
+                    object.set_state(&state.state);
+                }
+            }
+        }
+        self.restore_values(graph);
+        for table in graph.tables() {
+            if let Some(buffer) = self.tables.get(&table.name()) {
+                // the table has just been found in the graph
+                let _ = table.set_buffer(buffer);
+            }
+        }
+    }
+
+    // ZenGarden has no API for the values, so they're set through the `[value]`s of the graph
+    fn restore_values(&self, graph: &Graph<'_>) {
+        for object in graph.objects() {
+            if object.object_type() == ObjectType::ObjectPd {
+                self.restore_values(&subgraph(object));
+            } else if let Some(value) = object.value_name().and_then(|name| self.values.get(&name))
+            {
+                object.set_state(&value.to_string());
+            }
+        }
+    }
+}
+
+fn subgraph<'a>(object: Object) -> Graph<'a> {
+    Graph::child(object.0 as *mut ZGGraph)
+}
+
+fn find(graph: &Graph<'_>, path: &[usize]) -> Option<Object> {
+    let (&index, rest) = path.split_first()?;
+    let object = *graph.objects().get(index)?;
+    if rest.is_empty() {
+        Some(object)
+    } else if object.object_type() == ObjectType::ObjectPd {
+        find(&subgraph(object), rest)
+    } else {
+        None
+    }
+}
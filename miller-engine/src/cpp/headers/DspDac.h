@@ -37,6 +37,10 @@ class DspDac : public DspObject {
 
   private:
     static void processSignal(DspObject *dspObject, int fromIndex, int toIndex);
+
+    /** Adds the input to the output buffer, applying the output fade of the
+     * root graph. */
+    void addToOutput(float *output, float *input, int toIndex);
 };
 
 inline std::string DspDac::toString() { return DspDac::getObjectLabel(); }
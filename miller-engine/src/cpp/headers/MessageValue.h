@@ -35,6 +35,9 @@ class MessageValue : public MessageObject {
 
     static const char *getObjectLabel();
     std::string toString();
+    std::string getState();
+    void setState(const char *state);
+    const char *getValueName();
 
   private:
     void processMessage(int inletIndex, PdMessage *message);
@@ -48,4 +51,6 @@ inline std::string MessageValue::toString() {
 
 inline const char *MessageValue::getObjectLabel() { return "value"; }
 
+inline const char *MessageValue::getValueName() { return name; }
+
 #endif // _MESSAGE_VALUE_H_
@@ -50,6 +50,14 @@ class PdFileParser {
 
     PdGraph *execute(PdContext *context);
 
+    /**
+     * Parses the file as an abstraction with the given arguments, adds it to
+     * the graph and returns it. The graph itself is returned if the file is
+     * empty or can't be read.
+     */
+    PdGraph *executeAbstraction(PdMessage *initMsg, PdGraph *graph,
+                                PdContext *context);
+
   private:
     PdGraph *execute(PdMessage *initMsg, PdGraph *graph, PdContext *context,
                      bool isSubPatch);
@@ -125,6 +125,10 @@ class PdGraph : public DspObject {
      * code>false</code> otherwise. */
     bool isRootGraph();
 
+    /** Returns <code>true</code> if this graph is attached to its context,
+     * <code>false</code> otherwise. */
+    bool isAttached();
+
     /** Returns this graph's parent graph. Returns <code>NULL</code> if this
      * graph is a top-level graph. */
     PdGraph *getParentGraph();
@@ -228,12 +232,19 @@ class PdGraph : public DspObject {
     unsigned int getNumInlets();
     unsigned int getNumOutlets();
 
+    /** Returns DSP if the given inlet is an inlet~, MESSAGE otherwise. */
+    ConnectionType getInletConnectionType(unsigned int inletIndex);
+
     /** Returns the context with which this graph is associated. */
     PdContext *getContext();
 
     /** Returns this PdGraph's node list. */
     list<MessageObject *> getNodeList();
 
+    /** Returns this PdGraph's DSP objects in process order. The order is
+     * computed when the graph is attached. */
+    list<DspObject *> getDspNodeList();
+
     list<ObjectLetPair> getIncomingConnections(unsigned int inletIndex);
     list<ObjectLetPair> getOutgoingConnections(unsigned int outletIndex);
 
@@ -248,6 +259,26 @@ class PdGraph : public DspObject {
     /** Set the graph name. */
     void setName(string newName) { name = newName; }
 
+    /**
+     * Starts an equal-power fade of the output of the <code>dac~</code>
+     * objects in this graph and its subgraphs, over the given number of
+     * samples. The fade starts from the current gain, so an unfinished fade
+     * can be reversed without a jump. A fade of zero samples sets the gain
+     * immediately. Subgraphs hand the fade off to their root graph.
+     */
+    void fadeOutput(float numSamples, bool fadeIn);
+
+    /** Returns <code>true</code> while the output fade is in progress. */
+    bool isFadingOutput();
+
+    /** Returns the output gain at the given sample of the current block.
+     * Subgraphs use the gain of their root graph. */
+    float getOutputGain(int blockIndex);
+
+    /** Advances the output fade by the given number of samples. Called by the
+     * context after each block. */
+    void advanceOutputFade(int numSamples);
+
   private:
     static void processGraph(DspObject *dspObject, int fromIndex, int toIndex);
 
@@ -311,6 +342,15 @@ class PdGraph : public DspObject {
 
     /** PdGraphs may have an associated name, such as their abstraction name. */
     string name;
+
+    /** Position of the output fade, from 0 to 1. */
+    float fadePosition;
+
+    /** Fade position increment per sample. 0 if the output isn't fading. */
+    float fadeIncrement;
+
+    /** Direction of the output fade. */
+    bool fadingIn;
 };
 
 #endif // _PD_GRAPH_H_
@@ -69,12 +69,52 @@ typedef struct ZGReceiverMessagePair {
     ZGMessage *message;
 } ZGReceiverMessagePair;
 
+/** Enumerates the audio file formats supported by the table file functions. */
+typedef enum ZGAudioFileFormat {
+    ZG_AUDIO_FILE_WAV,
+    ZG_AUDIO_FILE_AIFF,
+    ZG_AUDIO_FILE_FLAC
+} ZGAudioFileFormat;
+
 /** Enumerates the kinds of connections in ZenGarden; Message and DSP */
 typedef enum ZGConnectionType {
     ZG_CONNECTION_MESSAGE,
     ZG_CONNECTION_DSP
 } ZGConnectionType;
 
+/** Enumerates the types of objects. The values match ObjectType. */
+typedef enum ZGObjectType {
+    ZG_OBJECT_DSP_ADC,
+    ZG_OBJECT_DSP_ADD,
+    ZG_OBJECT_DSP_BANDPASS_FILTER,
+    ZG_OBJECT_DSP_CATCH,
+    ZG_OBJECT_DSP_CLIP,
+    ZG_OBJECT_DSP_COSINE,
+    ZG_OBJECT_DSP_DAC,
+    ZG_OBJECT_DSP_TABLE_PLAY,
+    ZG_OBJECT_DSP_DELAY_READ,
+    ZG_OBJECT_DSP_DELAY_WRITE,
+    ZG_OBJECT_DSP_INLET,
+    ZG_OBJECT_DSP_OUTLET,
+    ZG_OBJECT_DSP_RECEIVE,
+    ZG_OBJECT_DSP_SEND,
+    ZG_OBJECT_DSP_TABLE_READ,
+    ZG_OBJECT_DSP_TABLE_READ4,
+    ZG_OBJECT_DSP_TABLE_WRITE,
+    ZG_OBJECT_DSP_THROW,
+    ZG_OBJECT_DSP_VARIABLE_DELAY,
+    ZG_OBJECT_MESSAGE_INLET,
+    ZG_OBJECT_MESSAGE_NOTEIN,
+    ZG_OBJECT_MESSAGE_OUTLET,
+    ZG_OBJECT_MESSAGE_RECEIVE,
+    ZG_OBJECT_MESSAGE_SEND,
+    ZG_OBJECT_MESSAGE_TABLE,
+    ZG_OBJECT_MESSAGE_TABLE_READ,
+    ZG_OBJECT_MESSAGE_TABLE_WRITE,
+    ZG_OBJECT_PD,
+    ZG_OBJECT_UNKNOWN
+} ZGObjectType;
+
 #pragma mark - Context
 
 /** Create a new context to which graphs can be added. */
@@ -116,6 +156,9 @@ void *zg_context_get_userinfo(ZGContext *context);
  */
 ZGGraph *zg_context_get_graphs(ZGContext *context, unsigned int *n);
 
+/** Returns the number of root graphs attached to this context. */
+unsigned int zg_context_get_num_graphs(ZGContext *context);
+
 /**
  * Register an external such that the context can instantiate instances of it.
  * If an object with the same label already exists, then the factory method is
@@ -129,6 +172,17 @@ void zg_context_register_external_object(
 void zg_context_unregister_external_object(ZGContext *context,
                                            const char *objectLabel);
 
+/**
+ * Returns an array of the labels of all objects which the context can
+ * instantiate, including the registered externals. The labels are valid until
+ * an external is registered or unregistered.
+ */
+const char **zg_context_get_object_labels(ZGContext *context, unsigned int *n);
+
+/** Returns true if the label refers to a registered external object. */
+bool zg_context_is_external_object(ZGContext *context,
+                                   const char *objectLabel);
+
 #pragma mark - Abstractions from Context
 
 /** Register an abstraction from memory. */
@@ -144,7 +198,7 @@ void zg_context_unregister_memorymapped_abstraction(ZGContext *context,
 
 /** Returns the global table object with the given name. NULL if the table does
  * not exist. */
-ZGObject *zg_context_get_table_for_name(ZGObject *table, const char *name);
+ZGObject *zg_context_get_table_for_name(ZGContext *context, const char *name);
 
 #pragma mark - Graph
 
@@ -159,13 +213,50 @@ unsigned int zg_graph_get_dollar_zero(ZGGraph *graph);
 /** Attaches a graph to its context */
 void zg_graph_attach(ZGGraph *graph);
 
+/** Returns true if the graph is attached to its context, false otherwise. */
+bool zg_graph_is_attached(ZGGraph *graph);
+
+/** Locks the context of the graph, whether or not the graph is attached. No
+ * block is processed until zg_graph_unlock_context() is called. The lock is
+ * recursive, so other graph functions may be called while it is held. */
+void zg_graph_lock_context(ZGGraph *graph);
+
+/** Unlocks the context of the graph locked with zg_graph_lock_context(). */
+void zg_graph_unlock_context(ZGGraph *graph);
+
 /** Unattaches a graph to its context */
 void zg_graph_unattach(ZGGraph *graph);
 
+/** Returns the top-level graph which contains the graph, or the graph itself. */
+ZGGraph *zg_graph_get_root(ZGGraph *graph);
+
+/** Starts an equal-power fade in or out of the dac~ output of the graph's
+ * top-level graph, over the given duration. The fade starts from the current
+ * gain, and a duration of 0 sets the gain immediately. */
+void zg_graph_fade_output(ZGGraph *graph, float duration_ms, bool fade_in);
+
+/** Returns true while the output of the graph's top-level graph is fading,
+ * false otherwise. */
+bool zg_graph_is_fading_output(ZGGraph *graph);
+
+/**
+ * Recomputes the DSP process order of the graph's top-level graph if it's
+ * attached, e.g. after objects were added to the graph or to one of its
+ * subgraphs while the context was locked. Unlike detaching and reattaching
+ * the graph, this keeps the order of the top-level graphs in the context.
+ */
+void zg_graph_compute_dsp_order(ZGGraph *graph);
+
 /** Returns all objects in this graph. The returned array, with length n, must
  * be freed by the caller. */
 ZGObject **zg_graph_get_objects(ZGGraph *graph, unsigned int *n);
 
+/** Returns the DSP objects of this graph in process order, including the
+ * implicit +~~ objects which sum fanned-in signal connections. The order is
+ * computed when the graph is attached. The returned array, with length n, must
+ * be freed by the caller. */
+ZGObject **zg_graph_get_dsp_order(ZGGraph *graph, unsigned int *n);
+
 #pragma mark - Manage Connections
 
 /**
@@ -191,6 +282,15 @@ void zg_graph_remove_connection(ZGGraph *graph, ZGObject *fromObject,
 ZGConnectionType zg_object_get_connection_type(ZGObject *object,
                                                unsigned int outletIndex);
 
+/** Returns the type of the given object. Objects without a specific type are
+ * ZG_OBJECT_UNKNOWN. */
+ZGObjectType zg_object_get_type(ZGObject *object);
+
+/** Returns ZG_CONNECTION_DSP if the inlet of the given object accepts signal
+ * connections, ZG_CONNECTION_MESSAGE if it accepts only messages. */
+ZGConnectionType zg_object_get_inlet_type(ZGObject *object,
+                                          unsigned int inletIndex);
+
 unsigned int zg_object_get_num_inlets(ZGObject *object);
 
 unsigned int zg_object_get_num_outlets(ZGObject *object);
@@ -285,6 +385,45 @@ void zg_context_unregister_receiver(ZGContext *context,
 ZGObject *zg_graph_add_new_object(ZGGraph *graph, const char *objectString,
                                   float canvasX, float canvasY);
 
+/**
+ * Create a new object with a string like zg_graph_add_new_object(), but don't
+ * add it to the graph yet, so it isn't seen by the audio thread. The object
+ * must be added with zg_graph_add_object() or deleted with zg_object_delete().
+ * A "pd name" string creates an empty subgraph. Returns NULL if the object
+ * can't be created.
+ */
+ZGObject *zg_graph_new_object(ZGGraph *graph, const char *objectString);
+
+/**
+ * Add an object created with zg_graph_new_object() to the graph it was
+ * created for.
+ */
+void zg_graph_add_object(ZGGraph *graph, ZGObject *object, float canvasX,
+                         float canvasY);
+
+/**
+ * Deletes an object created with zg_graph_new_object() which hasn't been added
+ * to its graph.
+ */
+void zg_object_delete(ZGObject *object);
+
+/**
+ * Create a new empty subgraph, i.e. [pd name], and add it to the graph. The
+ * subgraph shares the arguments and $0 of the graph. Its inlet/~ and outlet/~
+ * objects become the inlets and outlets of the subgraph object.
+ */
+ZGGraph *zg_graph_add_new_subgraph(ZGGraph *graph, const char *name,
+                                   float canvasX, float canvasY);
+
+/**
+ * Instantiate the abstraction at the given path with the given arguments (which
+ * may be NULL) and add it to the graph. A relative path is looked up in the
+ * declared paths of the graph. Returns NULL if the file can't be found.
+ */
+ZGGraph *zg_graph_add_new_abstraction(ZGGraph *graph, const char *path,
+                                      const char *arguments, float canvasX,
+                                      float canvasY);
+
 /**
  * Removes the object from the graph and deletes it from memory. Any connections
  * that this object may have had in the graph are also deleted. The reference to
@@ -329,6 +468,20 @@ void zg_object_set_canvas_position(ZGObject *object, float x, float y);
 /** Returns the object label, e.g. "osc~" or "+". */
 const char *zg_object_get_label(ZGObject *object);
 
+/**
+ * Returns the text the object was created from, e.g. "osc~ 440" or
+ * "msg 1 \, 2", with any $ arguments unresolved. The text is empty for objects
+ * which ZenGarden creates implicitly. The string is owned by the object.
+ */
+const char *zg_object_get_creation_text(ZGObject *object);
+
+/**
+ * Returns a number which is unique among all objects created by the process,
+ * so an object can be told apart from an earlier object which was allocated at
+ * the same address.
+ */
+unsigned long long zg_object_get_serial(ZGObject *object);
+
 /**
  * Returns a canonical string description of the object. The description
  * reflects the state of the object when at the time of the request. The pointer
@@ -336,6 +489,23 @@ const char *zg_object_get_label(ZGObject *object);
  */
 char *zg_object_to_string(ZGObject *object);
 
+/**
+ * Returns the state of the object which isn't part of its description, e.g.
+ * the value stored in a [float] or [value], the output of a [toggle] or the
+ * content of a message box. NULL if the object is stateless. The pointer must
+ * be freed by the caller.
+ */
+char *zg_object_get_state(ZGObject *object);
+
+/** Restores a state returned by zg_object_get_state(). */
+void zg_object_set_state(ZGObject *object, const char *state);
+
+/**
+ * Returns the name of a [value] with any $ arguments resolved, or NULL for the
+ * other objects.
+ */
+const char *zg_object_get_value_name(ZGObject *object);
+
 #pragma mark - Table
 
 /**
@@ -352,6 +522,58 @@ float *zg_table_get_buffer(ZGObject *table, unsigned int *n);
  */
 void zg_table_set_buffer(ZGObject *table, float *buffer, unsigned int n);
 
+/** Returns the name of the table. NULL if the object isn't a table. */
+const char *zg_table_get_name(ZGObject *table);
+
+/** Returns the length of the table's buffer. Zero if the object isn't a table.
+ */
+unsigned int zg_table_get_length(ZGObject *table);
+
+/**
+ * Copies n samples starting at the given offset from the table's buffer into
+ * the given buffer. Returns 0 on success, or -1 if the range is out of the
+ * table's bounds or the object isn't a table. This operation is thread-safe
+ * with regards to zg_context_process().
+ */
+int zg_table_read(ZGObject *table, unsigned int offset, float *buffer,
+                  unsigned int n);
+
+/**
+ * Copies n samples from the given buffer into the table's buffer starting at
+ * the given offset. Returns 0 on success, or -1 if the range is out of the
+ * table's bounds or the object isn't a table. This operation is thread-safe
+ * with regards to zg_context_process().
+ */
+int zg_table_write(ZGObject *table, unsigned int offset, float *buffer,
+                   unsigned int n);
+
+/**
+ * Resizes the table's buffer. Existing samples are kept, new samples are set to
+ * zero. This operation is thread-safe with regards to zg_context_process().
+ */
+void zg_table_resize(ZGObject *table, unsigned int n);
+
+/**
+ * Reads the given channel of the audio file into the table. If resize is
+ * non-zero, the table is resized to the length of the file, otherwise the
+ * samples beyond the table's length are dropped and the rest of the table is
+ * zeroed. Returns the number of samples written to the table, -1 if the file
+ * cannot be opened, -2 if the channel is out of range, -3 if the object isn't
+ * a table, -4 if the file is empty or too long to be read, or -5 if its
+ * samples cannot be allocated.
+ */
+int zg_table_load_audio_file(ZGObject *table, const char *path,
+                             unsigned int channel, int resize);
+
+/**
+ * Writes the table to a mono audio file in the given format, using the sample
+ * rate of the table's context. WAV and AIFF files are written as 32-bit float,
+ * FLAC files as 24-bit PCM. Returns the number of samples written, -1 if the
+ * file cannot be opened for writing, or -3 if the object isn't a table.
+ */
+int zg_table_save_audio_file(ZGObject *table, const char *path,
+                             ZGAudioFileFormat format);
+
 #pragma mark - Message
 
 /** Returns a new message with the given timestamp and the number of elements.
@@ -388,6 +610,12 @@ const char *zg_message_get_symbol(ZGMessage *message, unsigned int index);
  * the caller. */
 char *zg_message_to_string(ZGMessage *message);
 
+#pragma mark - Memory
+
+/** Frees an array or a string returned by ZenGarden which must be freed by the
+ * caller, with the allocator ZenGarden was built with. */
+void zg_mem_free(void *ptr);
+
 #ifdef __cplusplus
 }
 #endif
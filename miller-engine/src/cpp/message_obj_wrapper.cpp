@@ -1,10 +1,15 @@
 #include "message_obj_wrapper.h"
+#include "PdGraph.h"
 
 MessageObject *init_obj_wrapper(int num_ins, int num_outs, PdGraph *graph,
                                 MessageObjAdapter *adapter) {
     return new MessageObjWrapper(num_ins, num_outs, graph, adapter);
 }
 
+void message_obj_print_err(PdGraph *graph, const char *message) {
+    graph->printErr("%s", message);
+}
+
 MessageObjWrapper::MessageObjWrapper(int numMessageInlets,
                                      int numMessageOutlets, PdGraph *graph_,
                                      MessageObjAdapter *adapter_)
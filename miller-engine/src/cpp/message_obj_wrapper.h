@@ -87,6 +87,8 @@ extern "C" void message_obj_send_message(MessageObjAdapter *adapter,
 
 // C FFI
 
+extern "C" void message_obj_print_err(PdGraph *graph, const char *message);
+
 extern "C" MessageObject *init_obj_wrapper(int num_ins, int num_outs,
                                            PdGraph *graph_,
                                            MessageObjAdapter *adapter);
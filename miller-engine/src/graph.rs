@@ -1,66 +1,125 @@
 //! This module contains graph-related stuff.
 
+mod history;
+mod host;
+mod netlist;
+mod registry;
+mod transaction;
+
+use std::cell::RefCell;
 use std::ffi::CString;
+use std::fmt::Write;
+use std::fs;
 use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::path::Path;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Arc, Weak};
 
-use anyhow::Error as Anyhow;
+use anyhow::{anyhow, Error as Anyhow};
+use thiserror::Error;
 use zengarden_raw::{
     zg_context_new_empty_graph, zg_context_new_graph_from_string, zg_graph_add_connection,
-    zg_graph_add_new_object, zg_graph_attach, zg_graph_delete, zg_graph_get_dollar_zero,
-    zg_graph_get_objects, zg_graph_remove_connection, zg_graph_unattach, ZGGraph,
+    zg_graph_add_new_abstraction, zg_graph_add_new_object, zg_graph_add_new_subgraph,
+    zg_graph_add_object, zg_graph_attach, zg_graph_compute_dsp_order, zg_graph_delete,
+    zg_graph_fade_output, zg_graph_get_dollar_zero, zg_graph_get_dsp_order, zg_graph_get_objects,
+    zg_graph_get_root, zg_graph_is_attached, zg_graph_is_fading_output, zg_graph_lock_context,
+    zg_graph_new_object, zg_graph_remove_connection, zg_graph_unattach, zg_graph_unlock_context,
+    zg_mem_free, zg_object_delete, ZGGraph, ZGObject,
 };
 
 use crate::context::{AudioLoop, Context, Dispatcher};
-use crate::object::{ConnectionPair, Object, ObjectPosition};
+use crate::object::external::ObjectType;
+use crate::object::{ConnectionPair, InletType, Object, ObjectPosition, OutletType};
+use crate::table::Table;
+
+pub use history::{EditHistory, Error as HistoryError};
+pub use host::{Error as HostError, PatchHost, PatchWatcher};
+pub use registry::ObjectId;
+pub use transaction::{
+    Error as TransactionError, GraphTransaction, TransactionObject, TransactionPair,
+};
 
 /// A graph is a collection of objects and the connections between them. A [Graph] is a subclass of
 /// [object::Object], and thus [Graph]s can contain other [Graph]s (such as abstraction or
 /// subgraphs). However, this does not mean that [Graph]s and [object::Object]s are
 /// interchangeable in the API. Specific functions are made available for each.
 #[derive(Debug)]
-pub struct Graph<'a>(*mut ZGGraph, PhantomData<&'a i64>);
+pub struct Graph<'a> {
+    // shared with the context, which replaces the graph when it's reconfigured
+    raw: Arc<AtomicPtr<ZGGraph>>,
+    // keeps the graph !Send like the raw pointer it wraps
+    marker: PhantomData<&'a *mut ZGGraph>,
+    registry: RefCell<registry::Registry>,
+    // `false` for subgraphs and abstractions, which are deleted together with their parent
+    owned: bool,
+}
 
 impl<'a> Graph<'a> {
     /// Initialize a new empty graph.
     pub fn new_empty<D: Dispatcher, L: AudioLoop>(context: &'a Context<D, L>) -> Self {
         unsafe {
             let raw_ptr = zg_context_new_empty_graph(*context.raw_context.read().unwrap());
-            Self(raw_ptr, Default::default())
+            let graph = Self::owned(raw_ptr);
+            context.track_graph(&graph.raw, None);
+            graph
         }
     }
 
     /// Initialize a graph from a Pd file.
+    ///
+    /// Abstractions are looked up in the patch directory, [context::Config::search_paths] and the
+    /// directories declared with `[declare -path]` (relative to the patch directory).
     pub fn from_file<D: Dispatcher, L: AudioLoop>(
         context: Context<D, L>,
         file: &str,
     ) -> Result<Self, Anyhow> {
         unsafe {
-            let contents = std::fs::read_to_string(file)?;
+            let path = fs::canonicalize(file)?;
+            let contents = fs::read_to_string(&path)?;
+            if !contents.trim_start().starts_with("#N canvas") {
+                return Err(anyhow!("{} isn't a Pd patch", path.display()));
+            }
+            let contents =
+                netlist::resolve_paths(&contents, path.parent(), &context.config().search_paths);
             let contents = CString::new(contents)?;
             let raw_ptr = zg_context_new_graph_from_string(
                 *context.raw_context.read().unwrap(),
                 contents.as_ptr(),
             );
+            if raw_ptr.is_null() {
+                return Err(anyhow!("Can't parse {}", path.display()));
+            }
 
-            Ok(Self(raw_ptr, Default::default()))
+            let graph = Self::owned(raw_ptr);
+            context.track_graph(&graph.raw, Some(contents));
+            Ok(graph)
         }
     }
 
     /// Initialize graph from raw type.
+    ///
+    /// The graph isn't known to the context, so it isn't reloaded by [Context::reconfigure].
     pub fn from_raw(raw: *mut ZGGraph) -> Self {
-        Self(raw, Default::default())
+        Self::owned(raw)
     }
 
     /// Initialize a graph from a Pd file content.
+    ///
+    /// Abstractions are looked up in [context::Config::search_paths] and the directories declared
+    /// with `[declare -path]` (relative to the current directory).
     pub fn from_str<D: Dispatcher, L: AudioLoop>(context: Context<D, L>, string: &str) -> Self {
         unsafe {
-            let contents = CString::new(string).expect("Can't build CString from netlist");
+            let contents = netlist::resolve_paths(string, None, &context.config().search_paths);
+            let contents = CString::new(contents).expect("Can't build CString from netlist");
             let raw_ptr = zg_context_new_graph_from_string(
                 *context.raw_context.read().unwrap(),
                 contents.as_ptr(),
             );
 
-            Self(raw_ptr, Default::default())
+            let graph = Self::owned(raw_ptr);
+            context.track_graph(&graph.raw, Some(contents));
+            graph
         }
     }
 
@@ -69,6 +128,10 @@ impl<'a> Graph<'a> {
     /// the object is attached and graph reconfigured (if necessary). If the graph is unattached
     /// then no audio interruption will take place, even if reconfiguration takes place.
     ///
+    /// The object is added with the context locked, and the DSP order of the top-level graph is
+    /// recomputed if it's attached, also when the object is added to a subgraph or an
+    /// abstraction.
+    ///
     /// The [object::ObjectPosition] is only relevant for input/~ and output/~ objects, otherwise
     /// `None` may be specified.
     pub fn add_object(&self, object: &str, position: Option<ObjectPosition>) -> Object {
@@ -81,23 +144,103 @@ impl<'a> Graph<'a> {
                 (0.0, 0.0)
             };
 
-            zg_graph_add_new_object(self.0, object.as_ptr(), x, y).into()
+            self.atomically(|| zg_graph_add_new_object(self.raw(), object.as_ptr(), x, y))
+                .into()
         }
     }
 
+    /// Create an empty subgraph, i.e. **"pd name"**, and add it to the graph. The subgraph shares
+    /// the arguments and `$0` of the graph. The `inlet`/`inlet~` and `outlet`/`outlet~` objects
+    /// added to the subgraph become the inlets and outlets of the subgraph object (ordered by
+    /// their x position), which is returned by [Graph::as_object].
+    ///
+    /// The returned handle is valid as long as the subgraph object isn't removed from the graph.
+    /// The subgraph is attached and detached together with the graph.
+    pub fn add_subgraph(&self, name: &str, position: Option<ObjectPosition>) -> Graph<'_> {
+        let name = CString::new(name).expect("Can't build CString from subgraph name");
+        let position = position.unwrap_or_else(|| (0.0, 0.0).into());
+        let raw = self.atomically(|| unsafe {
+            zg_graph_add_new_subgraph(self.raw(), name.as_ptr(), position.x, position.y)
+        });
+        Self::child(raw)
+    }
+
+    /// Instantiate an abstraction from a Pd file with the given arguments (e.g. **"440 foo"**)
+    /// and add it to the graph. A relative path which doesn't exist is looked up in the
+    /// directories declared in the graph (see [Graph::from_file]).
+    ///
+    /// The returned handle has the same validity as the one of [Graph::add_subgraph].
+    pub fn add_abstraction<P: AsRef<Path>>(&self, path: P, args: &str) -> Result<Graph<'_>, Error> {
+        let path = path.as_ref();
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let raw_path = CString::new(path.to_string_lossy().as_bytes())
+            .map_err(|_| Error::Instantiating(path.display().to_string()))?;
+        let raw_args = CString::new(args).map_err(|_| Error::Instantiating(args.to_string()))?;
+        let raw = self.atomically(|| unsafe {
+            zg_graph_add_new_abstraction(self.raw(), raw_path.as_ptr(), raw_args.as_ptr(), 0.0, 0.0)
+        });
+
+        if raw.is_null() {
+            Err(Error::Instantiating(path.display().to_string()))
+        } else {
+            Ok(Self::child(raw))
+        }
+    }
+
+    /// Returns the object which represents the graph in its parent graph, e.g. to connect a
+    /// subgraph returned by [Graph::add_subgraph].
+    pub fn as_object(&self) -> Object {
+        Object(self.raw() as *mut ZGObject)
+    }
+
+    fn owned(raw: *mut ZGGraph) -> Self {
+        Self::with_ownership(raw, true)
+    }
+
+    pub(crate) fn child(raw: *mut ZGGraph) -> Self {
+        Self::with_ownership(raw, false)
+    }
+
+    fn with_ownership(raw: *mut ZGGraph, owned: bool) -> Self {
+        Self {
+            raw: Arc::new(AtomicPtr::new(raw)),
+            marker: PhantomData,
+            registry: Default::default(),
+            owned,
+        }
+    }
+
+    pub(crate) fn raw(&self) -> *mut ZGGraph {
+        self.raw.load(Ordering::Acquire)
+    }
+
     /// Add a connection between two objects, both of which are in the given graph. The new
     /// connection may cause the object graph to be reordered and cause audio dropouts. If the
-    /// arguments do not define a valid connection, then this function does nothing.
-    pub fn add_connection(&self, from: ConnectionPair, to: ConnectionPair) {
-        unsafe {
-            zg_graph_add_connection(
-                self.0,
-                from.object.0,
-                from.index as i32,
-                to.object.0,
-                to.index as i32,
-            );
+    /// arguments do not define a valid connection, then nothing is changed and the reason is
+    /// returned.
+    pub fn add_connection(
+        &self,
+        from: ConnectionPair,
+        to: ConnectionPair,
+    ) -> Result<(), ConnectError> {
+        self.check_connection(from, to)?;
+        if to.object.connections_at_inlet(to.index).contains(&from) {
+            return Err(ConnectError::Duplicate);
         }
+        if from.object.outlet_type(from.index) == OutletType::Dsp
+            && dsp_path(to.object, from.object, |object| {
+                (0..object.num_outlets())
+                    .filter(|&outlet| object.outlet_type(outlet) == OutletType::Dsp)
+                    .flat_map(|outlet| object.connections_at_outlet(outlet))
+                    .map(|pair| pair.object)
+                    .collect()
+            })
+        {
+            return Err(ConnectError::DspCycle);
+        }
+
+        self.connect_unchecked(from, to);
+        Ok(())
     }
 
     /// Remove a connection between two objects, both of which are in the given graph. If the
@@ -105,7 +248,7 @@ impl<'a> Graph<'a> {
     pub fn remove_connection(&self, from: ConnectionPair, to: ConnectionPair) {
         unsafe {
             zg_graph_remove_connection(
-                self.0,
+                self.raw(),
                 from.object.0,
                 from.index as i32,
                 to.object.0,
@@ -114,37 +257,461 @@ impl<'a> Graph<'a> {
         }
     }
 
+    /// Replace an object with a new one created from `text`, e.g. to turn **"osc~ 440"** into
+    /// **"phasor~"**. The new object keeps the position of the old one, and every connection is
+    /// made again if it's still valid for the new object (see [Graph::add_connection]). The old
+    /// object is removed.
+    ///
+    /// The connections which couldn't be made are reported in [Retyped::dropped]. If the new
+    /// object can't be created, the graph is left unchanged. Otherwise the new object is created
+    /// off the graph and swapped in with the context locked, so the audio thread never sees both.
+    pub fn retype_object(&self, object: Object, text: &str) -> Result<Retyped, Error> {
+        if !self.objects().contains(&object) {
+            return Err(Error::NotInGraph(object));
+        }
+
+        let new = self
+            .create_object(text)
+            .ok_or_else(|| Error::Instantiating(text.to_string()))?;
+        let replace = |pair: ConnectionPair| {
+            if pair.object == object {
+                ConnectionPair::from((new, pair.index))
+            } else {
+                pair
+            }
+        };
+
+        let mut connections = Vec::new();
+        for inlet in 0..object.num_inlets() {
+            for from in object.connections_at_inlet(inlet) {
+                connections.push((replace(from), (new, inlet).into()));
+            }
+        }
+        for outlet in 0..object.num_outlets() {
+            for to in object.connections_at_outlet(outlet) {
+                let connection = ((new, outlet).into(), replace(to));
+                if !connections.contains(&connection) {
+                    connections.push(connection);
+                }
+            }
+        }
+
+        let dropped = self.atomically(|| {
+            let position = object.position();
+            self.registry.borrow_mut().replace(object, new);
+            object.remove();
+            self.insert_object(new, position);
+            let mut dropped = Vec::new();
+            for (from, to) in connections {
+                if self.add_connection(from, to).is_err() {
+                    dropped.push((from, to));
+                }
+            }
+            dropped
+        });
+
+        Ok(Retyped {
+            object: new,
+            dropped,
+        })
+    }
+
+    /// Start a [GraphTransaction] which applies a batch of edits to this graph in one step.
+    pub fn transaction(&self) -> GraphTransaction<'_, 'a> {
+        GraphTransaction::new(self)
+    }
+
+    /// Start an [EditHistory] which records edits of this graph so they can be undone and redone.
+    pub fn history(&self) -> EditHistory<'_, 'a> {
+        EditHistory::new(self)
+    }
+
     /// Returns all objects in this graph.
     pub fn objects(&self) -> Vec<Object> {
         unsafe {
             let mut n = 0;
-            let objects = zg_graph_get_objects(self.0, &mut n);
-            let objects = std::slice::from_raw_parts(objects, n as usize);
-            objects.into_iter().copied().map(From::from).collect()
+            let objects = zg_graph_get_objects(self.raw(), &mut n);
+            take_objects(objects, n)
+        }
+    }
+
+    /// Returns the id of an object, or `None` if the object doesn't belong to this graph. Unlike
+    /// [Object]s, ids can be kept safely: see [ObjectId].
+    pub fn object_id(&self, object: Object) -> Option<ObjectId> {
+        let objects = self.objects();
+        if objects.contains(&object) {
+            Some(self.registry.borrow_mut().id(object, &objects))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the object with the id, or `None` if it has been removed.
+    pub fn object(&self, id: ObjectId) -> Option<Object> {
+        self.registry.borrow_mut().get(id, &self.objects())
+    }
+
+    /// Removes an object and all of its connections from the graph and deletes it from memory,
+    /// like [Object::remove], and invalidates its id right away.
+    pub fn remove_object(&self, object: Object) -> Result<(), Error> {
+        if !self.objects().contains(&object) {
+            return Err(Error::NotInGraph(object));
         }
+
+        self.remove_unchecked(object);
+        Ok(())
+    }
+
+    /// Returns the tables of the graph, i.e. the `[table]` objects and arrays, including the ones
+    /// in subgraphs and abstractions.
+    ///
+    /// The tables refer to this [Graph] handle, so they're missing once it's dropped, even if the
+    /// graph itself lives on as a subgraph.
+    pub fn tables(&self) -> Vec<Table> {
+        let mut tables = Vec::new();
+        self.collect_tables(&Arc::downgrade(&self.raw), &mut tables);
+        tables
+    }
+
+    fn collect_tables(&self, root: &Weak<AtomicPtr<ZGGraph>>, tables: &mut Vec<Table>) {
+        for object in self.objects() {
+            match object.object_type() {
+                ObjectType::MessageTable => tables.push(Table::in_graph(root.clone(), object)),
+                ObjectType::ObjectPd => {
+                    Self::child(object.0 as *mut ZGGraph).collect_tables(root, tables)
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Returns all connections between the objects of this graph as pairs of the outlet and the
+    /// inlet they connect, ordered by the source object and outlet.
+    pub fn connections(&self) -> Vec<(ConnectionPair, ConnectionPair)> {
+        let mut connections = Vec::new();
+        for object in self.objects() {
+            for outlet in 0..object.num_outlets() {
+                for to in object.connections_at_outlet(outlet) {
+                    connections.push(((object, outlet).into(), to));
+                }
+            }
+        }
+
+        connections
+    }
+
+    /// Returns the DSP objects of this graph in the order they are processed. The order is
+    /// computed when the graph is attached, so it's empty for a graph which has never been
+    /// attached and it doesn't reflect the connections made since.
+    ///
+    /// The order includes the implicit `+~~` objects which sum the signal connections to the
+    /// same inlet. These objects aren't in [Graph::objects].
+    pub fn dsp_order(&self) -> Vec<Object> {
+        unsafe {
+            let mut n = 0;
+            let objects = zg_graph_get_dsp_order(self.raw(), &mut n);
+            take_objects(objects, n)
+        }
+    }
+
+    /// Export the graph to the Graphviz DOT format. Signal connections are drawn bold and DSP
+    /// objects are labeled with their index in [Graph::dsp_order].
+    pub fn to_dot(&self) -> String {
+        let objects = self.objects();
+        let order = self.dsp_order();
+        let mut dot = String::from("digraph {\n    node [shape=box];\n");
+        for (n, object) in objects.iter().enumerate() {
+            let mut label = object
+                .to_string()
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"");
+            if let Some(index) = order.iter().position(|o| o == object) {
+                label.push_str(&format!("\\n#{}", index));
+            }
+            let _ = writeln!(dot, "    n{} [label=\"{}\"];", n, label);
+        }
+        for (from, to) in self.connections() {
+            let style = match from.object.outlet_type(from.index) {
+                OutletType::Dsp => "bold",
+                OutletType::Message => "solid",
+            };
+            let (from_node, to_node) = (
+                objects.iter().position(|o| *o == from.object),
+                objects.iter().position(|o| *o == to.object),
+            );
+            if let (Some(from_node), Some(to_node)) = (from_node, to_node) {
+                let _ = writeln!(
+                    dot,
+                    "    n{} -> n{} [taillabel=\"{}\", headlabel=\"{}\", style={}];",
+                    from_node, to_node, from.index, to.index, style
+                );
+            }
+        }
+        dot.push_str("}\n");
+        dot
     }
 
     /// Returns the $0 argument to a graph, allowing graph-specific receivers to be addressed.
     pub fn dollar_zero(&self) -> usize {
-        unsafe { zg_graph_get_dollar_zero(self.0) as usize }
+        unsafe { zg_graph_get_dollar_zero(self.raw()) as usize }
     }
 
-    /// Attaches a graph to its context.
+    /// Attaches a graph to its context. Has no effect on subgraphs and abstractions, which are
+    /// attached together with their parent.
     pub fn attach(&self) {
-        unsafe { zg_graph_attach(self.0) }
+        if self.owned {
+            unsafe { zg_graph_attach(self.raw()) }
+        }
     }
 
-    /// Detaches a graph from its context.
+    /// Detaches a graph from its context. Has no effect on subgraphs and abstractions.
     pub fn detach(&self) {
-        unsafe { zg_graph_unattach(self.0) }
+        if self.owned {
+            unsafe { zg_graph_unattach(self.raw()) }
+        }
+    }
+
+    /// Returns `true` if the graph is attached to its context, either directly or through its
+    /// parent.
+    pub fn is_attached(&self) -> bool {
+        unsafe { zg_graph_is_attached(self.raw()) }
+    }
+
+    /// Start an equal-power fade in or out of the `[dac~]` output of the top-level graph. The
+    /// fade starts from the current gain, and a duration of 0 sets the gain immediately.
+    pub(crate) fn fade_output(&self, duration_ms: f32, fade_in: bool) {
+        unsafe { zg_graph_fade_output(self.raw(), duration_ms, fade_in) }
+    }
+
+    /// Returns `true` while the output of the top-level graph is fading.
+    pub(crate) fn is_fading_output(&self) -> bool {
+        unsafe { zg_graph_is_fading_output(self.raw()) }
+    }
+
+    /// Run `f` with the context locked, so the audio thread sees its effects at a block boundary.
+    pub(crate) fn with_context_locked<R>(&self, f: impl FnOnce() -> R) -> R {
+        unsafe {
+            zg_graph_lock_context(self.raw());
+            let result = f();
+            zg_graph_unlock_context(self.raw());
+            result
+        }
+    }
+
+    /// Check that the objects belong to the graph, that they have the outlet and the inlet, and
+    /// that a signal outlet isn't connected to a message inlet.
+    fn check_connection(
+        &self,
+        from: ConnectionPair,
+        to: ConnectionPair,
+    ) -> Result<(), ConnectError> {
+        check_connection_among(&self.objects(), from, to)
+    }
+
+    fn remove_unchecked(&self, object: Object) {
+        self.registry.borrow_mut().invalidate(object);
+        object.remove();
+    }
+
+    fn connect_unchecked(&self, from: ConnectionPair, to: ConnectionPair) {
+        unsafe {
+            zg_graph_add_connection(
+                self.raw(),
+                from.object.0,
+                from.index as i32,
+                to.object.0,
+                to.index as i32,
+            );
+        }
+    }
+
+    /// Create a new object and add it to the graph, returning `None` if it can't be instantiated.
+    pub(crate) fn new_object(&self, text: &str, position: ObjectPosition) -> Option<Object> {
+        let object = self.create_object(text)?;
+        self.atomically(|| self.insert_object(object, position));
+        Some(object)
+    }
+
+    /// Create a new object for the graph without adding it, so it isn't seen by the audio thread.
+    /// The object must be added with [Graph::insert_object] or deleted with [discard_object].
+    fn create_object(&self, text: &str) -> Option<Object> {
+        let text = CString::new(text).ok()?;
+        let raw = unsafe { zg_graph_new_object(self.raw(), text.as_ptr()) };
+        if raw.is_null() {
+            None
+        } else {
+            Some(raw.into())
+        }
+    }
+
+    /// Add an object created with [Graph::create_object].
+    fn insert_object(&self, object: Object, position: ObjectPosition) {
+        unsafe { zg_graph_add_object(self.raw(), object.0, position.x, position.y) }
+    }
+
+    /// Run the edits with the context locked, so the audio thread sees all of them at a block
+    /// boundary, and then recompute the DSP order of the top-level graph once if it's attached.
+    fn atomically<R>(&self, edit: impl FnOnce() -> R) -> R {
+        unsafe {
+            let root = zg_graph_get_root(self.raw());
+            zg_graph_lock_context(root);
+            let result = edit();
+            zg_graph_compute_dsp_order(root);
+            zg_graph_unlock_context(root);
+            result
+        }
+    }
+}
+
+/// Result of [Graph::retype_object].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retyped {
+    /// The new object.
+    pub object: Object,
+    /// Connections which couldn't be made with the new object, e.g. as it doesn't have the
+    /// inlet/outlet. The retyped object is referred to as the new object.
+    pub dropped: Vec<(ConnectionPair, ConnectionPair)>,
+}
+
+/// [Graph] errors.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum Error {
+    /// The object doesn't belong to the graph.
+    #[error("Object {0:?} doesn't belong to the graph")]
+    NotInGraph(Object),
+    /// The object can't be instantiated.
+    #[error("Can't instantiate object '{0}'")]
+    Instantiating(String),
+}
+
+/// Copy an array of objects returned by ZenGarden and free it.
+unsafe fn take_objects(objects: *mut *mut ZGObject, n: u32) -> Vec<Object> {
+    if objects.is_null() {
+        return Vec::new();
+    }
+
+    let result = std::slice::from_raw_parts(objects, n as usize)
+        .iter()
+        .copied()
+        .map(From::from)
+        .collect();
+    zg_mem_free(objects as *mut c_void);
+    result
+}
+
+/// Delete an object created with [Graph::create_object] which hasn't been added to the graph.
+fn discard_object(object: Object) {
+    unsafe { zg_object_delete(object.0) }
+}
+
+/// Check that the objects are among `objects`, that they have the outlet and the inlet, and that
+/// a signal outlet isn't connected to a message inlet.
+fn check_connection_among(
+    objects: &[Object],
+    from: ConnectionPair,
+    to: ConnectionPair,
+) -> Result<(), ConnectError> {
+    if !objects.contains(&from.object) || !objects.contains(&to.object) {
+        return Err(ConnectError::DifferentGraphs);
     }
+
+    let num_outlets = from.object.num_outlets();
+    if from.index >= num_outlets {
+        return Err(ConnectError::OutletOutOfRange {
+            object: from.object.to_string(),
+            outlet: from.index,
+            num_outlets,
+        });
+    }
+    let num_inlets = to.object.num_inlets();
+    if to.index >= num_inlets {
+        return Err(ConnectError::InletOutOfRange {
+            object: to.object.to_string(),
+            inlet: to.index,
+            num_inlets,
+        });
+    }
+    if from.object.outlet_type(from.index) == OutletType::Dsp
+        && to.object.inlet_type(to.index) == InletType::Message
+    {
+        return Err(ConnectError::SignalToMessageInlet {
+            object: to.object.to_string(),
+            inlet: to.index,
+        });
+    }
+
+    Ok(())
+}
+
+/// Returns `true` if `target` can be reached from `start`, following the objects returned by
+/// `successors`.
+fn dsp_path(start: Object, target: Object, successors: impl Fn(Object) -> Vec<Object>) -> bool {
+    let mut visited = Vec::new();
+    let mut stack = vec![start];
+    while let Some(object) = stack.pop() {
+        if object == target {
+            return true;
+        }
+        if !visited.contains(&object) {
+            visited.push(object);
+            stack.extend(successors(object));
+        }
+    }
+
+    false
+}
+
+/// [Graph::add_connection] errors.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum ConnectError {
+    /// The object doesn't have the outlet.
+    #[error("Object '{object}' doesn't have outlet {outlet} (it has {num_outlets})")]
+    OutletOutOfRange {
+        /// Object.
+        object: String,
+        /// Outlet index.
+        outlet: usize,
+        /// Number of outlets of the object.
+        num_outlets: usize,
+    },
+    /// The object doesn't have the inlet.
+    #[error("Object '{object}' doesn't have inlet {inlet} (it has {num_inlets})")]
+    InletOutOfRange {
+        /// Object.
+        object: String,
+        /// Inlet index.
+        inlet: usize,
+        /// Number of inlets of the object.
+        num_inlets: usize,
+    },
+    /// A signal outlet is connected to an inlet which accepts only messages.
+    #[error("Inlet {inlet} of object '{object}' doesn't accept signals")]
+    SignalToMessageInlet {
+        /// Object.
+        object: String,
+        /// Inlet index.
+        inlet: usize,
+    },
+    /// The objects don't belong to the same graph.
+    #[error("The objects don't belong to the same graph")]
+    DifferentGraphs,
+    /// The connection already exists.
+    #[error("The connection already exists")]
+    Duplicate,
+    /// The connection would create a cycle of signal connections.
+    #[error("The connection would create a DSP cycle")]
+    DspCycle,
 }
 
 impl<'a> Drop for Graph<'a> {
     fn drop(&mut self) {
+        if !self.owned {
+            return;
+        }
+
         unsafe {
             self.detach();
-            zg_graph_delete(self.0);
+            zg_graph_delete(self.raw());
         }
     }
 }
@@ -171,10 +738,60 @@ mod tests {
     #[test]
     fn from_string() {
         let context = init_test_context();
-        let contents = std::fs::read_to_string("test/send_message.pd").unwrap();
+        let contents = fs::read_to_string("test/send_message.pd").unwrap();
         let _ = Graph::from_str(context, &contents);
     }
 
+    #[test]
+    fn declare_path() {
+        let context = init_test_context();
+        let receiver_name = "declare-test-s";
+        context.register_receiver(receiver_name);
+        let graph = Graph::from_file(context.clone(), "test/declare.pd").unwrap();
+        graph.attach();
+
+        send_message_and_process_block(&context);
+        assert_eq!(*context.user_data(), 42);
+    }
+
+    #[test]
+    fn search_paths() {
+        let config = Config::default().with_search_path("test/abstractions");
+        let context = Context::<TestDispatcher, AudioLoopF32>::new(config).unwrap();
+        let receiver_name = "declare-test-s";
+        context.register_receiver(receiver_name);
+        let graph = Graph::from_str(
+            context.clone(),
+            "#N canvas 0 0 450 300 12;\n#X obj 10 10 forward;\n",
+        );
+        graph.attach();
+
+        send_message_and_process_block(&context);
+        assert_eq!(*context.user_data(), 42);
+    }
+
+    #[test]
+    fn memory_mapped_abstraction() {
+        let context = init_test_context();
+        let receiver_name = "abstraction-test-s";
+        context.register_receiver(receiver_name);
+        context.register_abstraction(
+            "mem-forward",
+            "#N canvas 0 0 450 300 12;\n\
+             #X obj 10 10 receive outer-receive;\n\
+             #X obj 10 40 send abstraction-test-s;\n\
+             #X connect 0 0 1 0;\n",
+        );
+        let graph = Graph::from_str(
+            context.clone(),
+            "#N canvas 0 0 450 300 12;\n#X obj 10 10 mem-forward;\n",
+        );
+        graph.attach();
+
+        send_message_and_process_block(&context);
+        assert_eq!(*context.user_data(), 42);
+    }
+
     #[test]
     fn add_object() {
         let context = init_test_context();
@@ -202,7 +819,9 @@ mod tests {
         send_message_and_process_block(&context);
         assert_eq!(*context.user_data(), 0);
 
-        graph.add_connection((receiver, 0).into(), (sender, 0).into());
+        graph
+            .add_connection((receiver, 0).into(), (sender, 0).into())
+            .unwrap();
         send_message_and_process_block(&context);
         assert_eq!(*context.user_data(), 42);
 
@@ -213,6 +832,155 @@ mod tests {
         assert_eq!(*context.user_data(), 0);
     }
 
+    #[test]
+    fn retype_object() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let osc = graph.add_object("osc~ 440", None);
+        let multiply = graph.add_object("*~ 0.5", Some((10.0, 20.0).into()));
+        let dac = graph.add_object("dac~", None);
+        let float = graph.add_object("float", None);
+        graph
+            .add_connection((osc, 0).into(), (multiply, 0).into())
+            .unwrap();
+        graph
+            .add_connection((float, 0).into(), (multiply, 1).into())
+            .unwrap();
+        graph
+            .add_connection((multiply, 0).into(), (dac, 0).into())
+            .unwrap();
+        graph.attach();
+
+        let retyped = graph.retype_object(multiply, "lop~ 1000").unwrap();
+        let lop = retyped.object;
+        assert_eq!(lop.position(), (10.0, 20.0).into());
+        assert_eq!(lop.connections_at_inlet(0), vec![(osc, 0).into()]);
+        assert_eq!(lop.connections_at_inlet(1), vec![(float, 0).into()]);
+        assert_eq!(dac.connections_at_inlet(0), vec![(lop, 0).into()]);
+        assert!(retyped.dropped.is_empty());
+        // The DSP order is recomputed in place.
+        assert!(graph.is_attached());
+        assert_eq!(graph.dsp_order(), vec![osc, lop, dac]);
+
+        let retyped = graph.retype_object(lop, "wrap~").unwrap();
+        let wrap = retyped.object;
+        assert_eq!(graph.objects(), vec![osc, dac, float, wrap]);
+        assert_eq!(wrap.connections_at_inlet(0), vec![(osc, 0).into()]);
+        assert_eq!(retyped.dropped, vec![((float, 0).into(), (wrap, 1).into())]);
+
+        assert_eq!(
+            graph.retype_object(wrap, "no-such-object"),
+            Err(Error::Instantiating("no-such-object".to_string()))
+        );
+        assert_eq!(graph.objects(), vec![osc, dac, float, wrap]);
+    }
+
+    #[test]
+    fn connect_errors() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let osc = graph.add_object("osc~ 440", None);
+        let lop = graph.add_object("lop~ 1000", None);
+        let add = graph.add_object("+~", None);
+        let other = Graph::new_empty(&context);
+        let foreign = other.add_object("dac~", None);
+
+        assert_eq!(
+            graph.add_connection((osc, 1).into(), (lop, 0).into()),
+            Err(ConnectError::OutletOutOfRange {
+                object: "osc~ 440".to_string(),
+                outlet: 1,
+                num_outlets: 1
+            })
+        );
+        assert!(matches!(
+            graph.add_connection((osc, 0).into(), (lop, 2).into()),
+            Err(ConnectError::InletOutOfRange {
+                inlet: 2,
+                num_inlets: 2,
+                ..
+            })
+        ));
+        assert!(matches!(
+            graph.add_connection((osc, 0).into(), (lop, 1).into()),
+            Err(ConnectError::SignalToMessageInlet { inlet: 1, .. })
+        ));
+        assert_eq!(
+            graph.add_connection((osc, 0).into(), (foreign, 0).into()),
+            Err(ConnectError::DifferentGraphs)
+        );
+
+        graph
+            .add_connection((osc, 0).into(), (lop, 0).into())
+            .unwrap();
+        assert_eq!(
+            graph.add_connection((osc, 0).into(), (lop, 0).into()),
+            Err(ConnectError::Duplicate)
+        );
+
+        graph
+            .add_connection((lop, 0).into(), (add, 0).into())
+            .unwrap();
+        assert_eq!(
+            graph.add_connection((add, 0).into(), (lop, 0).into()),
+            Err(ConnectError::DspCycle)
+        );
+        assert_eq!(
+            graph.add_connection((add, 0).into(), (add, 1).into()),
+            Err(ConnectError::DspCycle)
+        );
+    }
+
+    #[test]
+    fn object_ids() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let osc = graph.add_object("osc~ 440", None);
+        let dac = graph.add_object("dac~", None);
+        let osc_id = graph.object_id(osc).unwrap();
+        let dac_id = graph.object_id(dac).unwrap();
+        assert_ne!(osc_id, dac_id);
+        assert_eq!(graph.object_id(osc), Some(osc_id));
+        assert_eq!(graph.object(osc_id), Some(osc));
+
+        let other = Graph::new_empty(&context);
+        assert_eq!(other.object_id(osc), None);
+
+        // Retyped objects keep their ids.
+        let phasor = graph.retype_object(osc, "phasor~").unwrap().object;
+        assert_eq!(graph.object(osc_id), Some(phasor));
+        assert_eq!(graph.object_id(phasor), Some(osc_id));
+
+        graph.remove_object(phasor).unwrap();
+        assert_eq!(graph.object(osc_id), None);
+
+        // A new object may reuse the slot, but not the id.
+        let osc = graph.add_object("osc~ 440", None);
+        let new_id = graph.object_id(osc).unwrap();
+        assert_ne!(new_id, osc_id);
+        assert_eq!(graph.object(osc_id), None);
+
+        // Objects removed directly are detected on lookup.
+        dac.remove();
+        assert_eq!(graph.object(dac_id), None);
+        assert_eq!(graph.object(new_id), Some(osc));
+    }
+
+    #[test]
+    fn object_ids_address_reuse() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let float = graph.add_object("float", None);
+        let id = graph.object_id(float).unwrap();
+
+        // Remove the object behind the registry's back. An object allocated at the same address
+        // afterwards is told apart by its serial, which is tested in the registry.
+        float.remove();
+        let _ = graph.add_object("float", None);
+
+        assert_eq!(graph.object(id), None);
+    }
+
     #[test]
     fn objects() {
         let context = init_test_context();
@@ -223,6 +991,42 @@ mod tests {
         assert_eq!(graph.objects(), expected);
     }
 
+    #[test]
+    fn connections() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let osc = graph.add_object("osc~ 440", None);
+        let dac = graph.add_object("dac~", None);
+        graph
+            .add_connection((osc, 0).into(), (dac, 0).into())
+            .unwrap();
+        graph
+            .add_connection((osc, 0).into(), (dac, 1).into())
+            .unwrap();
+
+        assert_eq!(
+            graph.connections(),
+            vec![
+                ((osc, 0).into(), (dac, 0).into()),
+                ((osc, 0).into(), (dac, 1).into())
+            ]
+        );
+        assert!(graph.dsp_order().is_empty());
+
+        graph.attach();
+        assert_eq!(graph.dsp_order(), vec![osc, dac]);
+        assert_eq!(
+            graph.to_dot(),
+            "digraph {\n    \
+             node [shape=box];\n    \
+             n0 [label=\"osc~ 440\\n#0\"];\n    \
+             n1 [label=\"dac~\\n#1\"];\n    \
+             n0 -> n1 [taillabel=\"0\", headlabel=\"0\", style=bold];\n    \
+             n0 -> n1 [taillabel=\"0\", headlabel=\"1\", style=bold];\n\
+             }\n"
+        );
+    }
+
     #[test]
     fn dollar_zero() {
         let context = init_test_context();
@@ -232,6 +1036,71 @@ mod tests {
         }
     }
 
+    #[test]
+    fn subgraph() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let osc = graph.add_object("osc~ 440", None);
+        let subgraph = graph.add_subgraph("sub", None);
+        subgraph.add_object("inlet", Some((50.0, 0.0).into()));
+        subgraph.add_object("inlet~", Some((10.0, 0.0).into()));
+        subgraph.add_object("outlet~", None);
+
+        let object = subgraph.as_object();
+        assert_eq!(graph.objects(), vec![osc, object]);
+        assert_eq!(object.num_inlets(), 2);
+        assert_eq!(object.inlet_type(0), InletType::Dsp);
+        assert_eq!(object.inlet_type(1), InletType::Message);
+        assert_eq!(object.num_outlets(), 1);
+        assert_eq!(subgraph.dollar_zero(), graph.dollar_zero());
+        graph
+            .add_connection((osc, 0).into(), (object, 0).into())
+            .unwrap();
+
+        // dropping the handle doesn't delete the subgraph
+        drop(subgraph);
+        assert_eq!(graph.objects(), vec![osc, object]);
+    }
+
+    #[test]
+    fn subgraph_dsp_order() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        graph.attach();
+
+        // Objects added to the subgraph of an attached graph are processed right away.
+        let subgraph = graph.add_subgraph("sub", None);
+        let osc = subgraph.add_object("osc~ 440", None);
+        let dac = subgraph.add_object("dac~", None);
+        assert!(graph.is_attached());
+        assert_eq!(subgraph.dsp_order(), vec![osc, dac]);
+        assert_eq!(graph.dsp_order(), vec![subgraph.as_object()]);
+    }
+
+    #[test]
+    fn abstraction() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let abstraction = graph
+            .add_abstraction("test/abstractions/gain.pd", "0.5")
+            .unwrap();
+
+        let object = abstraction.as_object();
+        assert_eq!(graph.objects(), vec![object]);
+        assert_eq!(abstraction.objects().len(), 4);
+        assert_eq!(abstraction.connections().len(), 3);
+        assert_eq!(object.num_inlets(), 2);
+        assert_eq!(object.inlet_type(0), InletType::Dsp);
+        assert_eq!(object.num_outlets(), 1);
+        assert_ne!(abstraction.dollar_zero(), graph.dollar_zero());
+
+        let missing = "test/abstractions/missing.pd";
+        assert_eq!(
+            graph.add_abstraction(missing, "").err(),
+            Some(Error::Instantiating(missing.to_string()))
+        );
+    }
+
     fn init_test_context() -> Context<TestDispatcher, AudioLoopF32> {
         Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap()
     }
@@ -0,0 +1,738 @@
+//! Undo/redo history of graph edits.
+
+use thiserror::Error;
+use zengarden_raw::ZGGraph;
+
+use super::{ConnectError, Graph, ObjectId};
+use crate::object::external::ObjectType;
+use crate::object::{ConnectionPair, Object, ObjectPosition};
+
+/// Slot of an object and the index of its inlet/outlet.
+type SlotPair = (usize, usize);
+
+/// Connection from an outlet to an inlet.
+type SlotConnection = (SlotPair, SlotPair);
+
+/// Edits refer to objects by slots rather than by [ObjectId]s, as undoing the removal of an object
+/// recreates it with a new id. Applying an edit returns its inverse.
+#[derive(Debug, Clone)]
+enum Edit {
+    AddObject {
+        slot: usize,
+        text: String,
+        position: ObjectPosition,
+        // the contents of a subgraph
+        contents: Option<Contents>,
+        connections: Vec<SlotConnection>,
+    },
+    RemoveObject(usize),
+    AddConnection(SlotPair, SlotPair),
+    RemoveConnection(SlotPair, SlotPair),
+    Move(usize, ObjectPosition),
+    Retype {
+        slot: usize,
+        text: String,
+        connections: Vec<SlotConnection>,
+    },
+}
+
+/// Objects and connections of a removed subgraph, from which it's rebuilt.
+#[derive(Debug, Clone)]
+struct Contents {
+    objects: Vec<(String, ObjectPosition, Option<Contents>)>,
+    // by the indices of the objects
+    connections: Vec<((usize, usize), (usize, usize))>,
+}
+
+/// Editing layer over a [Graph] which records the inverse of every edit so it can be undone and
+/// redone, created with [Graph::history].
+///
+/// Edits may be grouped with [EditHistory::begin_group] and [EditHistory::end_group] and are
+/// then undone and redone together. Undoing the removal of an object recreates it with all of
+/// its connections, and undoing the removal of a subgraph rebuilds its contents as well.
+/// Retyping an object works like [Graph::retype_object], and undoing it also restores the
+/// connections the retype dropped.
+///
+/// Objects are recreated from their [Object::creation_text], so abstractions, which can't be
+/// recreated that way, can't be removed with [EditHistory::remove_object]. Objects recreated by
+/// [EditHistory::undo] or [EditHistory::redo] are new [Object]s, so objects should be looked up
+/// again with [Graph::objects] afterwards. Edits made on the graph directly are not recorded, and
+/// edits which refer to objects removed that way fail with [Error::Removed].
+#[derive(Debug)]
+pub struct EditHistory<'g, 'a> {
+    graph: &'g Graph<'a>,
+    slots: Vec<Option<ObjectId>>,
+    undo: Vec<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    group: Vec<Edit>,
+    depth: usize,
+}
+
+impl<'g, 'a> EditHistory<'g, 'a> {
+    pub(super) fn new(graph: &'g Graph<'a>) -> Self {
+        Self {
+            graph,
+            slots: Vec::new(),
+            undo: Vec::new(),
+            redo: Vec::new(),
+            group: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    /// Create a new object, e.g. **"osc~ 440"**, and add it to the graph.
+    pub fn add_object(
+        &mut self,
+        text: &str,
+        position: Option<ObjectPosition>,
+    ) -> Result<Object, Error> {
+        let slot = self.slots.len();
+        self.slots.push(None);
+        self.record(Edit::AddObject {
+            slot,
+            text: text.to_string(),
+            position: position.unwrap_or_else(|| (0.0, 0.0).into()),
+            contents: None,
+            connections: Vec::new(),
+        })?;
+        self.object(slot)
+    }
+
+    /// Remove an object and all of its connections from the graph. Removing an abstraction fails
+    /// with [Error::Abstraction].
+    pub fn remove_object(&mut self, object: Object) -> Result<(), Error> {
+        let slot = self.slot(object)?;
+        self.record(Edit::RemoveObject(slot))
+    }
+
+    /// Add a connection between two objects.
+    pub fn add_connection(
+        &mut self,
+        from: ConnectionPair,
+        to: ConnectionPair,
+    ) -> Result<(), Error> {
+        let (from, to) = (self.slot_pair(from)?, self.slot_pair(to)?);
+        self.record(Edit::AddConnection(from, to))
+    }
+
+    /// Remove a connection between two objects.
+    pub fn remove_connection(
+        &mut self,
+        from: ConnectionPair,
+        to: ConnectionPair,
+    ) -> Result<(), Error> {
+        let (from, to) = (self.slot_pair(from)?, self.slot_pair(to)?);
+        self.record(Edit::RemoveConnection(from, to))
+    }
+
+    /// Move an object on the canvas.
+    pub fn move_object(&mut self, object: Object, position: ObjectPosition) -> Result<(), Error> {
+        let slot = self.slot(object)?;
+        self.record(Edit::Move(slot, position))
+    }
+
+    /// Replace the object with a new one created from `text`, keeping its position and
+    /// connections. Returns the new object.
+    pub fn retype_object(&mut self, object: Object, text: &str) -> Result<Object, Error> {
+        let slot = self.slot(object)?;
+        self.record(Edit::Retype {
+            slot,
+            text: text.to_string(),
+            connections: Vec::new(),
+        })?;
+        self.object(slot)
+    }
+
+    /// Start a group of edits which are undone and redone together. Groups may be nested, in
+    /// which case the outermost group is recorded.
+    pub fn begin_group(&mut self) {
+        self.depth += 1;
+    }
+
+    /// End the group started with [EditHistory::begin_group].
+    pub fn end_group(&mut self) {
+        if self.depth == 0 {
+            return;
+        }
+
+        self.depth -= 1;
+        if self.depth == 0 && !self.group.is_empty() {
+            let group = std::mem::take(&mut self.group);
+            self.undo.push(group);
+        }
+    }
+
+    /// Returns `true` if there are edits to undo.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Returns `true` if there are edits to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Undo the last edit or group of edits. Returns `false` if there is nothing to undo.
+    ///
+    /// If an edit can't be applied, the edits of the group already undone are redone and the
+    /// error is returned. The history is kept, unless they can't be redone either, in which case
+    /// it's cleared.
+    pub fn undo(&mut self) -> Result<bool, Error> {
+        self.end_all_groups();
+        match self.undo.pop() {
+            Some(group) => match self.apply_group(&group) {
+                Ok(inverse) => {
+                    self.redo.push(inverse);
+                    Ok(true)
+                }
+                Err((err, rolled_back)) => {
+                    if rolled_back {
+                        self.undo.push(group);
+                    } else {
+                        self.clear();
+                    }
+                    Err(err)
+                }
+            },
+            None => Ok(false),
+        }
+    }
+
+    /// Redo the last undone edit or group of edits. Returns `false` if there is nothing to redo.
+    ///
+    /// Failures are handled like in [EditHistory::undo].
+    pub fn redo(&mut self) -> Result<bool, Error> {
+        self.end_all_groups();
+        match self.redo.pop() {
+            Some(group) => match self.apply_group(&group) {
+                Ok(inverse) => {
+                    self.undo.push(inverse);
+                    Ok(true)
+                }
+                Err((err, rolled_back)) => {
+                    if rolled_back {
+                        self.redo.push(group);
+                    } else {
+                        self.clear();
+                    }
+                    Err(err)
+                }
+            },
+            None => Ok(false),
+        }
+    }
+
+    /// Clear the undo and redo stacks.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.group.clear();
+    }
+
+    fn end_all_groups(&mut self) {
+        while self.depth > 0 {
+            self.end_group();
+        }
+    }
+
+    /// Apply a new edit and record its inverse.
+    fn record(&mut self, edit: Edit) -> Result<(), Error> {
+        let inverse = self.apply(edit)?;
+        self.redo.clear();
+        if self.depth > 0 {
+            self.group.push(inverse);
+        } else {
+            self.undo.push(vec![inverse]);
+        }
+
+        Ok(())
+    }
+
+    /// Apply a group of inverse edits in reverse order and return the inverse group.
+    ///
+    /// If an edit fails, the edits already applied are rolled back, and the error is returned
+    /// with whether the rollback succeeded.
+    fn apply_group(&mut self, group: &[Edit]) -> Result<Vec<Edit>, (Error, bool)> {
+        let mut inverse = Vec::with_capacity(group.len());
+        for edit in group.iter().rev() {
+            match self.apply(edit.clone()) {
+                Ok(edit) => inverse.push(edit),
+                Err(err) => {
+                    // every edit is rolled back, even after one of them fails
+                    let mut rolled_back = true;
+                    for edit in inverse.into_iter().rev() {
+                        rolled_back &= self.apply(edit).is_ok();
+                    }
+                    return Err((err, rolled_back));
+                }
+            }
+        }
+
+        Ok(inverse)
+    }
+
+    fn apply(&mut self, edit: Edit) -> Result<Edit, Error> {
+        match edit {
+            Edit::AddObject {
+                slot,
+                text,
+                position,
+                contents,
+                connections,
+            } => {
+                let object = self.instantiate(&text, position)?;
+                if let Some(contents) = &contents {
+                    if let Err(err) = self.rebuild(&subgraph(object), contents) {
+                        self.graph.remove_unchecked(object);
+                        return Err(err);
+                    }
+                }
+                self.slots[slot] = self.graph.object_id(object);
+                self.connect(&connections);
+                Ok(Edit::RemoveObject(slot))
+            }
+            Edit::RemoveObject(slot) => {
+                let object = self.object(slot)?;
+                let inverse = Edit::AddObject {
+                    slot,
+                    text: object.creation_text(),
+                    position: object.position(),
+                    contents: capture(object)?,
+                    connections: self.connections(object)?,
+                };
+                self.graph.remove_unchecked(object);
+                self.slots[slot] = None;
+                Ok(inverse)
+            }
+            Edit::AddConnection(from, to) => {
+                let (from_pair, to_pair) = (self.pair(from)?, self.pair(to)?);
+                self.graph.add_connection(from_pair, to_pair)?;
+                Ok(Edit::RemoveConnection(from, to))
+            }
+            Edit::RemoveConnection(from, to) => {
+                let (from_pair, to_pair) = (self.pair(from)?, self.pair(to)?);
+                self.graph.remove_connection(from_pair, to_pair);
+                Ok(Edit::AddConnection(from, to))
+            }
+            Edit::Move(slot, position) => {
+                let object = self.object(slot)?;
+                let previous = object.position();
+                object.set_position(position);
+                Ok(Edit::Move(slot, previous))
+            }
+            Edit::Retype {
+                slot,
+                text,
+                connections,
+            } => {
+                let old = self.object(slot)?;
+                let old_text = old.creation_text();
+                // the retyped object keeps its id, and thus its slot
+                let retyped = self.graph.retype_object(old, &text)?;
+
+                let mut dropped = Vec::new();
+                for (from, to) in retyped.dropped {
+                    dropped.push((self.slot_pair(from)?, self.slot_pair(to)?));
+                }
+                // Restore the connections dropped by the previous retype, if the object has the
+                // inlets/outlets for them again.
+                for connection in connections {
+                    let restored = match (self.pair(connection.0), self.pair(connection.1)) {
+                        (Ok(from), Ok(to)) => self.graph.add_connection(from, to).is_ok(),
+                        _ => false,
+                    };
+                    if !restored {
+                        dropped.push(connection);
+                    }
+                }
+
+                Ok(Edit::Retype {
+                    slot,
+                    text: old_text,
+                    connections: dropped,
+                })
+            }
+        }
+    }
+
+    fn instantiate(&self, text: &str, position: ObjectPosition) -> Result<Object, Error> {
+        self.graph
+            .new_object(text, position)
+            .ok_or_else(|| Error::Instantiating(text.to_string()))
+    }
+
+    /// Add the captured objects and connections to an empty subgraph.
+    fn rebuild(&self, graph: &Graph<'_>, contents: &Contents) -> Result<(), Error> {
+        let mut objects = Vec::with_capacity(contents.objects.len());
+        for (text, position, contents) in &contents.objects {
+            let object = graph
+                .new_object(text, *position)
+                .ok_or_else(|| Error::Instantiating(text.clone()))?;
+            if let Some(contents) = contents {
+                self.rebuild(&subgraph(object), contents)?;
+            }
+            objects.push(object);
+        }
+        for &((from, outlet), (to, inlet)) in &contents.connections {
+            let _ =
+                graph.add_connection((objects[from], outlet).into(), (objects[to], inlet).into());
+        }
+
+        Ok(())
+    }
+
+    /// Add the connections between the objects which still exist. Connections which can't be
+    /// made anymore are skipped.
+    fn connect(&self, connections: &[SlotConnection]) {
+        for &(from, to) in connections {
+            if let (Ok(from), Ok(to)) = (self.pair(from), self.pair(to)) {
+                let _ = self.graph.add_connection(from, to);
+            }
+        }
+    }
+
+    /// Returns all connections from and to the object, assigning slots to the connected objects.
+    fn connections(&mut self, object: Object) -> Result<Vec<SlotConnection>, Error> {
+        let mut connections = Vec::new();
+        for inlet in 0..object.num_inlets() {
+            for from in object.connections_at_inlet(inlet) {
+                connections.push((self.slot_pair(from)?, (self.slot(object)?, inlet)));
+            }
+        }
+        for outlet in 0..object.num_outlets() {
+            for to in object.connections_at_outlet(outlet) {
+                let connection = ((self.slot(object)?, outlet), self.slot_pair(to)?);
+                if !connections.contains(&connection) {
+                    connections.push(connection);
+                }
+            }
+        }
+
+        Ok(connections)
+    }
+
+    fn object(&self, slot: usize) -> Result<Object, Error> {
+        self.slots[slot]
+            .and_then(|id| self.graph.object(id))
+            .ok_or(Error::Removed)
+    }
+
+    fn pair(&self, (slot, index): SlotPair) -> Result<ConnectionPair, Error> {
+        Ok((self.object(slot)?, index).into())
+    }
+
+    /// Returns the slot of an object, checking that it belongs to the graph.
+    fn slot(&mut self, object: Object) -> Result<usize, Error> {
+        let id = self
+            .graph
+            .object_id(object)
+            .ok_or(Error::NotInGraph(object))?;
+        match self.slots.iter().position(|slot| *slot == Some(id)) {
+            Some(slot) => Ok(slot),
+            None => {
+                self.slots.push(Some(id));
+                Ok(self.slots.len() - 1)
+            }
+        }
+    }
+
+    fn slot_pair(&mut self, pair: ConnectionPair) -> Result<SlotPair, Error> {
+        Ok((self.slot(pair.object)?, pair.index))
+    }
+}
+
+/// Capture the contents of a subgraph, or `None` for the other objects.
+fn capture(object: Object) -> Result<Option<Contents>, Error> {
+    if object.object_type() != ObjectType::ObjectPd {
+        return Ok(None);
+    }
+    if object.class_name() != "pd" {
+        return Err(Error::Abstraction(object.creation_text()));
+    }
+
+    let graph = subgraph(object);
+    let objects = graph.objects();
+    let mut captured = Vec::with_capacity(objects.len());
+    for &object in &objects {
+        captured.push((object.creation_text(), object.position(), capture(object)?));
+    }
+    let index = |object| objects.iter().position(|&other| other == object);
+    let connections = graph
+        .connections()
+        .into_iter()
+        .filter_map(|(from, to)| {
+            Some((
+                (index(from.object)?, from.index),
+                (index(to.object)?, to.index),
+            ))
+        })
+        .collect();
+
+    Ok(Some(Contents {
+        objects: captured,
+        connections,
+    }))
+}
+
+fn subgraph<'a>(object: Object) -> Graph<'a> {
+    Graph::child(object.0 as *mut ZGGraph)
+}
+
+impl From<super::Error> for Error {
+    fn from(err: super::Error) -> Self {
+        match err {
+            super::Error::NotInGraph(object) => Self::NotInGraph(object),
+            super::Error::Instantiating(text) => Self::Instantiating(text),
+        }
+    }
+}
+
+/// [EditHistory] errors.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum Error {
+    /// The object doesn't belong to the graph.
+    #[error("Object {0:?} doesn't belong to the graph")]
+    NotInGraph(Object),
+    /// The object can't be instantiated.
+    #[error("Can't instantiate object '{0}'")]
+    Instantiating(String),
+    /// The connection is invalid.
+    #[error("Invalid connection: {0}")]
+    Connect(#[from] ConnectError),
+    /// The edit refers to an object which has been removed.
+    #[error("The edit refers to a removed object")]
+    Removed,
+    /// Abstractions can't be recreated, so their removal can't be undone.
+    #[error("Can't remove abstraction '{0}', as it can't be recreated")]
+    Abstraction(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::{AudioLoopF32, Config, Context, Dispatcher};
+    use crate::message::Message;
+
+    use super::*;
+
+    #[test]
+    fn undo_redo() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let mut history = graph.history();
+
+        let float = history.add_object("float", None).unwrap();
+        let add = history
+            .add_object("+ 1", Some((10.0, 20.0).into()))
+            .unwrap();
+        history
+            .add_connection((float, 0).into(), (add, 1).into())
+            .unwrap();
+        history.move_object(add, (30.0, 40.0).into()).unwrap();
+        assert_eq!(add.position(), (30.0, 40.0).into());
+
+        history.undo().unwrap();
+        assert_eq!(add.position(), (10.0, 20.0).into());
+        history.undo().unwrap();
+        assert!(add.connections_at_inlet(1).is_empty());
+        history.undo().unwrap();
+        assert_eq!(graph.objects(), vec![float]);
+
+        history.redo().unwrap();
+        history.redo().unwrap();
+        let add = graph.objects()[1];
+        assert_eq!(add.creation_text(), "+ 1");
+        assert_eq!(add.connections_at_inlet(1), vec![(float, 0).into()]);
+
+        // A new edit clears the redo stack.
+        history.remove_object(float).unwrap();
+        assert!(!history.can_redo());
+        assert!(add.connections_at_inlet(1).is_empty());
+
+        history.undo().unwrap();
+        let objects = graph.objects();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(
+            objects[0].connections_at_inlet(1),
+            vec![(objects[1], 0).into()]
+        );
+    }
+
+    #[test]
+    fn group() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let mut history = graph.history();
+
+        history.begin_group();
+        let float = history.add_object("float", None).unwrap();
+        let print = history.add_object("print", None).unwrap();
+        history
+            .add_connection((float, 0).into(), (print, 0).into())
+            .unwrap();
+        history.end_group();
+
+        assert!(history.undo().unwrap());
+        assert!(graph.objects().is_empty());
+        assert!(!history.undo().unwrap());
+
+        assert!(history.redo().unwrap());
+        let objects = graph.objects();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(
+            objects[1].connections_at_inlet(0),
+            vec![(objects[0], 0).into()]
+        );
+    }
+
+    #[test]
+    fn retype() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let float = graph.add_object("float", None);
+        let add = graph.add_object("+ 1", None);
+        graph
+            .add_connection((float, 0).into(), (add, 0).into())
+            .unwrap();
+        graph
+            .add_connection((float, 0).into(), (add, 1).into())
+            .unwrap();
+        let mut history = graph.history();
+
+        let print = history.retype_object(add, "print").unwrap();
+        assert_eq!(print.creation_text(), "print");
+        assert_eq!(print.connections_at_inlet(0), vec![(float, 0).into()]);
+
+        history.undo().unwrap();
+        let add = graph.objects()[1];
+        assert_eq!(add.creation_text(), "+ 1");
+        assert_eq!(add.connections_at_inlet(0), vec![(float, 0).into()]);
+        assert_eq!(add.connections_at_inlet(1), vec![(float, 0).into()]);
+
+        assert_eq!(
+            history.retype_object(add, "no-such-object").unwrap_err(),
+            Error::Instantiating("no-such-object".to_string())
+        );
+        assert_eq!(graph.objects()[1], add);
+    }
+
+    #[test]
+    fn creation_text() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        // the string representations of these objects don't include their arguments
+        let float = graph.add_object("f 1", None);
+        let message = graph.add_object("msg 1 \\, 2", None);
+        let mut history = graph.history();
+
+        history.remove_object(float).unwrap();
+        history.retype_object(message, "print").unwrap();
+        history.undo().unwrap();
+        history.undo().unwrap();
+        let texts: Vec<_> = graph.objects().iter().map(Object::creation_text).collect();
+        assert_eq!(texts, vec!["msg 1 \\, 2", "f 1"]);
+    }
+
+    #[test]
+    fn removed_directly() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let mut history = graph.history();
+        let float = history.add_object("float", None).unwrap();
+        history.move_object(float, (10.0, 10.0).into()).unwrap();
+
+        // The slot refers to the object's id, so an object created at the same address isn't
+        // mistaken for it.
+        float.remove();
+        let _ = graph.add_object("float", None);
+        assert_eq!(history.undo(), Err(Error::Removed));
+    }
+
+    #[test]
+    fn remove_subgraph() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let sub = graph.add_subgraph("sub", None);
+        let inlet = sub.add_object("inlet", None);
+        let add = sub.add_object("+ 1", Some((0.0, 30.0).into()));
+        let outlet = sub.add_object("outlet", Some((0.0, 60.0).into()));
+        sub.add_connection((inlet, 0).into(), (add, 0).into())
+            .unwrap();
+        sub.add_connection((add, 0).into(), (outlet, 0).into())
+            .unwrap();
+        let print = graph.add_object("print", None);
+        graph
+            .add_connection((sub.as_object(), 0).into(), (print, 0).into())
+            .unwrap();
+        let mut history = graph.history();
+
+        history.remove_object(sub.as_object()).unwrap();
+        history.undo().unwrap();
+        let object = graph.objects()[1];
+        assert_eq!(object.creation_text(), "pd sub");
+        assert_eq!(print.connections_at_inlet(0), vec![(object, 0).into()]);
+
+        let sub = subgraph(object);
+        let objects = sub.objects();
+        let texts: Vec<_> = objects.iter().map(Object::creation_text).collect();
+        assert_eq!(texts, vec!["inlet", "+ 1", "outlet"]);
+        assert_eq!(objects[2].position(), (0.0, 60.0).into());
+        assert_eq!(
+            sub.connections(),
+            vec![
+                ((objects[0], 0).into(), (objects[1], 0).into()),
+                ((objects[1], 0).into(), (objects[2], 0).into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn remove_abstraction() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let gain = graph
+            .add_abstraction("test/abstractions/gain.pd", "0.5")
+            .unwrap()
+            .as_object();
+        let mut history = graph.history();
+
+        assert!(matches!(
+            history.remove_object(gain),
+            Err(Error::Abstraction(_))
+        ));
+        assert_eq!(graph.objects(), vec![gain]);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn failed_group() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let float = graph.add_object("float", None);
+        let print = graph.add_object("print", None);
+        let mut history = graph.history();
+
+        history.begin_group();
+        history.move_object(float, (10.0, 10.0).into()).unwrap();
+        history.move_object(print, (20.0, 20.0).into()).unwrap();
+        history.end_group();
+
+        // Undoing the group moves print back first, which is rolled back when float can't be.
+        float.remove();
+        assert_eq!(history.undo(), Err(Error::Removed));
+        assert_eq!(print.position(), (20.0, 20.0).into());
+        assert!(history.can_undo());
+    }
+
+    fn init_test_context() -> Context<TestDispatcher, AudioLoopF32> {
+        Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap()
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestDispatcher;
+
+    impl Dispatcher for TestDispatcher {
+        type UserData = ();
+
+        fn receiver_message(_name: String, _message: Option<Message>, _data: &mut Self::UserData) {}
+    }
+}
@@ -0,0 +1,303 @@
+//! Hot reloading of patch files.
+
+use std::fs;
+use std::mem;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Error as Anyhow;
+use thiserror::Error;
+
+use super::Graph;
+use crate::context::{AudioLoop, Context, Dispatcher};
+
+/// Keeps a patch file loaded and attached to a context, and reloads it when the file changes.
+///
+/// The new version is loaded with [Graph::from_file] on the calling thread (e.g. the watcher
+/// thread started by [PatchHost::watch]) and only then swapped in, by detaching the old graph
+/// and attaching the new one with the context locked. So the audio thread sees the swap at a
+/// block boundary and isn't blocked while the patch is parsed.
+///
+/// The contents of the tables (`[table]` objects and arrays) are carried over to the tables with
+/// the same name in the new version. Named `[value]`s are stored in the context, so they keep
+/// their values as well.
+#[derive(Debug)]
+pub struct PatchHost<D: Dispatcher, L: AudioLoop> {
+    // declared before the context, so it's deleted while the context is alive
+    graph: Graph<'static>,
+    context: Context<D, L>,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl<D: Dispatcher, L: AudioLoop> PatchHost<D, L> {
+    /// Load the patch file and attach it to the context.
+    pub fn new<P: AsRef<Path>>(context: Context<D, L>, path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let modified = modified(&path);
+        let graph = load(&context, &path)?;
+        graph.attach();
+
+        Ok(Self {
+            graph,
+            context,
+            path,
+            modified,
+        })
+    }
+
+    /// Get the patch file path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the currently loaded graph. The graph is replaced on reload.
+    pub fn graph(&self) -> &Graph<'static> {
+        &self.graph
+    }
+
+    /// Reload the patch file if it has been modified since it was last loaded. Returns `true` if
+    /// it's been reloaded. A file which can't be accessed (e.g. while an editor is replacing it)
+    /// is considered unmodified.
+    pub fn poll(&mut self) -> Result<bool, Error> {
+        let modified = modified(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return Ok(false);
+        }
+
+        self.modified = modified;
+        self.reload()?;
+        Ok(true)
+    }
+
+    /// Load the patch file and swap it in. The old version is kept if the file can't be loaded.
+    pub fn reload(&mut self) -> Result<(), Error> {
+        let graph = load(&self.context, &self.path)?;
+        let tables = graph.tables();
+
+        // the tables are copied under the same lock as the swap, so no block written by the audio
+        // thread (e.g. with `[tabwrite~]`) is lost in between
+        self.graph.with_context_locked(|| {
+            let contents: Vec<(String, Vec<f32>)> = self
+                .graph
+                .tables()
+                .iter()
+                .map(|table| (table.name(), table.to_vec()))
+                .collect();
+            self.graph.detach();
+            graph.attach();
+            for table in &tables {
+                let name = table.name();
+                if let Some((_, samples)) = contents.iter().find(|(old, _)| *old == name) {
+                    let len = samples.len().min(table.len());
+                    // the range is within both tables
+                    let _ = table.write_from(0, &samples[..len]);
+                }
+            }
+        });
+
+        // the old graph is deleted once it's detached
+        drop(mem::replace(&mut self.graph, graph));
+        Ok(())
+    }
+
+    /// Poll the patch file on a background thread every `interval`, reloading it when it
+    /// changes. `on_reload` is called on that thread with the result of every reload.
+    ///
+    /// The host is moved to the thread until [PatchWatcher::stop] returns it. [Object]s taken
+    /// from [PatchHost::graph] before must not be used in the meantime, as the graph may be
+    /// replaced at any time.
+    ///
+    /// [Object]: crate::object::Object
+    pub fn watch<F>(self, interval: Duration, mut on_reload: F) -> PatchWatcher<D, L>
+    where
+        F: FnMut(Result<(), Error>) + Send + 'static,
+        D: 'static,
+        L: 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            let host = Exclusive(self);
+            thread::spawn(move || {
+                let Exclusive(mut host) = host;
+                while !stop.load(Ordering::Relaxed) {
+                    match host.poll() {
+                        Ok(true) => on_reload(Ok(())),
+                        Ok(false) => (),
+                        Err(err) => on_reload(Err(err)),
+                    }
+                    thread::sleep(interval);
+                }
+                Exclusive(host)
+            })
+        };
+
+        PatchWatcher {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// A [PatchHost] moved between threads by value.
+///
+/// The host isn't `Send` because its [Graph] handle holds raw pointers and a `RefCell`. Moving
+/// the host by value is sound nonetheless: no borrow of the graph or its registry can outlive the
+/// move, so they are only ever accessed by the thread which owns the host, and the ZenGarden
+/// graph itself is swapped with the context locked, like the audio thread processes it. The
+/// context is shared with the audio thread anyway, and its state is behind locks.
+#[derive(Debug)]
+struct Exclusive<D: Dispatcher, L: AudioLoop>(PatchHost<D, L>);
+
+unsafe impl<D: Dispatcher, L: AudioLoop> Send for Exclusive<D, L> {}
+
+/// Background thread started by [PatchHost::watch]. The thread is stopped on drop, and a panic of
+/// the thread is then printed to stderr.
+#[derive(Debug)]
+pub struct PatchWatcher<D: Dispatcher, L: AudioLoop> {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Exclusive<D, L>>>,
+}
+
+impl<D: Dispatcher, L: AudioLoop> PatchWatcher<D, L> {
+    /// Stop watching and get the host back. A panic of the thread is resumed.
+    pub fn stop(mut self) -> PatchHost<D, L> {
+        match self.join() {
+            Some(Ok(host)) => host,
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => unreachable!("Patch watcher thread joined twice"),
+        }
+    }
+
+    fn join(&mut self) -> Option<thread::Result<PatchHost<D, L>>> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread
+            .take()
+            .map(|thread| thread.join().map(|Exclusive(host)| host))
+    }
+}
+
+impl<D: Dispatcher, L: AudioLoop> Drop for PatchWatcher<D, L> {
+    fn drop(&mut self) {
+        // a panic can't be resumed while dropping, as the thread may already be unwinding
+        if let Some(Err(payload)) = self.join() {
+            let reason = payload
+                .downcast_ref::<&str>()
+                .map(|reason| reason.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown reason".to_string());
+            eprintln!("Patch watcher thread panicked: {}", reason);
+        }
+    }
+}
+
+fn load<D: Dispatcher, L: AudioLoop>(
+    context: &Context<D, L>,
+    path: &Path,
+) -> Result<Graph<'static>, Error> {
+    let file = path.to_str().ok_or_else(|| Error::Loading {
+        path: path.to_path_buf(),
+        reason: Anyhow::msg("The path isn't valid UTF-8"),
+    })?;
+
+    Graph::from_file(context.clone(), file).map_err(|reason| Error::Loading {
+        path: path.to_path_buf(),
+        reason,
+    })
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// [PatchHost] errors.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The patch file can't be loaded.
+    #[error("Can't load {path:?}: {reason}")]
+    Loading {
+        /// Patch file path.
+        path: PathBuf,
+        /// The reason.
+        reason: Anyhow,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::sync::mpsc;
+
+    use crate::context::{AudioLoopF32, Config};
+
+    use super::*;
+
+    const PATCH: &str = "#N canvas 0 0 450 300 12;\n\
+                         #X obj 10 10 table host-test-table 4;\n";
+
+    #[test]
+    fn reload() {
+        let path = env::temp_dir().join("miller-host-test.pd");
+        fs::write(&path, PATCH).unwrap();
+
+        let context = Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
+        let mut host = PatchHost::new(context.clone(), &path).unwrap();
+        assert!(!host.poll().unwrap());
+        assert_eq!(host.graph().objects().len(), 1);
+
+        let table = context.table("host-test-table").unwrap();
+        table.write_from(0, &[1.0, 2.0, 3.0, 4.0]).unwrap();
+
+        fs::write(&path, format!("{}#X obj 10 40 osc~ 440;\n", PATCH)).unwrap();
+        host.reload().unwrap();
+        assert_eq!(host.graph().objects().len(), 2);
+
+        let tables = host.graph().tables();
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables[0].to_vec(), vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(
+            context.table("host-test-table").unwrap().to_vec(),
+            vec![1.0, 2.0, 3.0, 4.0]
+        );
+
+        fs::write(&path, "").unwrap();
+        assert!(host.reload().is_err());
+        assert_eq!(host.graph().objects().len(), 2);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn watch() {
+        let path = env::temp_dir().join("miller-host-watch-test.pd");
+        fs::write(&path, PATCH).unwrap();
+
+        let context = Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
+        let host = PatchHost::new(context, &path).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let watcher = host.watch(Duration::from_millis(10), move |result| {
+            let _ = sender.send(result.is_ok());
+        });
+
+        // make sure the modification time changes even on file systems with a coarse resolution
+        thread::sleep(Duration::from_millis(1100));
+        fs::write(&path, format!("{}#X obj 10 40 osc~ 440;\n", PATCH)).unwrap();
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(true));
+
+        let host = watcher.stop();
+        assert_eq!(host.graph().objects().len(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestDispatcher;
+
+    impl Dispatcher for TestDispatcher {
+        type UserData = ();
+    }
+}
@@ -0,0 +1,91 @@
+//! Netlist (Pd file contents) preprocessing.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+const STATEMENT_SEPARATOR: &str = ";\n";
+const DECLARE: &str = "#X declare";
+
+/// Prepare a netlist for parsing by ZenGarden, so that abstractions are searched in the right
+/// places.
+///
+/// ZenGarden looks for abstractions in the directories declared with `[declare -path]`. Relative
+/// directories are resolved here against `dir` (the patch directory) or, if there's no patch
+/// directory, against the current directory. The patch directory itself, followed by the
+/// `search_paths`, is declared at the top of the root canvas, so the lookup order is: the patch
+/// directory, the search paths, the paths declared by the patch.
+pub(crate) fn resolve_paths(netlist: &str, dir: Option<&Path>, search_paths: &[PathBuf]) -> String {
+    let base = dir
+        .map(Path::to_path_buf)
+        .or_else(|| env::current_dir().ok())
+        .unwrap_or_default();
+    let cwd = env::current_dir().unwrap_or_default();
+
+    let declared: Vec<String> = dir
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(search_paths.iter().map(|path| cwd.join(path)))
+        .map(|path| format!("{} -path {}", DECLARE, path.display()))
+        .collect();
+
+    let mut statements = netlist.split(STATEMENT_SEPARATOR);
+    let mut result: Vec<String> = statements.next().map(String::from).into_iter().collect();
+    result.extend(declared);
+    result.extend(statements.map(|statement| {
+        if statement.trim_start().starts_with(DECLARE) {
+            resolve_declare(statement, &base)
+        } else {
+            statement.to_string()
+        }
+    }));
+
+    result.join(STATEMENT_SEPARATOR)
+}
+
+fn resolve_declare(statement: &str, base: &Path) -> String {
+    let mut tokens: Vec<String> = statement.split_whitespace().map(String::from).collect();
+
+    for n in 1..tokens.len() {
+        if tokens[n - 1] == "-path" && Path::new(&tokens[n]).is_relative() {
+            tokens[n] = base.join(&tokens[n]).display().to_string();
+        }
+    }
+
+    tokens.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn declares_dir_and_search_paths() {
+        let netlist = "#N canvas 0 0 450 300 12;\n#X obj 10 10 osc~;\n";
+        let result = resolve_paths(
+            netlist,
+            Some(Path::new("/patches")),
+            &[PathBuf::from("/lib")],
+        );
+
+        assert_eq!(
+            result,
+            "#N canvas 0 0 450 300 12;\n\
+             #X declare -path /patches;\n\
+             #X declare -path /lib;\n\
+             #X obj 10 10 osc~;\n"
+        );
+    }
+
+    #[test]
+    fn resolves_relative_declare() {
+        let netlist = "#N canvas 0 0 450 300 12;\n#X declare -path abs -path /abs;\n";
+        let result = resolve_paths(netlist, Some(Path::new("/patches")), &[]);
+
+        assert_eq!(
+            result,
+            "#N canvas 0 0 450 300 12;\n\
+             #X declare -path /patches;\n\
+             #X declare -path /patches/abs -path /abs;\n"
+        );
+    }
+}
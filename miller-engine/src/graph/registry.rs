@@ -0,0 +1,149 @@
+//! Generation-checked object ids.
+
+use serde::{Deserialize, Serialize};
+use zengarden_raw::zg_object_get_serial;
+
+use crate::object::Object;
+
+/// Identifier of an object in a [Graph](super::Graph), returned by
+/// [Graph::object_id](super::Graph::object_id).
+///
+/// Unlike [Object], an id can't dangle: once the object is removed, however that happens,
+/// looking the id up returns `None`, even if a new object is created at the same address. Ids are
+/// stable for the lifetime of the graph, are kept when the object is retyped with
+/// [Graph::retype_object](super::Graph::retype_object), and can be serialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ObjectId {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+    object: Option<Object>,
+    // tells the object apart from an earlier one at the same address
+    serial: u64,
+    generation: u32,
+}
+
+impl Slot {
+    fn set(&mut self, object: Object) {
+        self.object = Some(object);
+        self.serial = serial(object);
+    }
+
+    fn clear(&mut self) {
+        self.object = None;
+        self.generation = self.generation.wrapping_add(1);
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Registry {
+    slots: Vec<Slot>,
+}
+
+impl Registry {
+    /// Returns the id of the object, assigning a new one if it doesn't have it yet. `objects`
+    /// are the objects currently in the graph.
+    pub(super) fn id(&mut self, object: Object, objects: &[Object]) -> ObjectId {
+        self.sync(objects);
+        if let Some(index) = self.slots.iter().position(|s| s.object == Some(object)) {
+            return ObjectId {
+                index: index as u32,
+                generation: self.slots[index].generation,
+            };
+        }
+
+        let index = match self.slots.iter().position(|s| s.object.is_none()) {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot::default());
+                self.slots.len() - 1
+            }
+        };
+        self.slots[index].set(object);
+        ObjectId {
+            index: index as u32,
+            generation: self.slots[index].generation,
+        }
+    }
+
+    /// Returns the object with the id, if it's still in the graph.
+    pub(super) fn get(&mut self, id: ObjectId, objects: &[Object]) -> Option<Object> {
+        self.sync(objects);
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.object)
+    }
+
+    /// Invalidate the id of an object which is about to be removed.
+    pub(super) fn invalidate(&mut self, object: Object) {
+        for slot in &mut self.slots {
+            if slot.object == Some(object) {
+                slot.clear();
+            }
+        }
+    }
+
+    /// Move the id of an object to its replacement.
+    pub(super) fn replace(&mut self, old: Object, new: Object) {
+        for slot in &mut self.slots {
+            if slot.object == Some(old) {
+                slot.set(new);
+            }
+        }
+    }
+
+    /// Invalidate the ids of the objects which were removed from the graph behind the registry's
+    /// back, e.g. with [Object::remove] or through another [Graph](super::Graph) handle. An
+    /// object at the same address as a removed one is told apart by its serial.
+    fn sync(&mut self, objects: &[Object]) {
+        for slot in &mut self.slots {
+            if let Some(object) = slot.object {
+                // the serial is only read from objects which are still alive
+                if !objects.contains(&object) || serial(object) != slot.serial {
+                    slot.clear();
+                }
+            }
+        }
+    }
+}
+
+fn serial(object: Object) -> u64 {
+    unsafe { zg_object_get_serial(object.0) }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::{AudioLoopF32, Config, Context, Dispatcher};
+    use crate::graph::Graph;
+
+    use super::*;
+
+    #[test]
+    fn address_reuse() {
+        let context = Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
+        let graph = Graph::new_empty(&context);
+        let float = graph.add_object("float", None);
+        let objects = [float];
+        let mut registry = Registry::default();
+        let id = registry.id(float, &objects);
+        assert_eq!(registry.get(id, &objects), Some(float));
+
+        // Another object at the same address has another serial.
+        registry.slots[0].serial += 1;
+        assert_eq!(registry.get(id, &objects), None);
+        let new_id = registry.id(float, &objects);
+        assert_ne!(new_id, id);
+        assert_eq!(registry.get(new_id, &objects), Some(float));
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestDispatcher;
+
+    impl Dispatcher for TestDispatcher {
+        type UserData = ();
+    }
+}
@@ -0,0 +1,442 @@
+//! Batched graph edits, applied in one step.
+
+use thiserror::Error;
+
+use super::{check_connection_among, discard_object, dsp_path, ConnectError, Graph};
+use crate::object::{ConnectionPair, Object, ObjectPosition, OutletType};
+
+/// Object referenced by a [GraphTransaction] operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionObject {
+    /// Object which is already in the graph.
+    Existing(Object),
+    /// Object added by the transaction, indexed in the order of [GraphTransaction::add_object]
+    /// calls.
+    New(usize),
+}
+
+impl From<Object> for TransactionObject {
+    fn from(object: Object) -> Self {
+        Self::Existing(object)
+    }
+}
+
+/// Indicates the object and the outlet/inlet index of a connection in a [GraphTransaction].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionPair {
+    /// Object to/from which connection comes.
+    pub object: TransactionObject,
+    /// Index of the inlet/outlet.
+    pub index: usize,
+}
+
+impl From<(TransactionObject, usize)> for TransactionPair {
+    fn from((object, index): (TransactionObject, usize)) -> Self {
+        Self { object, index }
+    }
+}
+
+impl From<(Object, usize)> for TransactionPair {
+    fn from((object, index): (Object, usize)) -> Self {
+        Self {
+            object: object.into(),
+            index,
+        }
+    }
+}
+
+impl From<ConnectionPair> for TransactionPair {
+    fn from(pair: ConnectionPair) -> Self {
+        (pair.object, pair.index).into()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Operation {
+    AddObject(String, Option<ObjectPosition>),
+    RemoveObject(TransactionObject),
+    AddConnection(TransactionPair, TransactionPair),
+    RemoveConnection(TransactionPair, TransactionPair),
+}
+
+/// A batch of object additions, removals and connection changes, created with
+/// [Graph::transaction].
+///
+/// Nothing is changed until [GraphTransaction::commit] is called. The commit instantiates the new
+/// objects, which is the expensive part, without adding them to the graph, so neither the audio
+/// thread nor the graph sees them yet. The whole batch is then validated, and the graph is left
+/// untouched if any operation is invalid. Finally, the objects are added and the removals and
+/// connection changes are applied with the context locked, so the audio thread sees them all at
+/// once at a block boundary, and the signal graph of an attached graph is reordered only once.
+///
+/// Operations are applied in the order they were added, and connections are validated like
+/// [Graph::add_connection] against the state of the graph at that point of the batch.
+#[derive(Debug)]
+pub struct GraphTransaction<'g, 'a> {
+    graph: &'g Graph<'a>,
+    operations: Vec<Operation>,
+    added: usize,
+}
+
+impl<'g, 'a> GraphTransaction<'g, 'a> {
+    pub(super) fn new(graph: &'g Graph<'a>) -> Self {
+        Self {
+            graph,
+            operations: Vec::new(),
+            added: 0,
+        }
+    }
+
+    /// Add a new object, e.g. **"osc~ 440"**, to the graph. The returned reference may be used in
+    /// the following operations of this transaction.
+    pub fn add_object(
+        &mut self,
+        object: &str,
+        position: Option<ObjectPosition>,
+    ) -> TransactionObject {
+        self.operations
+            .push(Operation::AddObject(object.to_string(), position));
+        self.added += 1;
+        TransactionObject::New(self.added - 1)
+    }
+
+    /// Remove an object and all of its connections from the graph.
+    pub fn remove_object(&mut self, object: impl Into<TransactionObject>) {
+        self.operations.push(Operation::RemoveObject(object.into()));
+    }
+
+    /// Add a connection between two objects.
+    pub fn add_connection(
+        &mut self,
+        from: impl Into<TransactionPair>,
+        to: impl Into<TransactionPair>,
+    ) {
+        self.operations
+            .push(Operation::AddConnection(from.into(), to.into()));
+    }
+
+    /// Remove a connection between two objects.
+    pub fn remove_connection(
+        &mut self,
+        from: impl Into<TransactionPair>,
+        to: impl Into<TransactionPair>,
+    ) {
+        self.operations
+            .push(Operation::RemoveConnection(from.into(), to.into()));
+    }
+
+    /// Returns `true` if the transaction has no operations.
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Validate and apply the transaction. Returns the added objects in the order of
+    /// [GraphTransaction::add_object] calls.
+    pub fn commit(self) -> Result<Vec<Object>, Error> {
+        self.validate_references()?;
+        let objects = self.instantiate()?;
+        if let Err(err) = self.validate_connections(&objects) {
+            objects.into_iter().for_each(discard_object);
+            return Err(err);
+        }
+
+        self.graph.atomically(|| {
+            let mut added = objects.iter();
+            for operation in &self.operations {
+                match operation {
+                    Operation::AddObject(_, position) => {
+                        if let Some(&object) = added.next() {
+                            let position = position.unwrap_or_else(|| (0.0, 0.0).into());
+                            self.graph.insert_object(object, position);
+                        }
+                    }
+                    Operation::RemoveObject(object) => {
+                        self.graph.remove_unchecked(resolve(*object, &objects))
+                    }
+                    Operation::AddConnection(from, to) => self.graph.connect_unchecked(
+                        resolve_pair(*from, &objects),
+                        resolve_pair(*to, &objects),
+                    ),
+                    Operation::RemoveConnection(from, to) => self.graph.remove_connection(
+                        resolve_pair(*from, &objects),
+                        resolve_pair(*to, &objects),
+                    ),
+                }
+            }
+        });
+
+        Ok(objects)
+    }
+
+    /// Check that the existing objects belong to the graph and that no operation refers to a
+    /// removed object.
+    fn validate_references(&self) -> Result<(), Error> {
+        let existing = self.graph.objects();
+        let mut added = 0;
+        let mut removed = Vec::new();
+        let check = |object: &TransactionObject, added: usize, removed: &[TransactionObject]| {
+            match object {
+                TransactionObject::Existing(object) if !existing.contains(object) => {
+                    return Err(Error::NotInGraph(*object))
+                }
+                TransactionObject::New(index) if *index >= added => {
+                    return Err(Error::UnknownObject(*index))
+                }
+                _ => {}
+            }
+            if removed.contains(object) {
+                return Err(Error::Removed(*object));
+            }
+            Ok(())
+        };
+
+        for operation in &self.operations {
+            match operation {
+                Operation::AddObject(..) => added += 1,
+                Operation::RemoveObject(object) => {
+                    check(object, added, &removed)?;
+                    removed.push(*object);
+                }
+                Operation::AddConnection(from, to) | Operation::RemoveConnection(from, to) => {
+                    check(&from.object, added, &removed)?;
+                    check(&to.object, added, &removed)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create the new objects without adding them to the graph. If any of them can't be
+    /// instantiated, the already created ones are deleted.
+    fn instantiate(&self) -> Result<Vec<Object>, Error> {
+        let mut objects = Vec::with_capacity(self.added);
+        for operation in &self.operations {
+            if let Operation::AddObject(text, _) = operation {
+                match self.graph.create_object(text) {
+                    Some(object) => objects.push(object),
+                    None => {
+                        objects.into_iter().for_each(discard_object);
+                        return Err(Error::Instantiating(text.clone()));
+                    }
+                }
+            }
+        }
+
+        Ok(objects)
+    }
+
+    /// Check the connections against the connections of the graph, updated by the operations in
+    /// the order they were added.
+    fn validate_connections(&self, objects: &[Object]) -> Result<(), Error> {
+        let mut known = self.graph.objects();
+        known.extend_from_slice(objects);
+        let mut connections = self.graph.connections();
+        for operation in &self.operations {
+            match operation {
+                Operation::AddObject(..) => {}
+                Operation::RemoveObject(object) => {
+                    let object = resolve(*object, objects);
+                    connections.retain(|(from, to)| from.object != object && to.object != object);
+                }
+                Operation::AddConnection(from, to) => {
+                    let (from, to) = (resolve_pair(*from, objects), resolve_pair(*to, objects));
+                    check_connection_among(&known, from, to)?;
+                    if connections.contains(&(from, to)) {
+                        return Err(ConnectError::Duplicate.into());
+                    }
+                    if from.object.outlet_type(from.index) == OutletType::Dsp
+                        && dsp_path(to.object, from.object, |object| {
+                            connections
+                                .iter()
+                                .filter(|(from, _)| {
+                                    from.object == object
+                                        && object.outlet_type(from.index) == OutletType::Dsp
+                                })
+                                .map(|(_, to)| to.object)
+                                .collect()
+                        })
+                    {
+                        return Err(ConnectError::DspCycle.into());
+                    }
+                    connections.push((from, to));
+                }
+                Operation::RemoveConnection(from, to) => {
+                    let (from, to) = (resolve_pair(*from, objects), resolve_pair(*to, objects));
+                    connections.retain(|connection| *connection != (from, to));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn resolve(object: TransactionObject, objects: &[Object]) -> Object {
+    match object {
+        TransactionObject::Existing(object) => object,
+        TransactionObject::New(index) => objects[index],
+    }
+}
+
+fn resolve_pair(pair: TransactionPair, objects: &[Object]) -> ConnectionPair {
+    (resolve(pair.object, objects), pair.index).into()
+}
+
+/// [GraphTransaction] errors. The graph is left unchanged when the commit fails.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum Error {
+    /// The object doesn't belong to the graph.
+    #[error("Object {0:?} doesn't belong to the graph")]
+    NotInGraph(Object),
+    /// The reference to a new object doesn't match any object added earlier in the transaction.
+    #[error("No object with index {0} is added before it's referenced")]
+    UnknownObject(usize),
+    /// The object is referenced after being removed in the same transaction.
+    #[error("Object {0:?} is referenced after being removed")]
+    Removed(TransactionObject),
+    /// The object can't be instantiated.
+    #[error("Can't instantiate object '{0}'")]
+    Instantiating(String),
+    /// The connection is invalid.
+    #[error("Invalid connection: {0}")]
+    Connect(#[from] ConnectError),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::{AudioLoopF32, Config, Context, Dispatcher};
+    use crate::message::{Message, MessageElement};
+
+    use super::*;
+
+    #[test]
+    fn commit() {
+        let context = init_test_context();
+        let receiver_name = "transaction-test-r";
+        context.register_receiver(receiver_name);
+        let graph = Graph::new_empty(&context);
+        let old = graph.add_object("float", None);
+        graph.attach();
+
+        let mut transaction = graph.transaction();
+        let receiver = transaction.add_object("receive outer-receive", None);
+        let sender = transaction.add_object(&format!("send {}", receiver_name), None);
+        transaction.add_connection((receiver, 0), (sender, 0));
+        transaction.remove_object(old);
+        let objects = transaction.commit().unwrap();
+
+        assert_eq!(objects.len(), 2);
+        assert_eq!(graph.objects(), objects);
+        assert_eq!(
+            objects[1].connections_at_inlet(0),
+            vec![(objects[0], 0).into()]
+        );
+
+        send_message_and_process_block(&context);
+        assert_eq!(*context.user_data(), 42);
+    }
+
+    #[test]
+    fn dsp_order() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        graph.attach();
+
+        let mut transaction = graph.transaction();
+        let osc = transaction.add_object("osc~ 440", None);
+        let dac = transaction.add_object("dac~", None);
+        transaction.add_connection((osc, 0), (dac, 0));
+        let objects = transaction.commit().unwrap();
+
+        // The order is recomputed without detaching the graph.
+        assert!(graph.is_attached());
+        assert_eq!(graph.dsp_order(), objects);
+    }
+
+    #[test]
+    fn invalid() {
+        let context = init_test_context();
+        let graph = Graph::new_empty(&context);
+        let osc = graph.add_object("osc~ 440", None);
+        graph.attach();
+
+        let mut transaction = graph.transaction();
+        let dac = transaction.add_object("dac~", None);
+        transaction.add_connection((osc, 0), (dac, 0));
+        transaction.add_connection((dac, 0), (osc, 0));
+        assert_eq!(
+            transaction.commit(),
+            Err(Error::Connect(ConnectError::OutletOutOfRange {
+                object: "dac~".to_string(),
+                outlet: 0,
+                num_outlets: 0
+            }))
+        );
+        assert_eq!(graph.objects(), vec![osc]);
+
+        let mut transaction = graph.transaction();
+        transaction.remove_object(osc);
+        transaction.add_connection((osc, 0), (osc, 0));
+        assert_eq!(
+            transaction.commit(),
+            Err(Error::Removed(TransactionObject::Existing(osc)))
+        );
+
+        let mut transaction = graph.transaction();
+        let lop = transaction.add_object("lop~ 1000", None);
+        transaction.add_connection((osc, 0), (lop, 0));
+        transaction.add_connection((lop, 0), (osc, 0));
+        assert_eq!(
+            transaction.commit(),
+            Err(Error::Connect(ConnectError::DspCycle))
+        );
+        assert_eq!(graph.objects(), vec![osc]);
+
+        let mut transaction = graph.transaction();
+        transaction.add_connection((TransactionObject::New(0), 0), (osc, 0));
+        assert_eq!(transaction.commit(), Err(Error::UnknownObject(0)));
+
+        let other = Graph::new_empty(&context);
+        let foreign = other.add_object("osc~", None);
+        let mut transaction = graph.transaction();
+        transaction.remove_object(foreign);
+        assert_eq!(transaction.commit(), Err(Error::NotInGraph(foreign)));
+
+        let mut transaction = graph.transaction();
+        transaction.add_object("no-such-object", None);
+        assert_eq!(
+            transaction.commit(),
+            Err(Error::Instantiating("no-such-object".to_string()))
+        );
+        assert_eq!(graph.objects(), vec![osc]);
+    }
+
+    fn init_test_context() -> Context<TestDispatcher, AudioLoopF32> {
+        Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap()
+    }
+
+    fn send_message_and_process_block(context: &Context<TestDispatcher, AudioLoopF32>) {
+        context.send_message(
+            "outer-receive",
+            Message::builder()
+                .with_element(MessageElement::Bang)
+                .build(),
+        );
+
+        for _ in 0..context.config().blocksize + 1 {
+            context.next_frame(&[0.0, 0.0]).unwrap();
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestDispatcher;
+
+    impl Dispatcher for TestDispatcher {
+        type UserData = u64;
+
+        fn receiver_message(_name: String, _message: Option<Message>, data: &mut Self::UserData) {
+            *data = 42;
+        }
+    }
+}
@@ -36,6 +36,10 @@
 //! without causing any audio dropouts. Graph attachement generally involves registering global
 //! senders and receivers and ensuring that existing objects are aware of the new ones. Similarly,
 //! a graph can be unattached from a context, leaving it in memory yet inert.
+//!
+//! Edits to a graph which is already attached can be batched in a [graph::GraphTransaction]. The
+//! new objects are instantiated first, and the rest of the batch is applied at once at a block
+//! boundary, reordering the signal graph only once.
 
 #![deny(
     nonstandard_style,
@@ -55,6 +59,10 @@
 
 pub mod context;
 pub mod graph;
+pub mod lint;
 pub mod message;
 pub mod object;
+pub mod plugin;
+pub mod project;
+pub mod session;
 pub mod table;
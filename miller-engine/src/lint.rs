@@ -0,0 +1,562 @@
+//! Compatibility linter for Pd vanilla patches.
+//!
+//! The engine supports only a subset of Pd vanilla. [Linter] walks a patch file, recursing into
+//! subpatches and abstractions, and reports what won't work as it does in Pd:
+//!
+//! - objects which can't be instantiated (they are skipped on load, which also breaks the
+//!   connections to the objects created after them),
+//! - GUI objects, which are loaded as plain message objects and can't be interacted with,
+//! - message box syntax which the engine's message boxes don't support,
+//! - `dac~` channels without any incoming connection.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::context::{AudioLoop, Context, Dispatcher};
+
+/// GUI objects which are loaded as plain message objects.
+const INERT_GUI: &[&str] = &["bng", "tgl", "nbx", "hsl", "vsl"];
+
+/// Methods which Pd's message boxes handle themselves, and the engine's message boxes just
+/// output.
+const MESSAGE_BOX_METHODS: &[&str] = &[
+    "set",
+    "add",
+    "add2",
+    "addcomma",
+    "addsemi",
+    "adddollar",
+    "adddollsym",
+];
+
+/// Lint a patch file with the object classes and search paths of the context.
+pub fn lint<D: Dispatcher, L: AudioLoop, P: AsRef<Path>>(
+    context: &Context<D, L>,
+    path: P,
+) -> Result<Vec<Issue>, Error> {
+    Linter::new(context).lint_file(path)
+}
+
+/// Patch linter.
+#[derive(Debug, Clone)]
+pub struct Linter {
+    classes: HashSet<String>,
+    search_paths: Vec<PathBuf>,
+}
+
+impl Linter {
+    /// Initialize a linter which accepts the object classes the context can instantiate
+    /// (including registered externals) and looks abstractions up in the context's search paths.
+    pub fn new<D: Dispatcher, L: AudioLoop>(context: &Context<D, L>) -> Self {
+        Self {
+            classes: context
+                .object_classes()
+                .into_iter()
+                .map(|class| class.name)
+                .collect(),
+            search_paths: context.config().search_paths.clone(),
+        }
+    }
+
+    /// Lint a patch file and the abstractions it uses. Each abstraction file is linted once.
+    /// Issues are ordered by file and line.
+    pub fn lint_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Issue>, Error> {
+        let mut visited = HashSet::new();
+        let mut issues = Vec::new();
+        self.lint_recursive(path.as_ref(), &mut visited, &mut issues)?;
+        Ok(issues)
+    }
+
+    fn lint_recursive(
+        &self,
+        path: &Path,
+        visited: &mut HashSet<PathBuf>,
+        issues: &mut Vec<Issue>,
+    ) -> Result<(), Error> {
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if !visited.insert(canonical) {
+            return Ok(());
+        }
+
+        let contents = fs::read_to_string(path).map_err(|source| Error::Reading {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let patch = Patch::parse(&contents, dir);
+
+        let mut abstractions = Vec::new();
+        let mut file_issues = Vec::new();
+        for (line, kind) in self.check(&patch, dir, &mut abstractions) {
+            file_issues.push(Issue {
+                location: Location {
+                    file: path.to_path_buf(),
+                    line,
+                },
+                kind,
+            });
+        }
+        file_issues.sort_by_key(|issue| issue.location.line);
+        issues.extend(file_issues);
+
+        for abstraction in abstractions {
+            self.lint_recursive(&abstraction, visited, issues)?;
+        }
+
+        Ok(())
+    }
+
+    fn check(
+        &self,
+        patch: &Patch,
+        dir: &Path,
+        abstractions: &mut Vec<PathBuf>,
+    ) -> Vec<(usize, IssueKind)> {
+        let mut issues = Vec::new();
+
+        for canvas in &patch.canvases {
+            for (index, entry) in canvas.entries.iter().enumerate() {
+                match &entry.kind {
+                    EntryKind::Object(name) => {
+                        if let Some(kind) = self.check_object(name, patch, dir, abstractions) {
+                            issues.push((entry.line, kind));
+                        }
+                    }
+                    EntryKind::Unsupported(name) => {
+                        issues.push((entry.line, IssueKind::UnsupportedObject(name.clone())))
+                    }
+                    EntryKind::Atom(name) => {
+                        issues.push((entry.line, IssueKind::InertGui(name.clone())))
+                    }
+                    EntryKind::Dac(channels) => {
+                        for (inlet, channel) in channels.iter().enumerate() {
+                            if !canvas.is_connected(index, inlet) {
+                                issues
+                                    .push((entry.line, IssueKind::UnconnectedDacChannel(*channel)));
+                            }
+                        }
+                    }
+                    EntryKind::MessageBox(text) => {
+                        issues.extend(
+                            check_message_box(text, index, canvas)
+                                .into_iter()
+                                .map(|syntax| (entry.line, IssueKind::UnsupportedMessage(syntax))),
+                        );
+                    }
+                    EntryKind::Other => (),
+                }
+            }
+        }
+
+        issues
+    }
+
+    fn check_object(
+        &self,
+        name: &str,
+        patch: &Patch,
+        dir: &Path,
+        abstractions: &mut Vec<PathBuf>,
+    ) -> Option<IssueKind> {
+        // dollar arguments are resolved only when the patch is loaded
+        if name.contains('$') || name.parse::<f32>().is_ok() {
+            return None;
+        }
+
+        if INERT_GUI.contains(&name) {
+            return Some(IssueKind::InertGui(name.to_string()));
+        }
+
+        if self.classes.contains(name) {
+            return None;
+        }
+
+        let file_name = format!("{}.pd", name);
+        let found = std::iter::once(dir.to_path_buf())
+            .chain(self.search_paths.iter().cloned())
+            .chain(patch.declared_paths.iter().cloned())
+            .map(|path| path.join(&file_name))
+            .find(|path| path.is_file());
+
+        match found {
+            Some(path) => {
+                abstractions.push(path);
+                None
+            }
+            None => Some(IssueKind::UnsupportedObject(name.to_string())),
+        }
+    }
+}
+
+fn check_message_box(text: &str, index: usize, canvas: &Canvas) -> Vec<MessageSyntax> {
+    let mut result = Vec::new();
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+
+    if tokens.iter().any(|token| token.contains("\\$0")) {
+        result.push(MessageSyntax::DollarZero);
+    }
+
+    if tokens.iter().any(|token| {
+        token.match_indices("\\$").any(|(n, _)| {
+            let digits = token[n + 2..]
+                .bytes()
+                .take_while(u8::is_ascii_digit)
+                .count();
+            digits > 1
+        })
+    }) {
+        result.push(MessageSyntax::MultiDigitDollar);
+    }
+
+    // only the local messages, i.e. the ones before the first semicolon, go to the outlet
+    let local = text.split("\\;").next().unwrap_or_default();
+    let methods: Vec<String> = local
+        .split("\\,")
+        .filter_map(|message| message.split_whitespace().next())
+        .filter(|selector| MESSAGE_BOX_METHODS.contains(selector))
+        .map(String::from)
+        .collect();
+    if !methods.is_empty() && canvas.sends_to_message_box(index) {
+        result.extend(methods.into_iter().map(MessageSyntax::MessageBoxMethod));
+    }
+
+    result
+}
+
+/// A single lint finding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// Where the offending statement starts.
+    pub location: Location,
+    /// What's wrong.
+    pub kind: IssueKind,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.kind)
+    }
+}
+
+/// Location of an [Issue].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// The patch or abstraction file.
+    pub file: PathBuf,
+    /// 1-based line number.
+    pub line: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file.display(), self.line)
+    }
+}
+
+/// Kind of an [Issue].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IssueKind {
+    /// The object class isn't supported and there's no abstraction with the name.
+    UnsupportedObject(String),
+    /// The GUI object is loaded as a plain message object and can't be interacted with.
+    InertGui(String),
+    /// The message box uses syntax which isn't supported.
+    UnsupportedMessage(MessageSyntax),
+    /// Nothing is connected to the `dac~` channel.
+    UnconnectedDacChannel(usize),
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedObject(name) => write!(f, "unsupported object [{}]", name),
+            Self::InertGui(name) => write!(f, "GUI object [{}] will be inert", name),
+            Self::UnsupportedMessage(syntax) => {
+                write!(f, "unsupported message box syntax: {}", syntax)
+            }
+            Self::UnconnectedDacChannel(channel) => {
+                write!(f, "[dac~] channel {} isn't connected", channel)
+            }
+        }
+    }
+}
+
+/// Message box syntax which isn't supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageSyntax {
+    /// `$0` is replaced with 0 rather than the patch's `$0`.
+    DollarZero,
+    /// Only single-digit dollar arguments (`$1`-`$9`) are supported.
+    MultiDigitDollar,
+    /// A method of the message box (e.g. **"set"**) sent to another message box, which just
+    /// outputs its contents instead.
+    MessageBoxMethod(String),
+}
+
+impl fmt::Display for MessageSyntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DollarZero => write!(f, "$0 isn't supported"),
+            Self::MultiDigitDollar => write!(f, "dollar arguments above $9 aren't supported"),
+            Self::MessageBoxMethod(method) => {
+                write!(f, "message boxes don't handle the \"{}\" method", method)
+            }
+        }
+    }
+}
+
+/// [Linter] errors.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The patch or abstraction file can't be read.
+    #[error("Can't read {path:?}: {source}")]
+    Reading {
+        /// File path.
+        path: PathBuf,
+        /// Underlying error.
+        source: io::Error,
+    },
+}
+
+/// Patch file contents, with every canvas flattened out.
+#[derive(Debug, Default)]
+struct Patch {
+    canvases: Vec<Canvas>,
+    declared_paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+struct Canvas {
+    entries: Vec<Entry>,
+    connections: Vec<(usize, usize, usize, usize)>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    line: usize,
+    kind: EntryKind,
+}
+
+#[derive(Debug)]
+enum EntryKind {
+    Object(String),
+    Unsupported(String),
+    Atom(String),
+    Dac(Vec<usize>),
+    MessageBox(String),
+    Other,
+}
+
+impl Patch {
+    fn parse(contents: &str, dir: &Path) -> Self {
+        let mut patch = Self::default();
+        let mut stack: Vec<Canvas> = Vec::new();
+
+        for (line, statement) in statements(contents) {
+            let tokens: Vec<&str> = statement.split_whitespace().collect();
+            let kind = match (tokens.first().copied(), tokens.get(1).copied()) {
+                (Some("#N"), Some("canvas")) => {
+                    stack.push(Canvas::default());
+                    continue;
+                }
+                (Some("#X"), Some("restore")) => {
+                    if let Some(canvas) = stack.pop() {
+                        patch.canvases.push(canvas);
+                    }
+                    EntryKind::Other
+                }
+                (Some("#X"), Some("obj")) => match tokens.get(4).copied() {
+                    Some("dac~") => {
+                        let channels: Vec<usize> = tokens[5..]
+                            .iter()
+                            .filter_map(|arg| arg.parse().ok())
+                            .collect();
+                        if channels.is_empty() {
+                            EntryKind::Dac(vec![1, 2])
+                        } else {
+                            EntryKind::Dac(channels)
+                        }
+                    }
+                    Some(name) => EntryKind::Object(name.to_string()),
+                    None => EntryKind::Other,
+                },
+                (Some("#X"), Some("msg")) => {
+                    EntryKind::MessageBox(tokens[4.min(tokens.len())..].join(" "))
+                }
+                (Some("#X"), Some(atom @ "floatatom"))
+                | (Some("#X"), Some(atom @ "symbolatom")) => EntryKind::Atom(atom.to_string()),
+                (Some("#X"), Some("text")) | (Some("#X"), Some("array")) => EntryKind::Other,
+                (Some("#X"), Some("connect")) => {
+                    let numbers: Vec<usize> = tokens[2..]
+                        .iter()
+                        .filter_map(|arg| arg.parse().ok())
+                        .collect();
+                    if let (Some(canvas), [from, outlet, to, inlet]) =
+                        (stack.last_mut(), numbers.as_slice())
+                    {
+                        canvas.connections.push((*from, *outlet, *to, *inlet));
+                    }
+                    continue;
+                }
+                (Some("#X"), Some("declare")) => {
+                    for pair in tokens[2..].windows(2) {
+                        if pair[0] == "-path" {
+                            patch.declared_paths.push(dir.join(pair[1]));
+                        }
+                    }
+                    continue;
+                }
+                (Some("#X"), Some("coords")) | (Some("#A"), _) => continue,
+                (Some("#X"), Some(other)) => EntryKind::Unsupported(other.to_string()),
+                _ => continue,
+            };
+
+            if let Some(canvas) = stack.last_mut() {
+                canvas.entries.push(Entry { line, kind });
+            }
+        }
+
+        patch.canvases.extend(stack.into_iter().rev());
+        patch
+    }
+}
+
+impl Canvas {
+    fn is_connected(&self, object: usize, inlet: usize) -> bool {
+        self.connections
+            .iter()
+            .any(|&(_, _, to, to_inlet)| to == object && to_inlet == inlet)
+    }
+
+    fn sends_to_message_box(&self, object: usize) -> bool {
+        self.connections.iter().any(|&(from, _, to, _)| {
+            from == object
+                && matches!(
+                    self.entries.get(to).map(|entry| &entry.kind),
+                    Some(EntryKind::MessageBox(_))
+                )
+        })
+    }
+}
+
+/// Split a netlist into statements, with the line each of them starts at. As in ZenGarden's
+/// parser, a statement starts at a line beginning with `#`. The trailing semicolon and the
+/// box width (e.g. **", f 10"**) are removed.
+fn statements(contents: &str) -> Vec<(usize, String)> {
+    let mut result: Vec<(usize, String)> = Vec::new();
+
+    for (n, line) in contents.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        match result.last_mut() {
+            Some((_, statement)) if !line.starts_with('#') => {
+                statement.push(' ');
+                statement.push_str(line);
+            }
+            _ => result.push((n + 1, line.to_string())),
+        }
+    }
+
+    for (_, statement) in &mut result {
+        let trimmed = statement.trim_end().trim_end_matches(';');
+        let trimmed = match trimmed.rfind(", f ") {
+            Some(n) if trimmed[n + 4..].trim().parse::<u32>().is_ok() => &trimmed[..n],
+            _ => trimmed,
+        };
+        *statement = trimmed.to_string();
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::{AudioLoopF32, Config};
+
+    use super::*;
+
+    #[test]
+    fn lint_patch() {
+        let context = Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
+        let issues = lint(&context, "test/lint.pd").unwrap();
+        let issues: Vec<(String, usize, IssueKind)> = issues
+            .into_iter()
+            .map(|issue| {
+                let file = issue.location.file.file_name().unwrap();
+                (
+                    file.to_string_lossy().into_owned(),
+                    issue.location.line,
+                    issue.kind,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            issues,
+            vec![
+                (
+                    "lint.pd".to_string(),
+                    4,
+                    IssueKind::UnsupportedObject("hradio".to_string())
+                ),
+                (
+                    "lint.pd".to_string(),
+                    5,
+                    IssueKind::InertGui("tgl".to_string())
+                ),
+                (
+                    "lint.pd".to_string(),
+                    6,
+                    IssueKind::InertGui("floatatom".to_string())
+                ),
+                (
+                    "lint.pd".to_string(),
+                    7,
+                    IssueKind::UnsupportedMessage(MessageSyntax::DollarZero)
+                ),
+                (
+                    "lint.pd".to_string(),
+                    8,
+                    IssueKind::UnsupportedMessage(MessageSyntax::MessageBoxMethod(
+                        "set".to_string()
+                    ))
+                ),
+                (
+                    "lint.pd".to_string(),
+                    11,
+                    IssueKind::UnconnectedDacChannel(2)
+                ),
+                (
+                    "lint_sub.pd".to_string(),
+                    2,
+                    IssueKind::UnsupportedObject("expr".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn statements() {
+        let netlist =
+            "#N canvas 0 0 450 300 12;\n#X msg 10 10 foo bar\nbaz, f 12;\n#X obj 1 1 f;\n";
+        assert_eq!(
+            super::statements(netlist),
+            vec![
+                (1, "#N canvas 0 0 450 300 12".to_string()),
+                (2, "#X msg 10 10 foo bar baz".to_string()),
+                (4, "#X obj 1 1 f".to_string()),
+            ]
+        );
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestDispatcher;
+
+    impl Dispatcher for TestDispatcher {
+        type UserData = ();
+    }
+}
@@ -2,19 +2,27 @@
 
 pub mod external;
 
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_void;
 
 use zengarden_raw::{
-    zg_object_get_canvas_position, zg_object_get_connection_type,
-    zg_object_get_connections_at_inlet, zg_object_get_connections_at_outlet, zg_object_get_label,
-    zg_object_get_num_inlets, zg_object_get_num_outlets, zg_object_remove, zg_object_send_message,
-    zg_object_set_canvas_position, zg_object_to_string, ZGConnectionPair, ZGConnectionType,
-    ZGObject,
+    zg_mem_free, zg_object_get_canvas_position, zg_object_get_connection_type,
+    zg_object_get_connections_at_inlet, zg_object_get_connections_at_outlet,
+    zg_object_get_creation_text, zg_object_get_inlet_type, zg_object_get_label,
+    zg_object_get_num_inlets, zg_object_get_num_outlets, zg_object_get_state, zg_object_get_type,
+    zg_object_get_value_name, zg_object_remove, zg_object_send_message,
+    zg_object_set_canvas_position, zg_object_set_state, zg_object_to_string, ZGConnectionPair,
+    ZGConnectionType, ZGObject,
 };
 
 use crate::message::Message;
+use external::ObjectType;
 
 /// Represents an object in a [Graph].
+///
+/// An [Object] is a plain handle which becomes invalid once the object is removed or its graph
+/// is dropped, and a new object may be created at the same address. Use
+/// [crate::graph::ObjectId] to keep references to objects, e.g. in editor state.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Object(pub(crate) *mut ZGObject);
 
@@ -41,14 +49,19 @@ impl Object {
         unsafe { zg_object_get_connection_type(self.0, outlet as u32).into() }
     }
 
+    /// Get inlet type. [InletType::Dsp] inlets accept signal connections (and usually messages as
+    /// well), [InletType::Message] inlets accept only messages.
+    pub fn inlet_type(&self, inlet: usize) -> InletType {
+        unsafe { zg_object_get_inlet_type(self.0, inlet as u32).into() }
+    }
+
     /// Returns [ConnectionPair]s which indicate the objects and outlets from which the
     /// connections are comming.
     pub fn connections_at_inlet(&self, inlet: usize) -> Vec<ConnectionPair> {
         unsafe {
             let mut size = 0_u32;
             let pairs = zg_object_get_connections_at_inlet(self.0, inlet as u32, &mut size);
-            let pairs = std::slice::from_raw_parts(pairs, size as usize);
-            pairs.into_iter().copied().map(From::from).collect()
+            take_pairs(pairs, size)
         }
     }
 
@@ -58,8 +71,7 @@ impl Object {
         unsafe {
             let mut size = 0_u32;
             let pairs = zg_object_get_connections_at_outlet(self.0, outlet as u32, &mut size);
-            let pairs = std::slice::from_raw_parts(pairs, size as usize);
-            pairs.into_iter().copied().map(From::from).collect()
+            take_pairs(pairs, size)
         }
     }
 
@@ -72,6 +84,49 @@ impl Object {
         }
     }
 
+    /// Returns the text the object was created from, e.g. **"osc~ 440"**, with any `$`
+    /// arguments unresolved. Message boxes are **"msg"** followed by their content, subgraphs
+    /// **"pd"** followed by their name and number boxes **"float"**. The text is empty for the
+    /// objects ZenGarden creates implicitly, such as `+~~`.
+    ///
+    /// Unlike [Object::to_string], the text recreates an equivalent object with
+    /// [crate::graph::Graph::add_object], except for abstractions added with
+    /// [crate::graph::Graph::add_abstraction].
+    pub fn creation_text(&self) -> String {
+        unsafe {
+            let text = zg_object_get_creation_text(self.0);
+            CStr::from_ptr(text).to_string_lossy().to_string()
+        }
+    }
+
+    /// Returns the class name as written in the creation text, e.g. “osc~” for **"osc~ 440"**
+    /// and “f” for **"f 1"**. It's empty for the objects which don't have a creation text.
+    pub fn class_name(&self) -> String {
+        self.creation_text()
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Returns the creation arguments, e.g. `440` for **"osc~ 440"**. They're parsed like
+    /// [Message::from_str], so numbers are [crate::message::MessageElement::Float]s and the rest
+    /// are symbols. `$` arguments are kept as symbols.
+    pub fn arguments(&self) -> Message {
+        let text = self.creation_text();
+        match text.trim().split_once(' ') {
+            Some((_, arguments)) => Message::from_str(0.0, arguments.trim())
+                .unwrap_or_else(|_| Message::builder().build()),
+            None => Message::builder().build(),
+        }
+    }
+
+    /// Returns the object type. Objects without a specific type, including most of the message
+    /// objects, are [ObjectType::ObjectUnknown].
+    pub fn object_type(&self) -> ObjectType {
+        unsafe { zg_object_get_type(self.0).into() }
+    }
+
     /// Get number of inlets.
     pub fn num_inlets(&self) -> usize {
         unsafe { zg_object_get_num_inlets(self.0) as usize }
@@ -85,6 +140,9 @@ impl Object {
     /// Removes the object from the graph and deletes it from memory. Any connections that this
     /// object may have had in the graph are also deleted. The reference to the object after this
     /// function completes is invalid.
+    ///
+    /// Prefer [crate::graph::Graph::remove_object], which also invalidates the object's
+    /// [crate::graph::ObjectId] right away.
     pub fn remove(self) {
         unsafe {
             zg_object_remove(self.0);
@@ -102,14 +160,69 @@ impl Object {
             zg_object_send_message(self.0, inlet as u32, message.into_raw());
         }
     }
+
+    /// Returns the state of the object which isn't part of its description, or `None` if the
+    /// object is stateless. That's the value stored in a `[float]` or a `[value]`, the output of
+    /// a `[toggle]` (`0` when it's off) and the content of a message box, e.g.
+    /// `1 \, 2 \; foo bang`.
+    pub fn state(&self) -> Option<String> {
+        unsafe {
+            let state = zg_object_get_state(self.0);
+            if state.is_null() {
+                None
+            } else {
+                let result = CStr::from_ptr(state).to_string_lossy().to_string();
+                zg_mem_free(state as *mut c_void);
+                Some(result)
+            }
+        }
+    }
+
+    /// Returns the name of a `[value]` (or `[v]`) with any `$` arguments resolved, or `None` for
+    /// the other objects.
+    pub fn value_name(&self) -> Option<String> {
+        unsafe {
+            let name = zg_object_get_value_name(self.0);
+            if name.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(name).to_string_lossy().to_string())
+            }
+        }
+    }
+
+    /// Restores a state returned by [Object::state]. Nothing is sent from the outlets, like with
+    /// Pd's `set` method.
+    pub fn set_state(&self, state: &str) {
+        let state = CString::new(state).expect("Can't initialize object state");
+        unsafe {
+            zg_object_set_state(self.0, state.as_ptr());
+        }
+    }
+}
+
+/// Copy an array of connections returned by ZenGarden and free it.
+unsafe fn take_pairs(pairs: *mut ZGConnectionPair, size: u32) -> Vec<ConnectionPair> {
+    if pairs.is_null() {
+        return Vec::new();
+    }
+
+    let result = std::slice::from_raw_parts(pairs, size as usize)
+        .iter()
+        .copied()
+        .map(From::from)
+        .collect();
+    zg_mem_free(pairs as *mut c_void);
+    result
 }
 
 impl ToString for Object {
     fn to_string(&self) -> String {
         unsafe {
-            CStr::from_ptr(zg_object_to_string(self.0))
-                .to_string_lossy()
-                .to_string()
+            let raw = zg_object_to_string(self.0);
+            let string = CStr::from_ptr(raw).to_string_lossy().to_string();
+            zg_mem_free(raw as *mut c_void);
+            string
         }
     }
 }
@@ -156,6 +269,9 @@ impl From<ZGConnectionType> for OutletType {
     }
 }
 
+/// Inlet type.
+pub type InletType = OutletType;
+
 /// Indicates the object and the outlet/inlet index from/to which the connection are comming.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct ConnectionPair {
@@ -214,14 +330,27 @@ mod tests {
         assert_eq!(osc.outlet_type(0), OutletType::Dsp);
     }
 
+    #[test]
+    fn inlet_type() {
+        let context = Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
+        let graph = Graph::new_empty(&context);
+        let lop = graph.add_object("lop~", None);
+        assert_eq!(lop.inlet_type(0), InletType::Dsp);
+        assert_eq!(lop.inlet_type(1), InletType::Message);
+    }
+
     #[test]
     fn connections() {
         let context = Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
         let graph = Graph::new_empty(&context);
         let osc = graph.add_object("osc~", None);
         let dac = graph.add_object("dac~", None);
-        graph.add_connection((osc, 0).into(), (dac, 0).into());
-        graph.add_connection((osc, 0).into(), (dac, 1).into());
+        graph
+            .add_connection((osc, 0).into(), (dac, 0).into())
+            .unwrap();
+        graph
+            .add_connection((osc, 0).into(), (dac, 1).into())
+            .unwrap();
 
         assert_eq!(
             osc.connections_at_outlet(0),
@@ -239,6 +368,51 @@ mod tests {
         assert_eq!("obj".to_string(), osc.label());
     }
 
+    #[test]
+    fn class_name_and_arguments() {
+        let context = Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
+        let graph = Graph::new_empty(&context);
+        let osc = graph.add_object("osc~ 440", None);
+        assert_eq!(osc.class_name(), "osc~");
+        let arguments = osc.arguments();
+        assert_eq!(arguments.num_elements(), 1);
+        assert_eq!(arguments.element_at(0), Some(&MessageElement::Float(440.0)));
+
+        let print = graph.add_object("print foo", None);
+        assert_eq!(print.class_name(), "print");
+        assert_eq!(
+            print.arguments().element_at(0),
+            Some(&MessageElement::Symbol("foo".to_string()))
+        );
+
+        let dac = graph.add_object("dac~", None);
+        assert_eq!(dac.class_name(), "dac~");
+        assert_eq!(dac.arguments().num_elements(), 0);
+
+        // [float] doesn't include its argument in its string representation
+        let float = graph.add_object("f 1", None);
+        assert_eq!(float.creation_text(), "f 1");
+        assert_eq!(float.class_name(), "f");
+        assert_eq!(float.arguments().element_at(0), Some(&MessageElement::Float(1.0)));
+
+        let message = graph.add_object("msg 1 \\, foo \\; bar 2", None);
+        assert_eq!(message.creation_text(), "msg 1 \\, foo \\; bar 2");
+        assert_eq!(message.class_name(), "msg");
+        assert_eq!(message.state(), Some("1 \\, foo \\; bar 2".to_string()));
+    }
+
+    #[test]
+    fn object_type() {
+        let context = Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
+        let graph = Graph::new_empty(&context);
+        let dac = graph.add_object("dac~", None);
+        assert_eq!(dac.object_type(), ObjectType::DspDac);
+        let receive = graph.add_object("receive foo", None);
+        assert_eq!(receive.object_type(), ObjectType::MessageReceive);
+        let float = graph.add_object("float", None);
+        assert_eq!(float.object_type(), ObjectType::ObjectUnknown);
+    }
+
     #[test]
     fn num_io() {
         let context = Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
@@ -293,6 +467,16 @@ mod tests {
         assert_eq!(osc.to_string(), "osc~ 440".to_string());
     }
 
+    #[test]
+    fn value_name() {
+        let context = Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
+        let graph = Graph::new_empty(&context);
+        let value = graph.add_object("v value-name", None);
+        let osc = graph.add_object("osc~", None);
+        assert_eq!(value.value_name().as_deref(), Some("value-name"));
+        assert!(osc.value_name().is_none());
+    }
+
     #[derive(Debug, Clone)]
     struct TestDispatcher;
 
@@ -3,7 +3,12 @@
 //! There are objects which only process messages, the [MessageObject], and those objects which
 //! process messages and audio, the [DspObject].
 
-use zengarden_raw::{PdMessage, ZGGraph, ZGObject};
+use std::any::Any;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+
+use zengarden_raw::{PdMessage, ZGGraph, ZGObject, ZGObjectType};
 
 use super::{ConnectionPair, ObjectPosition, OutletType};
 use crate::graph::Graph;
@@ -124,6 +129,7 @@ pub trait MessageObject: ToString {
 }
 
 #[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ObjectType {
     DspAdc,
     DspAdd,
@@ -156,6 +162,42 @@ pub enum ObjectType {
     ObjectUnknown,
 }
 
+impl From<ZGObjectType> for ObjectType {
+    fn from(raw: ZGObjectType) -> Self {
+        match raw {
+            ZGObjectType::ZG_OBJECT_DSP_ADC => Self::DspAdc,
+            ZGObjectType::ZG_OBJECT_DSP_ADD => Self::DspAdd,
+            ZGObjectType::ZG_OBJECT_DSP_BANDPASS_FILTER => Self::DspBandpassFilter,
+            ZGObjectType::ZG_OBJECT_DSP_CATCH => Self::DspCatch,
+            ZGObjectType::ZG_OBJECT_DSP_CLIP => Self::DspClip,
+            ZGObjectType::ZG_OBJECT_DSP_COSINE => Self::DspCosine,
+            ZGObjectType::ZG_OBJECT_DSP_DAC => Self::DspDac,
+            ZGObjectType::ZG_OBJECT_DSP_TABLE_PLAY => Self::DspTablePlay,
+            ZGObjectType::ZG_OBJECT_DSP_DELAY_READ => Self::DspDelayRead,
+            ZGObjectType::ZG_OBJECT_DSP_DELAY_WRITE => Self::DspDelayWrite,
+            ZGObjectType::ZG_OBJECT_DSP_INLET => Self::DspInlet,
+            ZGObjectType::ZG_OBJECT_DSP_OUTLET => Self::DspOutlet,
+            ZGObjectType::ZG_OBJECT_DSP_RECEIVE => Self::DspReceive,
+            ZGObjectType::ZG_OBJECT_DSP_SEND => Self::DspSend,
+            ZGObjectType::ZG_OBJECT_DSP_TABLE_READ => Self::DspTableRead,
+            ZGObjectType::ZG_OBJECT_DSP_TABLE_READ4 => Self::DspTableRead4,
+            ZGObjectType::ZG_OBJECT_DSP_TABLE_WRITE => Self::DspTableWrite,
+            ZGObjectType::ZG_OBJECT_DSP_THROW => Self::DspThrow,
+            ZGObjectType::ZG_OBJECT_DSP_VARIABLE_DELAY => Self::DspVariableDelay,
+            ZGObjectType::ZG_OBJECT_MESSAGE_INLET => Self::MessageInlet,
+            ZGObjectType::ZG_OBJECT_MESSAGE_NOTEIN => Self::MessageNotein,
+            ZGObjectType::ZG_OBJECT_MESSAGE_OUTLET => Self::MessageOutlet,
+            ZGObjectType::ZG_OBJECT_MESSAGE_RECEIVE => Self::MessageReceive,
+            ZGObjectType::ZG_OBJECT_MESSAGE_SEND => Self::MessageSend,
+            ZGObjectType::ZG_OBJECT_MESSAGE_TABLE => Self::MessageTable,
+            ZGObjectType::ZG_OBJECT_MESSAGE_TABLE_READ => Self::MessageTableRead,
+            ZGObjectType::ZG_OBJECT_MESSAGE_TABLE_WRITE => Self::MessageTableWrite,
+            ZGObjectType::ZG_OBJECT_PD => Self::ObjectPd,
+            ZGObjectType::ZG_OBJECT_UNKNOWN => Self::ObjectUnknown,
+        }
+    }
+}
+
 /// A `DspObject` is the trait for any object which processes audio. `DspObject` is a subtrait of
 /// [MessageObject], such that all of the former can implicitly also process [message::Message]s.
 pub trait DspObject: MessageObject {
@@ -215,7 +257,79 @@ pub trait DspObject: MessageObject {
 
 #[doc(hidden)]
 #[repr(C)]
-pub struct MessageObjAdapter(pub Box<dyn MessageObject>);
+pub struct MessageObjAdapter {
+    object: Box<dyn MessageObject>,
+    graph: *mut ZGGraph,
+    disabled: bool,
+}
+
+impl MessageObjAdapter {
+    /// Initialize an adapter for the object instantiated in the given graph.
+    pub fn new(object: Box<dyn MessageObject>, graph: *mut ZGGraph) -> Self {
+        Self {
+            object,
+            graph,
+            disabled: false,
+        }
+    }
+
+    /// Returns `true` if the object panicked and doesn't receive messages anymore.
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    // A panic must not unwind into ZenGarden, so it's caught here, reported to the context and
+    // the object is disabled, while the rest of the graph keeps running.
+    unsafe fn guard<F>(adapter: *mut Self, f: F)
+    where
+        F: FnOnce(&mut dyn MessageObject),
+    {
+        let adapter = match adapter.as_mut() {
+            Some(adapter) if !adapter.disabled => adapter,
+            _ => return,
+        };
+
+        let object = &mut adapter.object;
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| f(object.as_mut()))) {
+            adapter.disabled = true;
+            adapter.report_panic(payload);
+        }
+    }
+
+    unsafe fn report_panic(&self, payload: Box<dyn Any + Send>) {
+        if self.graph.is_null() {
+            return;
+        }
+
+        let object = &self.object;
+        let label = panic::catch_unwind(AssertUnwindSafe(|| object.label()))
+            .unwrap_or_else(|_| "obj".to_string());
+        let reason = payload
+            .downcast_ref::<&str>()
+            .map(|reason| reason.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown reason".to_string());
+        let message =
+            format!("{}: panicked ({}), the object is disabled.", label, reason).replace('\0', "");
+
+        if let Ok(message) = CString::new(message) {
+            message_obj_print_err(self.graph, message.as_ptr());
+        }
+    }
+}
+
+/// Instantiate a ZenGarden object backed by the given [MessageObject]. This is meant to be
+/// called from an external factory (see [plugin::ExternalFactory]).
+///
+/// # Safety
+///
+/// `graph` must be the graph pointer passed to the factory.
+pub unsafe fn new_object(object: Box<dyn MessageObject>, graph: *mut ZGGraph) -> *mut ZGObject {
+    let num_ins = object.num_inlets() as i32;
+    let num_outs = object.num_outlets() as i32;
+    let adapter = Box::into_raw(Box::new(MessageObjAdapter::new(object, graph)));
+    init_obj_wrapper(num_ins, num_outs, graph, adapter)
+}
 
 extern "C" {
     fn init_obj_wrapper(
@@ -224,6 +338,8 @@ extern "C" {
         graph: *mut ZGGraph,
         adapter: *mut MessageObjAdapter,
     ) -> *mut ZGObject;
+
+    fn message_obj_print_err(graph: *mut ZGGraph, message: *const c_char);
 }
 
 #[doc(hidden)]
@@ -233,9 +349,11 @@ unsafe extern "C" fn message_obj_receive_message(
     inlet: usize,
     message: *mut PdMessage,
 ) {
-    if let Some(message) = Message::from_raw(message) {
-        (*adapter).0.receive_message(inlet, message);
-    }
+    MessageObjAdapter::guard(adapter, |object| {
+        if let Some(message) = Message::from_raw(message) {
+            object.receive_message(inlet, message);
+        }
+    });
 }
 
 #[doc(hidden)]
@@ -245,9 +363,11 @@ unsafe extern "C" fn message_obj_process_message(
     inlet: usize,
     message: *mut PdMessage,
 ) {
-    if let Some(message) = Message::from_raw(message) {
-        (*adapter).0.process_message(inlet, message);
-    }
+    MessageObjAdapter::guard(adapter, |object| {
+        if let Some(message) = Message::from_raw(message) {
+            object.process_message(inlet, message);
+        }
+    });
 }
 
 #[doc(hidden)]
@@ -257,7 +377,104 @@ unsafe extern "C" fn message_obj_send_message(
     outlet: usize,
     message: *mut PdMessage,
 ) {
-    if let Some(message) = Message::from_raw(message) {
-        (*adapter).0.send_message(outlet, message);
+    MessageObjAdapter::guard(adapter, |object| {
+        if let Some(message) = Message::from_raw(message) {
+            object.send_message(outlet, message);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use zengarden_raw::zg_message_delete;
+
+    use crate::message::MessageElement;
+
+    use super::*;
+
+    #[test]
+    fn panic_disables_object() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let object = PanickingObject {
+            calls: calls.clone(),
+            graph: ptr::null_mut(),
+        };
+        let adapter = Box::into_raw(Box::new(MessageObjAdapter::new(
+            Box::new(object),
+            ptr::null_mut(),
+        )));
+
+        unsafe {
+            for _ in 0..2 {
+                let message = Message::builder()
+                    .with_element(MessageElement::Bang)
+                    .build();
+                let raw_message = message.into_raw();
+                message_obj_process_message(adapter, 0, raw_message);
+                zg_message_delete(raw_message);
+            }
+
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+            assert!((*adapter).is_disabled());
+
+            let _ = Box::from_raw(adapter);
+        }
+    }
+
+    struct PanickingObject {
+        calls: Arc<AtomicUsize>,
+        graph: *mut ZGGraph,
+    }
+
+    impl fmt::Display for PanickingObject {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "panic")
+        }
+    }
+
+    impl MessageObject for PanickingObject {
+        fn process_message(&mut self, _inlet: usize, _message: Message) {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            panic!("test panic");
+        }
+
+        fn outlet_type(&self, _outlet: usize) -> OutletType {
+            OutletType::Message
+        }
+
+        fn object_type(&self) -> ObjectType {
+            ObjectType::ObjectUnknown
+        }
+
+        fn is_leaf_node(&self) -> bool {
+            true
+        }
+
+        fn process_order(&self) -> Vec<Box<dyn DspObject>> {
+            Vec::new()
+        }
+
+        fn num_inlets(&self) -> usize {
+            1
+        }
+
+        fn num_outlets(&self) -> usize {
+            0
+        }
+
+        fn graph(&self) -> Graph<'_> {
+            Graph::child(self.graph)
+        }
+
+        fn position(&self) -> ObjectPosition {
+            (0.0, 0.0).into()
+        }
+
+        fn set_position(&self, _position: ObjectPosition) {}
     }
 }
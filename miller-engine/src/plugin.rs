@@ -0,0 +1,448 @@
+//! Loading externals from dynamic libraries.
+//!
+//! A plugin is a `cdylib` crate, which exports its externals using [declare_plugin]:
+//!
+//! ```ignore
+//! use miller_engine::declare_plugin;
+//! use miller_engine::plugin::Registry;
+//!
+//! fn register(registry: &mut Registry) {
+//!     registry.register("my_external", my_external_factory);
+//! }
+//!
+//! declare_plugin!(register);
+//! ```
+//!
+//! The host loads it using [context::Context::load_plugin]. The externals are handed over to the
+//! host through a C interface: their names are passed as C strings and their factories are
+//! `extern "C"` functions. The interface version is checked using [ABI_VERSION].
+//!
+//! Nothing else is checked. Externals written with [crate::object::external::new_object] pass Rust
+//! trait objects to the host, so such plugins must be built with the same compiler and
+//! `miller-engine` version as the host.
+
+use std::ffi::{CStr, CString, OsStr};
+use std::fmt;
+use std::fs;
+use std::os::raw::{c_char, c_void};
+use std::path::{Path, PathBuf};
+
+use libloading::{Library, Symbol};
+use thiserror::Error;
+use zengarden_raw::{ZGGraph, ZGMessage, ZGObject};
+
+use crate::context::{AudioLoop, Context, Dispatcher};
+
+/// The plugin ABI version. It's bumped each time the [Registry] or the external object layout
+/// changes in an incompatible way.
+pub const ABI_VERSION: u32 = 2;
+
+/// The name of the symbol, which returns the plugin's ABI version.
+pub const ABI_VERSION_SYMBOL: &str = "miller_plugin_abi_version";
+
+/// The name of the symbol, which registers the plugin's externals.
+pub const REGISTER_SYMBOL: &str = "miller_register_externals";
+
+/// External object factory.
+pub type ExternalFactory = unsafe extern "C" fn(*mut ZGMessage, *mut ZGGraph) -> *mut ZGObject;
+
+/// Callback, which the host passes to the plugin's registration function. The plugin calls it
+/// with the opaque registry pointer for each of its externals.
+pub type RegisterCallback =
+    unsafe extern "C" fn(registry: *mut c_void, name: *const c_char, factory: ExternalFactory);
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type RegisterFn = unsafe extern "C" fn(*mut c_void, RegisterCallback);
+
+/// Collection of externals exported by a plugin.
+#[derive(Debug, Default, Clone)]
+pub struct Registry {
+    externals: Vec<(String, ExternalFactory)>,
+}
+
+impl Registry {
+    /// Register an external under the given name. If an external with the same name is already
+    /// registered, its factory is replaced with the new one.
+    pub fn register(&mut self, name: &str, factory: ExternalFactory) {
+        match self.externals.iter_mut().find(|(label, _)| label == name) {
+            Some(external) => external.1 = factory,
+            None => self.externals.push((name.to_string(), factory)),
+        }
+    }
+
+    /// Returns the names of the registered externals.
+    pub fn names(&self) -> Vec<&str> {
+        self.externals
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Returns the number of the registered externals.
+    pub fn len(&self) -> usize {
+        self.externals.len()
+    }
+
+    /// Returns `true` if there are no registered externals.
+    pub fn is_empty(&self) -> bool {
+        self.externals.is_empty()
+    }
+
+    /// Register all the externals in the context.
+    pub fn register_into<D: Dispatcher, L: AudioLoop>(&self, context: &Context<D, L>) {
+        for (name, factory) in &self.externals {
+            context.register_external(name, *factory);
+        }
+    }
+
+    /// Pass the externals to the host. Used by [declare_plugin].
+    ///
+    /// # Safety
+    ///
+    /// `registry` and `callback` must be the ones passed to the plugin's registration function.
+    #[doc(hidden)]
+    pub unsafe fn export(&self, registry: *mut c_void, callback: RegisterCallback) {
+        for (name, factory) in &self.externals {
+            // names with a nul byte can't be passed and are skipped
+            if let Ok(name) = CString::new(name.as_str()) {
+                callback(registry, name.as_ptr(), *factory);
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn register_external(
+    registry: *mut c_void,
+    name: *const c_char,
+    factory: ExternalFactory,
+) {
+    if let (Some(registry), false) = ((registry as *mut Registry).as_mut(), name.is_null()) {
+        registry.register(&CStr::from_ptr(name).to_string_lossy(), factory);
+    }
+}
+
+/// Dynamic library with externals.
+///
+/// The library is kept loaded as long as the plugin lives, so it shouldn't be dropped while
+/// there are objects instantiated from it.
+pub struct Plugin {
+    path: PathBuf,
+    registry: Registry,
+    _library: Library,
+}
+
+impl Plugin {
+    /// Load plugin from the given path.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+
+        unsafe {
+            let library = Library::new(&path).map_err(|err| Error::Loading {
+                path: path.clone(),
+                source: err,
+            })?;
+
+            let abi_version: Symbol<'_, AbiVersionFn> = library
+                .get(ABI_VERSION_SYMBOL.as_bytes())
+                .map_err(|_| Error::MissingSymbol {
+                    path: path.clone(),
+                    symbol: ABI_VERSION_SYMBOL,
+                })?;
+            let found = abi_version();
+            if found != ABI_VERSION {
+                return Err(Error::AbiMismatch {
+                    path,
+                    expected: ABI_VERSION,
+                    found,
+                });
+            }
+
+            let register: Symbol<'_, RegisterFn> = library
+                .get(REGISTER_SYMBOL.as_bytes())
+                .map_err(|_| Error::MissingSymbol {
+                    path: path.clone(),
+                    symbol: REGISTER_SYMBOL,
+                })?;
+            let mut registry = Registry::default();
+            let registry_ptr: *mut Registry = &mut registry;
+            register(registry_ptr as *mut c_void, register_external);
+
+            Ok(Self {
+                path,
+                registry,
+                _library: library,
+            })
+        }
+    }
+
+    /// Load all plugins found in the given directory. Files, which don't have the platform's
+    /// dynamic library extension, are skipped.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<Self>, Error> {
+        let entries = fs::read_dir(dir.as_ref()).map_err(|err| Error::ReadingDir {
+            path: dir.as_ref().to_path_buf(),
+            source: err,
+        })?;
+
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension() == Some(OsStr::new(DYLIB_EXTENSION)))
+            .collect();
+        paths.sort();
+
+        paths.into_iter().map(Self::load).collect()
+    }
+
+    /// Get the path from which the plugin has been loaded.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Borrow the plugin's registry.
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+}
+
+impl fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Plugin")
+            .field("path", &self.path)
+            .field("registry", &self.registry)
+            .finish()
+    }
+}
+
+#[cfg(target_os = "macos")]
+const DYLIB_EXTENSION: &str = "dylib";
+#[cfg(target_os = "windows")]
+const DYLIB_EXTENSION: &str = "dll";
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const DYLIB_EXTENSION: &str = "so";
+
+/// Export the plugin's symbols. The argument is a function with the `fn(&mut Registry)`
+/// signature, which registers the plugin's externals. The registered externals are passed to the
+/// host through [RegisterCallback].
+#[macro_export]
+macro_rules! declare_plugin {
+    ($register:path) => {
+        #[doc(hidden)]
+        #[no_mangle]
+        pub extern "C" fn miller_plugin_abi_version() -> u32 {
+            $crate::plugin::ABI_VERSION
+        }
+
+        #[doc(hidden)]
+        #[no_mangle]
+        pub unsafe extern "C" fn miller_register_externals(
+            host_registry: *mut ::std::os::raw::c_void,
+            callback: $crate::plugin::RegisterCallback,
+        ) {
+            let mut registry = $crate::plugin::Registry::default();
+            $register(&mut registry);
+            registry.export(host_registry, callback);
+        }
+    };
+}
+
+/// [Plugin] errors.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The library can't be loaded.
+    #[error("Can't load plugin {path:?}: {source}")]
+    Loading {
+        /// Plugin path.
+        path: PathBuf,
+        /// The underlying error.
+        source: libloading::Error,
+    },
+    /// The library doesn't export a required symbol.
+    #[error("Plugin {path:?} doesn't export `{symbol}`")]
+    MissingSymbol {
+        /// Plugin path.
+        path: PathBuf,
+        /// Symbol name.
+        symbol: &'static str,
+    },
+    /// The plugin is built for another ABI version.
+    #[error("Plugin {path:?} ABI version is {found}, but {expected} is expected")]
+    AbiMismatch {
+        /// Plugin path.
+        path: PathBuf,
+        /// The host's ABI version.
+        expected: u32,
+        /// The plugin's ABI version.
+        found: u32,
+    },
+    /// The plugin directory can't be read.
+    #[error("Can't read plugin directory {path:?}: {source}")]
+    ReadingDir {
+        /// Directory path.
+        path: PathBuf,
+        /// The underlying error.
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process::Command;
+    use std::ptr;
+
+    use crate::context::{AudioLoopF32, Config};
+    use crate::graph::Graph;
+    use crate::message::{Message, MessageElement};
+
+    use super::*;
+
+    #[test]
+    fn registry() {
+        let mut registry = Registry::default();
+        assert!(registry.is_empty());
+
+        registry.register("foo", dummy_factory);
+        registry.register("bar", dummy_factory);
+        registry.register("foo", dummy_factory);
+
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.names(), vec!["foo", "bar"]);
+    }
+
+    #[test]
+    fn load() {
+        let path = build_test_plugin("load", false);
+        let plugin = Plugin::load(&path).unwrap();
+        assert_eq!(plugin.path(), path);
+        assert_eq!(
+            plugin.registry().names(),
+            vec!["test_external", "test_external~"]
+        );
+    }
+
+    #[test]
+    fn load_into_context() {
+        let context = Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap();
+        context
+            .load_plugin(build_test_plugin("context", false))
+            .unwrap();
+        let graph = Graph::new_empty(&context);
+        let external = graph.add_object("test_external", None);
+        assert_eq!(external.creation_text(), "test_external");
+        graph.attach();
+
+        external.send_message(
+            0,
+            Message::builder()
+                .with_element(MessageElement::Bang)
+                .build(),
+        );
+        for _ in 0..context.config().blocksize + 1 {
+            context.next_frame(&[0.0, 0.0]).unwrap();
+        }
+
+        assert!(context.user_data().ends_with("test_external: bang"));
+    }
+
+    #[test]
+    fn load_abi_mismatch() {
+        let result = Plugin::load(build_test_plugin("abi-mismatch", true));
+        assert!(matches!(
+            result,
+            Err(Error::AbiMismatch {
+                expected: ABI_VERSION,
+                found: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    #[allow(unreachable_pub)]
+    fn declare_plugin() {
+        fn register(registry: &mut Registry) {
+            registry.register("declared", dummy_factory);
+        }
+        crate::declare_plugin!(register);
+
+        let mut registry = Registry::default();
+        let registry_ptr: *mut Registry = &mut registry;
+        unsafe {
+            assert_eq!(miller_plugin_abi_version(), ABI_VERSION);
+            miller_register_externals(registry_ptr as *mut c_void, register_external);
+        }
+        assert_eq!(registry.names(), vec!["declared"]);
+    }
+
+    #[test]
+    fn load_missing() {
+        let result = Plugin::load("test/no_such_plugin.so");
+        assert!(matches!(result, Err(Error::Loading { .. })));
+    }
+
+    #[test]
+    fn load_dir_skips_other_files() {
+        let path = build_test_plugin("load-dir", false);
+        let dir = path.parent().unwrap();
+        fs::write(dir.join("plugin.rs"), "not a plugin").unwrap();
+
+        let plugins = Plugin::load_dir(dir).unwrap();
+        assert_eq!(plugins.len(), 1);
+        assert_eq!(plugins[0].path(), path);
+    }
+
+    // Build test/plugin.rs with rustc into a temporary directory of its own, linking it with the
+    // zengarden-raw the tests are linked with.
+    fn build_test_plugin(name: &str, old_abi: bool) -> PathBuf {
+        let dir = env::temp_dir().join(format!("miller-plugin-test-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("test_plugin.{}", DYLIB_EXTENSION));
+
+        let mut rustc = Command::new(env::var_os("RUSTC").unwrap_or_else(|| "rustc".into()));
+        rustc
+            .args(["--edition", "2018", "--crate-type", "cdylib", "-o"])
+            .arg(&path)
+            .arg("test/plugin.rs")
+            .arg("--extern")
+            .arg(zengarden_raw_rlib());
+        if let Some(sndfile_path) = env::var_os("LIBSNDFILE_PATH") {
+            rustc.arg("-L").arg(sndfile_path);
+        }
+        if old_abi {
+            rustc.args(["--cfg", "old_abi"]);
+        }
+        assert!(rustc.status().unwrap().success());
+        path
+    }
+
+    // The tests run from the directory with the dependencies of the test binary.
+    fn zengarden_raw_rlib() -> String {
+        let deps = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+        let rlib = fs::read_dir(deps)
+            .unwrap()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                let name = path.file_name().unwrap().to_string_lossy();
+                name.starts_with("libzengarden_raw-") && name.ends_with(".rlib")
+            })
+            .max_by_key(|path| path.metadata().and_then(|meta| meta.modified()).ok())
+            .expect("Can't find the zengarden-raw rlib");
+        format!("zengarden_raw={}", rlib.display())
+    }
+
+    unsafe extern "C" fn dummy_factory(_: *mut ZGMessage, _: *mut ZGGraph) -> *mut ZGObject {
+        ptr::null_mut()
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestDispatcher;
+
+    impl Dispatcher for TestDispatcher {
+        // the last printed message
+        type UserData = String;
+
+        fn print_std(message: String, data: &mut Self::UserData) {
+            *data = message;
+        }
+    }
+}
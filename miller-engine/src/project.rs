@@ -0,0 +1,217 @@
+//! Project files.
+//!
+//! A project file describes how to run a set of patches: the [Config] of the context, the audio
+//! device, the patches to load on startup and the receivers to register. It's written in TOML
+//! or JSON, e.g.:
+//!
+//! ```toml
+//! audio_device = "Built-in Output"
+//! patches = ["main.pd"]
+//! receivers = ["level"]
+//!
+//! [context]
+//! blocksize = 128
+//! search_paths = ["abstractions"]
+//! ```
+//!
+//! Any field may be left out, in which case its default is used. Relative paths are kept as they
+//! are and resolved against the project file directory when they're used, see
+//! [Project::resolve].
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::context::{AudioLoop, Config, ConfigError, Context, Dispatcher};
+
+/// Project file contents.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Project {
+    /// Name of the audio device. The default device is used if it isn't set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio_device: Option<String>,
+    /// Context config.
+    pub context: Config,
+    /// Patch files loaded on startup.
+    pub patches: Vec<PathBuf>,
+    /// Receivers registered with [Context::register_receiver].
+    pub receivers: Vec<String>,
+    // the directory of the file the project was loaded from
+    #[serde(skip)]
+    pub(crate) dir: Option<PathBuf>,
+}
+
+impl Project {
+    /// Load a project file. The format is chosen by the extension, which should be `.toml` or
+    /// `.json`. Relative paths are resolved against the project file directory by
+    /// [Project::resolve].
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|source| Error::Reading {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        let mut project = match Format::from_path(path) {
+            Some(Format::Toml) => Self::from_toml(&contents)?,
+            Some(Format::Json) => Self::from_json(&contents)?,
+            None => return Err(Error::UnknownFormat(path.to_path_buf())),
+        };
+        project.dir = path.parent().map(Path::to_path_buf);
+
+        Ok(project)
+    }
+
+    /// Parse a project in TOML and validate its config.
+    pub fn from_toml(contents: &str) -> Result<Self, Error> {
+        let project: Self = toml::from_str(contents)?;
+        project.context.validate()?;
+        Ok(project)
+    }
+
+    /// Parse a project in JSON and validate its config.
+    pub fn from_json(contents: &str) -> Result<Self, Error> {
+        let project: Self = serde_json::from_str(contents)?;
+        project.context.validate()?;
+        Ok(project)
+    }
+
+    /// Resolve a path from the project against the directory of the project file. Absolute paths
+    /// and the paths of projects which weren't loaded with [Project::from_file] are returned as
+    /// they are.
+    pub fn resolve<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        match &self.dir {
+            Some(dir) if path.as_ref().is_relative() => dir.join(path),
+            _ => path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Returns the resolved paths of the patch files.
+    pub fn patch_files(&self) -> Vec<PathBuf> {
+        self.patches.iter().map(|path| self.resolve(path)).collect()
+    }
+
+    /// Returns the context config with the search paths resolved.
+    pub fn context_config(&self) -> Config {
+        let mut config = self.context.clone();
+        config.search_paths = config
+            .search_paths
+            .iter()
+            .map(|path| self.resolve(path))
+            .collect();
+        config
+    }
+
+    /// Register the project receivers with the context.
+    pub fn register_receivers<D: Dispatcher, L: AudioLoop>(&self, context: &Context<D, L>) {
+        for receiver in &self.receivers {
+            context.register_receiver(receiver);
+        }
+    }
+}
+
+/// Project and [session](crate::session) file format.
+pub(crate) enum Format {
+    Toml,
+    Json,
+}
+
+impl Format {
+    /// Choose the format by the file extension.
+    pub(crate) fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Some(Format::Toml),
+            Some("json") => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+/// [Project] loading errors.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The project file can't be read.
+    #[error("Can't read {path:?}: {source}")]
+    Reading {
+        /// Project file path.
+        path: PathBuf,
+        /// The reason.
+        source: io::Error,
+    },
+    /// The project file extension is neither `.toml` nor `.json`.
+    #[error("Unknown project file format of {0:?}, it should be .toml or .json")]
+    UnknownFormat(PathBuf),
+    /// The TOML is malformed.
+    #[error("Invalid TOML: {0}")]
+    Toml(#[from] toml::de::Error),
+    /// The JSON is malformed.
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    /// The context config is invalid.
+    #[error("Invalid context config: {0}")]
+    Config(#[from] ConfigError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_file() {
+        let dir = fs::canonicalize("./test/").unwrap();
+        let toml = Project::from_file(dir.join("project.toml")).unwrap();
+        let json = Project::from_file(dir.join("project.json")).unwrap();
+
+        for project in &[toml, json] {
+            assert_eq!(project.audio_device.as_deref(), Some("Test Device"));
+            assert_eq!(project.context.blocksize, 128);
+            // the rest of the config is the default one
+            assert_eq!(project.context.sample_rate, 44100);
+            assert_eq!(project.receivers, vec!["test-send-message-s".to_string()]);
+
+            // the paths are kept as they are, but resolved when they're used
+            assert_eq!(project.patches, vec![PathBuf::from("send_message.pd")]);
+            assert_eq!(project.patch_files(), vec![dir.join("send_message.pd")]);
+            assert_eq!(
+                project.context_config().search_paths,
+                vec![dir.join("abstractions")]
+            );
+            assert_eq!(project.resolve("/abs.pd"), PathBuf::from("/abs.pd"));
+        }
+
+        assert!(matches!(
+            Project::from_file(dir.join("send_message.pd")),
+            Err(Error::UnknownFormat(_))
+        ));
+        assert!(matches!(
+            Project::from_file(dir.join("missing.toml")),
+            Err(Error::Reading { .. })
+        ));
+    }
+
+    #[test]
+    fn defaults_and_validation() {
+        let project = Project::from_toml("").unwrap();
+        assert!(project.audio_device.is_none());
+        assert_eq!(project.context.blocksize, 64);
+        assert!(project.patches.is_empty());
+        assert_eq!(project.resolve("a.pd"), PathBuf::from("a.pd"));
+
+        assert!(matches!(
+            Project::from_toml("[context]\nblocksize = 100\n"),
+            Err(Error::Config(ConfigError::BlockSize(100)))
+        ));
+        assert!(matches!(
+            Project::from_json(r#"{"context": {"input_ch_num": 0, "output_ch_num": 0}}"#),
+            Err(Error::Config(ConfigError::NoChannels))
+        ));
+        assert!(matches!(
+            Project::from_toml("patches = 1"),
+            Err(Error::Toml(_))
+        ));
+    }
+}
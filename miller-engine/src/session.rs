@@ -0,0 +1,544 @@
+//! Session files.
+//!
+//! A [Session] describes everything needed to bring a performance setup back: the
+//! [project](crate::project) settings, i.e. the context config, the patches and the receivers to
+//! register, and the MIDI and OSC mappings, the messages to send on startup and the tables to
+//! preload. It's saved in TOML or JSON, e.g.:
+//!
+//! ```toml
+//! version = 1
+//! patches = ["main.pd"]
+//! receivers = ["level"]
+//!
+//! [context]
+//! blocksize = 128
+//!
+//! [[midi]]
+//! controller = 7
+//! receiver = "volume"
+//!
+//! [[messages]]
+//! receiver = "tempo"
+//! message = "120"
+//!
+//! [[tables]]
+//! table = "kick"
+//! file = { path = "kick.wav" }
+//! ```
+//!
+//! Sessions carry a schema [version](Session::version). Older sessions are migrated to
+//! [SCHEMA_VERSION] on load, and sessions without a version are read as project files. Relative
+//! paths are kept as they are, so a session saves back the way it was loaded, and are resolved
+//! against the session file directory when they're used, like in a project.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::Error as Anyhow;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::context::{self, AudioLoop, ConfigError, Context, Dispatcher};
+use crate::graph::Graph;
+use crate::message::{self, Message};
+use crate::project::{Format, Project};
+use crate::table;
+
+/// The session schema version written by this version of the engine.
+pub const SCHEMA_VERSION: u32 = 1;
+
+// upgrades from each version to the next one, starting from version 0
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[migrate_v0];
+
+/// Session file contents.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Session {
+    /// Schema version, [SCHEMA_VERSION] once the session is loaded.
+    pub version: u32,
+    /// The audio device, context config, patches and receivers, which are saved at the top level
+    /// of the session like in a project file. The patches are loaded and attached to the context.
+    #[serde(flatten)]
+    pub project: Project,
+    /// MIDI control change mappings.
+    pub midi: Vec<MidiMapping>,
+    /// OSC address mappings.
+    pub osc: Vec<OscMapping>,
+    /// Messages sent once the patches are loaded, in order.
+    pub messages: Vec<InitialMessage>,
+    /// Tables filled once the patches are loaded.
+    pub tables: Vec<TablePreload>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            project: Project::default(),
+            midi: Vec::new(),
+            osc: Vec::new(),
+            messages: Vec::new(),
+            tables: Vec::new(),
+        }
+    }
+}
+
+/// Sends the values of a MIDI control change to a receiver.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MidiMapping {
+    /// MIDI channel, from 0 to 15. Any channel matches if it isn't set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u8>,
+    /// Controller number.
+    pub controller: u8,
+    /// Receiver the value is sent to.
+    pub receiver: String,
+    /// The range the controller values from 0 to 127 are scaled to.
+    #[serde(default = "unit_range")]
+    pub range: [f32; 2],
+}
+
+impl MidiMapping {
+    /// Returns the scaled value if the control change matches the mapping.
+    pub fn map(&self, channel: u8, controller: u8, value: u8) -> Option<f32> {
+        if controller != self.controller || matches!(self.channel, Some(ch) if ch != channel) {
+            return None;
+        }
+
+        let [min, max] = self.range;
+        Some(min + (max - min) * f32::from(value.min(127)) / 127.0)
+    }
+}
+
+fn unit_range() -> [f32; 2] {
+    [0.0, 1.0]
+}
+
+/// Sends the arguments of the OSC messages with an address to a receiver.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct OscMapping {
+    /// OSC address, e.g. `/synth/cutoff`.
+    pub address: String,
+    /// Receiver the arguments are sent to.
+    pub receiver: String,
+}
+
+/// Message sent on startup.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct InitialMessage {
+    /// Receiver name.
+    pub receiver: String,
+    /// The message in Pd's notation, e.g. `set 1 2`.
+    pub message: String,
+}
+
+/// Table contents loaded on startup.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TablePreload {
+    /// Table name.
+    pub table: String,
+    /// Where the contents come from.
+    #[serde(flatten)]
+    pub source: TableSource,
+}
+
+/// Source of [TablePreload] contents.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TableSource {
+    /// The samples, which replace the table contents and length.
+    Samples(Vec<f32>),
+    /// A channel of an audio file, which is loaded with [table::Table::load_audio_file],
+    /// resizing the table.
+    File {
+        /// Audio file path.
+        path: PathBuf,
+        /// Channel index.
+        #[serde(default)]
+        channel: usize,
+    },
+}
+
+impl Session {
+    /// Load a session file. The format is chosen by the extension, which should be `.toml` or
+    /// `.json`. Relative paths are resolved against the session file directory by
+    /// [Project::resolve].
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|source| Error::Reading {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        let mut session = match format(path)? {
+            Format::Toml => Self::from_toml(&contents)?,
+            Format::Json => Self::from_json(&contents)?,
+        };
+        session.project.dir = path.parent().map(Path::to_path_buf);
+
+        Ok(session)
+    }
+
+    /// Parse a session in TOML, migrating it to [SCHEMA_VERSION].
+    pub fn from_toml(contents: &str) -> Result<Self, Error> {
+        Self::from_value(toml::from_str(contents)?)
+    }
+
+    /// Parse a session in JSON, migrating it to [SCHEMA_VERSION].
+    pub fn from_json(contents: &str) -> Result<Self, Error> {
+        Self::from_value(serde_json::from_str(contents)?)
+    }
+
+    fn from_value(value: Value) -> Result<Self, Error> {
+        let mut session = match value {
+            Value::Object(session) => session,
+            _ => return Err(Error::NotATable),
+        };
+
+        let version = match session.get("version") {
+            None => 0,
+            Some(version) => version
+                .as_u64()
+                .filter(|&version| version <= u64::from(SCHEMA_VERSION))
+                .ok_or_else(|| Error::UnsupportedVersion(version.to_string()))?
+                as usize,
+        };
+        for migrate in &MIGRATIONS[version..] {
+            migrate(&mut session);
+        }
+        session.insert("version".to_string(), SCHEMA_VERSION.into());
+
+        let session: Self = serde_json::from_value(Value::Object(session))?;
+        session.project.context.validate()?;
+        Ok(session)
+    }
+
+    /// Save the session file, in the format chosen by the extension like in
+    /// [Session::from_file]. The paths are saved as they are, so the relative ones stay relative
+    /// to the directory the session was loaded from.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let contents = match format(path)? {
+            Format::Toml => self.to_toml()?,
+            Format::Json => self.to_json()?,
+        };
+
+        fs::write(path, contents).map_err(|source| Error::Writing {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Serialize the session to TOML.
+    pub fn to_toml(&self) -> Result<String, Error> {
+        // going through a value puts the plain values before the tables, as TOML requires
+        Ok(toml::to_string(&toml::Value::try_from(self)?)?)
+    }
+
+    /// Serialize the session to JSON.
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Create a context with the session config and set it up: register the receivers, load and
+    /// attach the patches, preload the tables and send the initial messages. Returns an
+    /// [Instance] with the context and the graphs of the patches.
+    pub fn instantiate<D: Dispatcher, L: AudioLoop>(&self) -> Result<Instance<D, L>, Error> {
+        let context = Context::new(self.project.context_config())?;
+        self.project.register_receivers(&context);
+
+        let mut graphs = Vec::with_capacity(self.project.patches.len());
+        for path in self.project.patch_files() {
+            let graph = path
+                .to_str()
+                .ok_or_else(|| Anyhow::msg("The path isn't valid UTF-8"))
+                .and_then(|file| Graph::from_file(context.clone(), file))
+                .map_err(|reason| Error::Loading {
+                    path: path.clone(),
+                    reason,
+                })?;
+            graph.attach();
+            graphs.push(graph);
+        }
+
+        for preload in &self.tables {
+            let table = context
+                .table(&preload.table)
+                .ok_or_else(|| Error::NoSuchTable(preload.table.clone()))?;
+            match &preload.source {
+                TableSource::Samples(samples) => table.set_buffer(samples)?,
+                TableSource::File { path, channel } => {
+                    table.load_audio_file(self.project.resolve(path), *channel, true)?;
+                }
+            }
+        }
+
+        for initial in &self.messages {
+            context.send_message(&initial.receiver, parse(&initial.message)?);
+        }
+
+        Ok(Instance { graphs, context })
+    }
+
+    /// Send the value of a MIDI control change to the receivers of the matching mappings.
+    pub fn send_control_change<D: Dispatcher, L: AudioLoop>(
+        &self,
+        context: &Context<D, L>,
+        channel: u8,
+        controller: u8,
+        value: u8,
+    ) {
+        for mapping in &self.midi {
+            if let Some(value) = mapping.map(channel, controller, value) {
+                let message = Message::builder()
+                    .with_element(message::MessageElement::Float(f64::from(value)))
+                    .build();
+                context.send_message(&mapping.receiver, message);
+            }
+        }
+    }
+
+    /// Send the arguments of an OSC message, in Pd's notation, to the receivers of the mappings
+    /// with its address.
+    pub fn send_osc<D: Dispatcher, L: AudioLoop>(
+        &self,
+        context: &Context<D, L>,
+        address: &str,
+        arguments: &str,
+    ) -> Result<(), Error> {
+        for mapping in self.osc.iter().filter(|mapping| mapping.address == address) {
+            context.send_message(&mapping.receiver, parse(arguments)?);
+        }
+
+        Ok(())
+    }
+}
+
+/// A context set up by [Session::instantiate] and the graphs of the session patches, which are
+/// deleted when the instance is dropped. The graphs are dropped before the context, so they're
+/// never deleted after it.
+#[derive(Debug)]
+pub struct Instance<D: Dispatcher, L: AudioLoop> {
+    // declared before the context, so they're dropped first
+    graphs: Vec<Graph<'static>>,
+    context: Context<D, L>,
+}
+
+impl<D: Dispatcher, L: AudioLoop> Instance<D, L> {
+    /// Get the context.
+    pub fn context(&self) -> &Context<D, L> {
+        &self.context
+    }
+
+    /// Get the graphs of the session patches, in the order of [Project::patches].
+    pub fn graphs(&self) -> &[Graph<'static>] {
+        &self.graphs
+    }
+}
+
+fn parse(message: &str) -> Result<Message, Error> {
+    Message::from_str(0.0, message).map_err(|source| Error::Message {
+        message: message.to_string(),
+        source,
+    })
+}
+
+/// Version 0 is the [project](crate::project) file, which is a session without the version and
+/// the session settings, so there's nothing to change.
+fn migrate_v0(_: &mut Map<String, Value>) {}
+
+fn format(path: &Path) -> Result<Format, Error> {
+    Format::from_path(path).ok_or_else(|| Error::UnknownFormat(path.to_path_buf()))
+}
+
+/// [Session] errors.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The session file can't be read.
+    #[error("Can't read {path:?}: {source}")]
+    Reading {
+        /// Session file path.
+        path: PathBuf,
+        /// The reason.
+        source: io::Error,
+    },
+    /// The session file can't be written.
+    #[error("Can't write {path:?}: {source}")]
+    Writing {
+        /// Session file path.
+        path: PathBuf,
+        /// The reason.
+        source: io::Error,
+    },
+    /// The session file extension is neither `.toml` nor `.json`.
+    #[error("Unknown session file format of {0:?}, it should be .toml or .json")]
+    UnknownFormat(PathBuf),
+    /// The session isn't a TOML table or a JSON object.
+    #[error("The session should be a table of settings")]
+    NotATable,
+    /// The session version is newer than [SCHEMA_VERSION] or isn't a number.
+    #[error(
+        "Unsupported session version {0}, the latest supported one is {}",
+        SCHEMA_VERSION
+    )]
+    UnsupportedVersion(String),
+    /// The TOML is malformed.
+    #[error("Invalid TOML: {0}")]
+    Toml(#[from] toml::de::Error),
+    /// The session can't be serialized to TOML.
+    #[error("Can't serialize the session to TOML: {0}")]
+    TomlSerializing(#[from] toml::ser::Error),
+    /// The JSON is malformed or doesn't match the schema.
+    #[error("Invalid session: {0}")]
+    Json(#[from] serde_json::Error),
+    /// The context config is invalid.
+    #[error("Invalid context config: {0}")]
+    Config(#[from] ConfigError),
+    /// The context can't be created.
+    #[error(transparent)]
+    Context(#[from] context::Error),
+    /// A patch can't be loaded.
+    #[error("Can't load {path:?}: {reason}")]
+    Loading {
+        /// Patch file path.
+        path: PathBuf,
+        /// The reason.
+        reason: Anyhow,
+    },
+    /// There's no table to preload.
+    #[error("There's no table named {0}")]
+    NoSuchTable(String),
+    /// A table can't be preloaded.
+    #[error(transparent)]
+    Table(#[from] table::Error),
+    /// A message can't be parsed.
+    #[error("Can't parse message {message:?}: {source}")]
+    Message {
+        /// The message.
+        message: String,
+        /// The reason.
+        source: message::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::AudioLoopF32;
+    use crate::message::MessageElement;
+
+    use super::*;
+
+    #[test]
+    fn parse_and_save() {
+        let session =
+            Session::from_toml(&fs::read_to_string("./test/session.toml").unwrap()).unwrap();
+        assert_eq!(session.version, SCHEMA_VERSION);
+        assert_eq!(session.project.context.blocksize, 16);
+        assert_eq!(session.midi[0].map(1, 7, 127), Some(10.0));
+        assert_eq!(session.midi[0].map(0, 7, 127), None);
+        assert_eq!(session.midi[0].map(1, 8, 127), None);
+        assert_eq!(
+            session.tables[0].source,
+            TableSource::Samples(vec![1.0, 2.0, 3.0])
+        );
+
+        for saved in &[
+            Session::from_toml(&session.to_toml().unwrap()).unwrap(),
+            Session::from_json(&session.to_json().unwrap()).unwrap(),
+        ] {
+            assert_eq!(saved.project.patches, session.project.patches);
+            assert_eq!(saved.project.receivers, session.project.receivers);
+            assert_eq!(saved.project.context.blocksize, 16);
+            assert_eq!(saved.midi, session.midi);
+            assert_eq!(saved.osc, session.osc);
+            assert_eq!(saved.messages, session.messages);
+            assert_eq!(saved.tables, session.tables);
+        }
+    }
+
+    #[test]
+    fn save_relative_paths() {
+        let dir = fs::canonicalize("./test/").unwrap();
+        let session = Session::from_file(dir.join("session.toml")).unwrap();
+        assert_eq!(session.project.patches, vec![PathBuf::from("session.pd")]);
+        assert_eq!(session.project.patch_files(), vec![dir.join("session.pd")]);
+
+        let path = std::env::temp_dir().join("miller-session-test.json");
+        session.save(&path).unwrap();
+        let saved = Session::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved.project.patches, vec![PathBuf::from("session.pd")]);
+    }
+
+    #[test]
+    fn migration() {
+        // a project file, i.e. version 0
+        let session =
+            Session::from_toml("patches = [\"a.pd\"]\n[context]\nblocksize = 32\n").unwrap();
+        assert_eq!(session.version, SCHEMA_VERSION);
+        assert_eq!(session.project.context.blocksize, 32);
+        assert_eq!(session.project.patches, vec![PathBuf::from("a.pd")]);
+
+        assert!(matches!(
+            Session::from_json(r#"{"version": 2}"#),
+            Err(Error::UnsupportedVersion(version)) if version == "2"
+        ));
+        assert!(matches!(Session::from_json("[]"), Err(Error::NotATable)));
+        assert!(matches!(
+            Session::from_toml("[context]\nblocksize = 3\n"),
+            Err(Error::Config(ConfigError::BlockSize(3)))
+        ));
+    }
+
+    #[test]
+    fn instantiate() {
+        let session = Session::from_file("./test/session.toml").unwrap();
+
+        let instance = session
+            .instantiate::<TestDispatcher, AudioLoopF32>()
+            .unwrap();
+        let context = instance.context();
+        assert_eq!(instance.graphs().len(), 1);
+        assert!(instance.graphs()[0].is_attached());
+        assert_eq!(context.config().blocksize, 16);
+        assert_eq!(
+            context.table("session-table").unwrap().to_vec(),
+            vec![1.0, 2.0, 3.0]
+        );
+
+        let process = || {
+            for _ in 0..17 {
+                context.next_frame(&[0.0, 0.0]).unwrap();
+            }
+        };
+        process();
+        assert_eq!(
+            context.user_data().0,
+            Some(MessageElement::Symbol("foo".to_string()))
+        );
+
+        session.send_control_change(context, 1, 7, 127);
+        process();
+        assert_eq!(context.user_data().0, Some(MessageElement::Float(10.0)));
+
+        session.send_osc(context, "/session", "5").unwrap();
+        process();
+        assert_eq!(context.user_data().0, Some(MessageElement::Float(5.0)));
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestDispatcher;
+
+    #[derive(Debug, Default)]
+    struct TestUserData(Option<MessageElement>);
+
+    impl Dispatcher for TestDispatcher {
+        type UserData = TestUserData;
+
+        fn receiver_message(_: String, message: Option<Message>, data: &mut Self::UserData) {
+            data.0 = message.and_then(|message| message.element_at(0).cloned());
+        }
+    }
+}
@@ -1,27 +1,503 @@
 //! Pd table/array.
 
-use zengarden_raw::{zg_table_get_buffer, zg_table_set_buffer};
+use std::ffi::{CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Arc, RwLock, Weak};
 
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zengarden_raw::{
+    zg_context_get_table_for_name, zg_table_get_length, zg_table_get_name,
+    zg_table_load_audio_file, zg_table_read, zg_table_resize, zg_table_save_audio_file,
+    zg_table_set_buffer, zg_table_write, PdContext, ZGAudioFileFormat, ZGGraph, ZGObject,
+};
+
+use crate::context::{AudioLoop, Context, Dispatcher};
+use crate::graph::Graph;
+use crate::object::external::ObjectType;
 use crate::object::Object;
 
 /// Pd table/array.
+///
+/// A [Table] refers to the table by its name and looks it up on every operation, so it never
+/// dangles. Once the table is gone, i.e. it's removed, its graph is dropped or, for the tables
+/// taken with [Table::for_name], detached, the operations return [Error::Missing].
+///
+/// All the operations are thread-safe with regards to [Context::next_frame].
 #[derive(Debug, Clone)]
-pub struct Table(Object);
+pub struct Table {
+    name: String,
+    source: Source,
+}
+
+#[derive(Debug, Clone)]
+enum Source {
+    // a table registered with the context, i.e. one in an attached graph
+    Context(Weak<RwLock<*mut PdContext>>),
+    // a table in the graph or its subgraphs, which doesn't have to be attached
+    Graph(Weak<AtomicPtr<ZGGraph>>),
+}
 
 impl Table {
-    /// Clone table buffer for the given lentgh.
-    pub fn buffer(&self, length: usize) -> &[f32] {
-        unsafe {
-            let raw = zg_table_get_buffer(self.0 .0, &mut (length as u32));
-            std::slice::from_raw_parts(raw, length)
+    /// Get the global table with the given name. Returns `None` if there's no such table in any
+    /// graph attached to the context.
+    pub fn for_name<D: Dispatcher, L: AudioLoop>(
+        context: &Context<D, L>,
+        name: &str,
+    ) -> Option<Self> {
+        let table = Self {
+            name: name.to_string(),
+            source: Source::Context(Arc::downgrade(&context.raw_context)),
+        };
+        table.with_raw(|_| ()).ok().map(|_| table)
+    }
+
+    /// Wrap a `[table]` object of the graph or one of its subgraphs.
+    pub(crate) fn in_graph(graph: Weak<AtomicPtr<ZGGraph>>, object: Object) -> Self {
+        Self {
+            name: raw_name(object.0).unwrap_or_default(),
+            source: Source::Graph(graph),
+        }
+    }
+
+    /// Get the table name.
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Get the table length. It's zero if the table is missing.
+    pub fn len(&self) -> usize {
+        self.with_raw(|raw| unsafe { zg_table_get_length(raw) as usize })
+            .unwrap_or_default()
+    }
+
+    /// Returns `true` if the table buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copy the table contents starting at `offset` into `buffer`. The whole `buffer` is filled,
+    /// so the range should be within the table bounds.
+    pub fn read_into(&self, offset: usize, buffer: &mut [f32]) -> Result<(), Error> {
+        self.with_raw(|raw| unsafe {
+            check_range(raw, offset, buffer.len())?;
+            let result =
+                zg_table_read(raw, offset as u32, buffer.as_mut_ptr(), buffer.len() as u32);
+            check_result(raw, result, offset, buffer.len())
+        })?
+    }
+
+    /// Copy `buffer` into the table starting at `offset`. The range should be within the table
+    /// bounds, use [Table::resize] to grow the table.
+    pub fn write_from(&self, offset: usize, buffer: &[f32]) -> Result<(), Error> {
+        self.with_raw(|raw| unsafe {
+            check_range(raw, offset, buffer.len())?;
+            // ZenGarden doesn't modify the source buffer
+            let result = zg_table_write(
+                raw,
+                offset as u32,
+                buffer.as_ptr() as *mut f32,
+                buffer.len() as u32,
+            );
+            check_result(raw, result, offset, buffer.len())
+        })?
+    }
+
+    /// Resize the table. Existing samples are kept, new samples are zeroed. The length should be
+    /// positive and fit in an `i32`, which is what ZenGarden uses for buffer lengths.
+    pub fn resize(&self, length: usize) -> Result<(), Error> {
+        if length == 0 || length > i32::MAX as usize {
+            return Err(Error::InvalidLength(length));
+        }
+
+        self.with_raw(|raw| unsafe { zg_table_resize(raw, length as u32) })
+    }
+
+    /// Copy the whole table contents. It's empty if the table is missing.
+    pub fn to_vec(&self) -> Vec<f32> {
+        self.with_raw(|raw| unsafe {
+            let mut buffer = vec![0.0; zg_table_get_length(raw) as usize];
+            // the table could be resized in between, so just return what we've got in this case
+            let _ = zg_table_read(raw, 0, buffer.as_mut_ptr(), buffer.len() as u32);
+            buffer
+        })
+        .unwrap_or_default()
+    }
+
+    /// The table’s buffer is resized and copied from the given buffer.
+    pub fn set_buffer(&self, buffer: &[f32]) -> Result<(), Error> {
+        if buffer.len() > i32::MAX as usize {
+            return Err(Error::InvalidLength(buffer.len()));
         }
+
+        self.with_raw(|raw| unsafe {
+            zg_table_set_buffer(raw, buffer.as_ptr() as *mut f32, buffer.len() as u32);
+        })
     }
 
-    /// The table’s buffer is resized and copied from the given buffer. This set operation is
-    /// thread-safe especially with regards to [context::Context::process].
-    pub fn set_buffer(&self, buffer: &mut [f32]) {
-        unsafe {
-            zg_table_set_buffer(self.0 .0, buffer.as_mut_ptr(), buffer.len() as u32);
+    /// Load a channel of an audio file (WAV, AIFF, FLAC or any other format supported by
+    /// libsndfile) into the table. If `resize` is `true`, the table is resized to the file length,
+    /// otherwise the samples which don't fit are dropped and the rest of the table is zeroed.
+    ///
+    /// Returns the number of samples loaded.
+    pub fn load_audio_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        channel: usize,
+        resize: bool,
+    ) -> Result<usize, Error> {
+        let path = path.as_ref();
+        let raw_path = path_to_cstring(path)?;
+
+        let result = self.with_raw(|raw| unsafe {
+            zg_table_load_audio_file(raw, raw_path.as_ptr(), channel as u32, resize as i32)
+        })?;
+
+        match result {
+            -1 => Err(Error::OpeningFile(path.to_path_buf())),
+            -2 => Err(Error::ChannelOutOfRange(channel)),
+            -4 => Err(Error::InvalidFileLength(path.to_path_buf())),
+            -5 => Err(Error::Allocation),
+            n if n < 0 => Err(Error::NotATable),
+            n => Ok(n as usize),
         }
     }
+
+    /// Save the table to a mono audio file using the context sample rate. WAV and AIFF are
+    /// written as 32-bit float, FLAC as 24-bit integer.
+    ///
+    /// Returns the number of samples written.
+    pub fn save_audio_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        format: AudioFileFormat,
+    ) -> Result<usize, Error> {
+        let path = path.as_ref();
+        let raw_path = path_to_cstring(path)?;
+
+        let result = self.with_raw(|raw| unsafe {
+            zg_table_save_audio_file(raw, raw_path.as_ptr(), format.into())
+        })?;
+
+        match result {
+            -1 => Err(Error::OpeningFile(path.to_path_buf())),
+            n if n < 0 => Err(Error::NotATable),
+            n => Ok(n as usize),
+        }
+    }
+
+    /// Look the table up and run `f` with it. The lookup keeps the context from being
+    /// reconfigured until `f` returns.
+    fn with_raw<R>(&self, f: impl FnOnce(*mut ZGObject) -> R) -> Result<R, Error> {
+        let missing = || Error::Missing(self.name.clone());
+        match &self.source {
+            Source::Context(context) => {
+                let context = context.upgrade().ok_or_else(missing)?;
+                let raw_context = context.read().unwrap();
+                let raw_name = CString::new(self.name.as_str()).map_err(|_| missing())?;
+                let raw = unsafe { zg_context_get_table_for_name(*raw_context, raw_name.as_ptr()) };
+                if raw.is_null() {
+                    Err(missing())
+                } else {
+                    Ok(f(raw))
+                }
+            }
+            Source::Graph(graph) => {
+                let graph = graph.upgrade().ok_or_else(missing)?;
+                let graph = Graph::child(graph.load(Ordering::Acquire));
+                let object = find_in_graph(&graph, &self.name).ok_or_else(missing)?;
+                Ok(f(object.0))
+            }
+        }
+    }
+}
+
+fn find_in_graph(graph: &Graph<'_>, name: &str) -> Option<Object> {
+    graph
+        .objects()
+        .into_iter()
+        .find_map(|object| match object.object_type() {
+            ObjectType::MessageTable if raw_name(object.0).as_deref() == Some(name) => Some(object),
+            ObjectType::ObjectPd => find_in_graph(&Graph::child(object.0 as *mut ZGGraph), name),
+            _ => None,
+        })
+}
+
+fn raw_name(raw: *mut ZGObject) -> Option<String> {
+    unsafe {
+        let name = zg_table_get_name(raw);
+        if name.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(name).to_string_lossy().to_string())
+        }
+    }
+}
+
+unsafe fn check_range(raw: *mut ZGObject, offset: usize, length: usize) -> Result<(), Error> {
+    let table_len = zg_table_get_length(raw) as usize;
+    match offset.checked_add(length) {
+        Some(end) if end <= table_len => Ok(()),
+        _ => Err(Error::OutOfBounds {
+            offset,
+            length,
+            table_len,
+        }),
+    }
+}
+
+unsafe fn check_result(
+    raw: *mut ZGObject,
+    result: i32,
+    offset: usize,
+    length: usize,
+) -> Result<(), Error> {
+    if result == 0 {
+        Ok(())
+    } else {
+        // the table has been resized after the range has been checked
+        Err(Error::OutOfBounds {
+            offset,
+            length,
+            table_len: zg_table_get_length(raw) as usize,
+        })
+    }
+}
+
+fn path_to_cstring(path: &Path) -> Result<CString, Error> {
+    path.to_str()
+        .and_then(|path| CString::new(path).ok())
+        .ok_or_else(|| Error::OpeningFile(path.to_path_buf()))
+}
+
+/// Audio file format for [Table::save_audio_file].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum AudioFileFormat {
+    /// WAV.
+    Wav,
+    /// AIFF.
+    Aiff,
+    /// FLAC.
+    Flac,
+}
+
+impl AudioFileFormat {
+    /// Guess the format from the file extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "wav" | "wave" => Some(Self::Wav),
+            "aif" | "aiff" => Some(Self::Aiff),
+            "flac" => Some(Self::Flac),
+            _ => None,
+        }
+    }
+}
+
+impl From<AudioFileFormat> for ZGAudioFileFormat {
+    fn from(format: AudioFileFormat) -> Self {
+        match format {
+            AudioFileFormat::Wav => Self::ZG_AUDIO_FILE_WAV,
+            AudioFileFormat::Aiff => Self::ZG_AUDIO_FILE_AIFF,
+            AudioFileFormat::Flac => Self::ZG_AUDIO_FILE_FLAC,
+        }
+    }
+}
+
+/// [Table] errors.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum Error {
+    /// The range is out of the table bounds.
+    #[error("Range {offset}..{offset}+{length} is out of the table bounds (0..{table_len})")]
+    OutOfBounds {
+        /// Range start.
+        offset: usize,
+        /// Range length.
+        length: usize,
+        /// Table length.
+        table_len: usize,
+    },
+    /// Invalid table length.
+    #[error("Invalid table length: {0}")]
+    InvalidLength(usize),
+    /// The audio file can't be opened.
+    #[error("Can't open audio file {0:?}")]
+    OpeningFile(PathBuf),
+    /// The audio file doesn't have the requested channel.
+    #[error("The audio file doesn't have channel {0}")]
+    ChannelOutOfRange(usize),
+    /// The audio file is empty or too long to be loaded.
+    #[error("Audio file {0:?} is empty or too long")]
+    InvalidFileLength(PathBuf),
+    /// The samples of the audio file can't be allocated.
+    #[error("Can't allocate the samples of the audio file")]
+    Allocation,
+    /// The object isn't a table.
+    #[error("The object isn't a table")]
+    NotATable,
+    /// The table has been removed, or its graph has been dropped or detached.
+    #[error("Table {0:?} is missing")]
+    Missing(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::{AudioLoopF32, Config};
+    use crate::graph::Graph;
+
+    use super::*;
+
+    #[test]
+    fn for_name() {
+        let context = init_test_context();
+        let graph = Graph::from_str(context.clone(), TABLE_PATCH);
+        graph.attach();
+
+        assert!(context.table("no-such-table").is_none());
+        let table = context.table("test-table").unwrap();
+        assert_eq!(table.name(), "test-table");
+        assert_eq!(table.len(), 8);
+    }
+
+    #[test]
+    fn read_write() {
+        let context = init_test_context();
+        let graph = Graph::from_str(context.clone(), TABLE_PATCH);
+        graph.attach();
+        let table = context.table("test-table").unwrap();
+
+        table.write_from(2, &[1.0, 2.0, 3.0]).unwrap();
+        let mut buffer = [0.0; 4];
+        table.read_into(1, &mut buffer).unwrap();
+        assert_eq!(buffer, [0.0, 1.0, 2.0, 3.0]);
+
+        assert_eq!(
+            table.write_from(6, &[1.0, 2.0, 3.0]),
+            Err(Error::OutOfBounds {
+                offset: 6,
+                length: 3,
+                table_len: 8
+            })
+        );
+        assert!(table.read_into(usize::MAX, &mut buffer).is_err());
+    }
+
+    #[test]
+    fn resize() {
+        let context = init_test_context();
+        let graph = Graph::from_str(context.clone(), TABLE_PATCH);
+        graph.attach();
+        let table = context.table("test-table").unwrap();
+
+        table.write_from(0, &[1.0; 8]).unwrap();
+        table.resize(10).unwrap();
+        assert_eq!(
+            table.to_vec(),
+            [1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0]
+        );
+
+        table.resize(2).unwrap();
+        assert_eq!(table.to_vec(), [1.0, 1.0]);
+
+        assert_eq!(table.resize(0), Err(Error::InvalidLength(0)));
+        let too_long = i32::MAX as usize + 1;
+        assert_eq!(table.resize(too_long), Err(Error::InvalidLength(too_long)));
+
+        table.set_buffer(&[3.0; 3]).unwrap();
+        assert_eq!(table.to_vec(), [3.0; 3]);
+    }
+
+    #[test]
+    fn missing() {
+        let context = init_test_context();
+        let graph = Graph::from_str(context.clone(), TABLE_PATCH);
+        let in_graph = graph.tables().remove(0);
+        assert_eq!(in_graph.name(), "test-table");
+        assert_eq!(in_graph.len(), 8);
+        // only the tables of attached graphs are registered with the context
+        assert!(context.table("test-table").is_none());
+
+        graph.attach();
+        let registered = context.table("test-table").unwrap();
+        graph.detach();
+        let missing = Err(Error::Missing("test-table".to_string()));
+        assert_eq!(registered.resize(4), missing);
+        assert_eq!(in_graph.len(), 8);
+
+        drop(graph);
+        assert_eq!(in_graph.write_from(0, &[1.0]), missing);
+        assert!(in_graph.to_vec().is_empty());
+    }
+
+    #[test]
+    fn audio_file() {
+        let context = init_test_context();
+        let graph = Graph::from_str(context.clone(), TABLE_PATCH);
+        graph.attach();
+        let table = context.table("test-table").unwrap();
+        let path = std::env::temp_dir().join("miller-table-audio-file-test.wav");
+
+        let expected = [0.0, 0.25, 0.5, 0.75, -0.25, -0.5, -0.75, -1.0];
+        table.write_from(0, &expected).unwrap();
+        assert_eq!(table.save_audio_file(&path, AudioFileFormat::Wav), Ok(8));
+
+        table.resize(2).unwrap();
+        assert_eq!(table.load_audio_file(&path, 0, false), Ok(2));
+        assert_eq!(table.to_vec(), expected[..2]);
+
+        assert_eq!(table.load_audio_file(&path, 0, true), Ok(8));
+        assert_eq!(table.to_vec(), expected);
+
+        assert_eq!(
+            table.load_audio_file(&path, 1, true),
+            Err(Error::ChannelOutOfRange(1))
+        );
+        assert!(matches!(
+            table.load_audio_file("test/no_such_file.wav", 0, true),
+            Err(Error::OpeningFile(_))
+        ));
+
+        // a 16-bit mono WAV file without samples
+        let mut empty = b"RIFF\x24\0\0\0WAVEfmt \x10\0\0\0\x01\0\x01\0".to_vec();
+        empty.extend_from_slice(b"\x44\xac\0\0\x88\x58\x01\0\x02\0\x10\0data\0\0\0\0");
+        std::fs::write(&path, empty).unwrap();
+        assert!(matches!(
+            table.load_audio_file(&path, 0, true),
+            Err(Error::InvalidFileLength(_))
+        ));
+        assert_eq!(table.to_vec(), expected);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn audio_file_format() {
+        assert_eq!(
+            AudioFileFormat::from_path("foo.WAV"),
+            Some(AudioFileFormat::Wav)
+        );
+        assert_eq!(
+            AudioFileFormat::from_path("foo.aif"),
+            Some(AudioFileFormat::Aiff)
+        );
+        assert_eq!(
+            AudioFileFormat::from_path("foo.flac"),
+            Some(AudioFileFormat::Flac)
+        );
+        assert_eq!(AudioFileFormat::from_path("foo.mp3"), None);
+    }
+
+    const TABLE_PATCH: &str = "#N canvas 0 0 450 300 12;\n#X obj 10 10 table test-table 8;\n";
+
+    fn init_test_context() -> Context<TestDispatcher, AudioLoopF32> {
+        Context::<TestDispatcher, AudioLoopF32>::new(Config::default()).unwrap()
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestDispatcher;
+
+    impl Dispatcher for TestDispatcher {
+        type UserData = ();
+    }
 }
@@ -0,0 +1,39 @@
+//! Plugin for the plugin loading tests. It implements the plugin interface by hand, so it can be
+//! built with plain `rustc` against `zengarden-raw`. Build it with `--cfg old_abi` to get a plugin
+//! with a stale ABI version.
+
+use std::os::raw::{c_char, c_void};
+
+use zengarden_raw::{zg_graph_new_object, ZGGraph};
+
+type Factory = unsafe extern "C" fn(*mut c_void, *mut c_void) -> *mut c_void;
+type Callback = unsafe extern "C" fn(*mut c_void, *const c_char, Factory);
+
+#[no_mangle]
+pub extern "C" fn miller_plugin_abi_version() -> u32 {
+    if cfg!(old_abi) {
+        1
+    } else {
+        2
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn miller_register_externals(registry: *mut c_void, callback: Callback) {
+    callback(
+        registry,
+        b"test_external\0".as_ptr() as *const c_char,
+        factory,
+    );
+    callback(
+        registry,
+        b"test_external~\0".as_ptr() as *const c_char,
+        factory,
+    );
+}
+
+// The externals print the messages they receive, as they're instantiated as `[print]` objects.
+unsafe extern "C" fn factory(_: *mut c_void, graph: *mut c_void) -> *mut c_void {
+    let text = b"print test_external\0".as_ptr() as *const c_char;
+    zg_graph_new_object(graph as *mut ZGGraph, text) as *mut c_void
+}
@@ -1,72 +1,201 @@
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
 use cpal::{
     traits::{DeviceTrait, HostTrait, StreamTrait},
-    StreamConfig,
+    SampleFormat, StreamConfig,
+};
+
+use miller_engine::context::{
+    AudioLoopF32, Config as ContextConfig, Context, ContextMixer, Dispatcher, Dither,
+    ResamplingLoop, Sample,
 };
+use miller_engine::graph::PatchHost;
+use miller_engine::lint;
+use miller_engine::project::Project;
 
-use miller_engine::context::{AudioLoopF32, Config as ContextConfig, Context, Dispatcher};
+/// Audio loop of the patch contexts.
+type PatchLoop = ResamplingLoop<AudioLoopF32>;
+
+/// How often the patch file is checked for changes with `--watch`.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("lint") {
+        match args.get(2) {
+            Some(patch) => process::exit(run_lint(patch)),
+            None => {
+                eprintln!("usage: {} lint <patch.pd>", args[0]);
+                process::exit(2);
+            }
+        }
+    }
+
+    let watch = args.iter().any(|arg| arg == "--watch");
+    // the patches run at the device sample rate unless it's set with `--sample-rate=<rate>`
+    let sample_rate = args.iter().find_map(|arg| {
+        let rate = arg.strip_prefix("--sample-rate=")?;
+        Some(rate.parse::<u32>().unwrap_or_else(|_| {
+            eprintln!("invalid sample rate: {}", rate);
+            process::exit(2);
+        }))
+    });
+    // the project file sets the context config, the audio device, and the patches and receivers
+    // to load in addition to the ones on the command line
+    let project = args.iter().find_map(|arg| {
+        let path = arg.strip_prefix("--config=")?;
+        Some(Project::from_file(path).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(2);
+        }))
+    });
+    let mut patches: Vec<PathBuf> = args
+        .iter()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .map(PathBuf::from)
+        .collect();
+    if let Some(project) = &project {
+        patches.extend(project.patch_files());
+    }
+
     let host = cpal::default_host();
-    let device = host
-        .default_output_device()
-        .expect("no output device available");
+    let device = match project.as_ref().and_then(|p| p.audio_device.as_ref()) {
+        Some(name) => host
+            .output_devices()
+            .expect("error while querying devices")
+            .find(|device| device.name().ok().as_ref() == Some(name))
+            .unwrap_or_else(|| {
+                eprintln!("no such output device: {}", name);
+                process::exit(2);
+            }),
+        None => host
+            .default_output_device()
+            .expect("no output device available"),
+    };
 
     let mut configs_range = device
         .supported_output_configs()
         .expect("error while querying configs");
-    let config: StreamConfig = configs_range
+    let supported_config = configs_range
         .next()
         .expect("no supported config?!")
-        .with_max_sample_rate()
-        .into();
+        .with_max_sample_rate();
+    let sample_format = supported_config.sample_format();
+    let config: StreamConfig = supported_config.into();
 
-    let context_config = ContextConfig::default()
-        .with_sample_rate(config.sample_rate.0)
-        .with_in_ch_num(config.channels)
-        .with_out_ch_num(config.channels);
+    // the contexts are resampled to the device rate if it's different
+    let context_config = match &project {
+        Some(project) => project
+            .context_config()
+            .with_sample_rate(sample_rate.unwrap_or(project.context.sample_rate)),
+        None => ContextConfig::default()
+            .with_sample_rate(sample_rate.unwrap_or(config.sample_rate.0))
+            .with_in_ch_num(config.channels)
+            .with_out_ch_num(config.channels),
+    }
+    .with_device_sample_rate(config.sample_rate.0);
 
-    let context =
-        ContextWrapper(Context::<ContextDispatcher, AudioLoopF32>::new(context_config).unwrap());
-
-    // unsafe {
-    // let dir = CString::new("/Users/alestsurko/Desktop/miller/").unwrap();
-    // let filename = CString::new("test.pd").unwrap();
-    // let graph = zg_context_new_graph_from_file(
-    // *(context.raw_context.clone().read().unwrap()),
-    // dir.as_ptr(),
-    // filename.as_ptr(),
-    // );
-    // zg_graph_attach(graph);
-    // }
-
-    let stream = device
+    // every patch runs in its own context, and the contexts are mixed into the device output
+    let mut mixer = ContextMixer::new(config.channels, config.channels);
+
+    // keep the patches loaded as long as the audio is playing
+    let mut _hosts = Vec::new();
+    let mut _watchers = Vec::new();
+    for patch in patches {
+        let context = Context::<ContextDispatcher, PatchLoop>::new(context_config.clone())
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(2);
+            });
+        if let Some(project) = &project {
+            project.register_receivers(&context);
+        }
+        let patch_host = PatchHost::new(context.clone(), patch).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+        mixer.add(context).unwrap();
+
+        if watch {
+            _watchers.push(patch_host.watch(WATCH_INTERVAL, |result| match result {
+                Ok(()) => println!("Patch reloaded"),
+                Err(err) => eprintln!("{}", err),
+            }));
+        } else {
+            _hosts.push(patch_host);
+        }
+    }
+
+    let mixer = MixerWrapper(mixer);
+    let stream = match sample_format {
+        SampleFormat::F32 => build_stream::<f32>(&device, &config, mixer),
+        SampleFormat::I16 => build_stream::<i16>(&device, &config, mixer),
+        SampleFormat::U16 => build_stream::<u16>(&device, &config, mixer),
+    };
+
+    stream.play().unwrap();
+
+    loop {}
+}
+
+/// Build the output stream, converting the mix to the device sample format.
+fn build_stream<S: Sample + cpal::Sample>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    mut mixer: MixerWrapper,
+) -> cpal::Stream {
+    let channels = config.channels as usize;
+    let in_frame = vec![0.0; channels];
+    let mut dither = Dither::default();
+
+    device
         .build_output_stream(
-            &config,
-            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                let mut offset = 0;
-
-                while offset < data.len() {
-                    let frame = context.0.next_frame(&[0.0, 0.0]).unwrap();
-                    let end = offset + frame.len();
-                    data[offset..end].copy_from_slice(&frame);
-                    offset = end;
+            config,
+            move |data: &mut [S], _: &cpal::OutputCallbackInfo| {
+                for frame in data.chunks_mut(channels) {
+                    let mix = mixer.0.next_frame(&in_frame).unwrap();
+                    for (sample, mixed) in frame.iter_mut().zip(mix) {
+                        *sample = S::from_f32(*mixed, &mut dither);
+                    }
                 }
             },
             move |err| {
                 eprintln!("Audio I/O error: {}", err);
             },
         )
-        .unwrap();
-
-    stream.play().unwrap();
+        .unwrap()
+}
 
-    loop {}
+/// Print the lint issues of the patch. Returns the exit code: 0 if there are no issues.
+fn run_lint(patch: &str) -> i32 {
+    let context =
+        Context::<ContextDispatcher, AudioLoopF32>::new(ContextConfig::default()).unwrap();
+    match lint::lint(&context, patch) {
+        Ok(issues) => {
+            for issue in &issues {
+                println!("{}", issue);
+            }
+            if issues.is_empty() {
+                0
+            } else {
+                1
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            2
+        }
+    }
 }
 
-struct ContextWrapper(Context<ContextDispatcher, AudioLoopF32>);
+struct MixerWrapper(ContextMixer<ContextDispatcher, PatchLoop>);
 
-unsafe impl Send for ContextWrapper {}
-unsafe impl Sync for ContextWrapper {}
+unsafe impl Send for MixerWrapper {}
+unsafe impl Sync for MixerWrapper {}
 
 #[derive(Debug, Clone)]
 struct ContextDispatcher;
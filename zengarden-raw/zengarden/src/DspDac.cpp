@@ -58,16 +58,29 @@ void DspDac::processSignal(DspObject *dspObject, int fromIndex, int toIndex) {
         // allow fallthrough
     }
     case 2: {
-        ArrayArithmetic::add(d->dspBufferAtOutlet[1], d->dspBufferAtInlet[1],
-                             d->dspBufferAtOutlet[1], 0, toIndex);
+        d->addToOutput(d->dspBufferAtOutlet[1], d->dspBufferAtInlet[1],
+                       toIndex);
         // allow fallthrough
     }
     case 1: {
-        ArrayArithmetic::add(d->dspBufferAtOutlet[0], d->dspBufferAtInlet[0],
-                             d->dspBufferAtOutlet[0], 0, toIndex);
+        d->addToOutput(d->dspBufferAtOutlet[0], d->dspBufferAtInlet[0],
+                       toIndex);
         // allow fallthrough
     }
     case 0:
         break;
     }
 }
+
+void DspDac::addToOutput(float *output, float *input, int toIndex) {
+    if (!graph->isFadingOutput()) {
+        // the graph is either at full gain or silent
+        if (graph->getOutputGain(0) == 1.0f) {
+            ArrayArithmetic::add(output, input, output, 0, toIndex);
+        }
+    } else {
+        for (int i = 0; i < toIndex; i++) {
+            output[i] += input[i] * graph->getOutputGain(i);
+        }
+    }
+}
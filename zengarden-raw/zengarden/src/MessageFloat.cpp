@@ -35,6 +35,14 @@ MessageFloat::~MessageFloat() {
     // nothing to do
 }
 
+std::string MessageFloat::getState() {
+    char state[32];
+    snprintf(state, sizeof(state), "%.9g", constant);
+    return string(state);
+}
+
+void MessageFloat::setState(const char *state) { constant = atof(state); }
+
 void MessageFloat::processMessage(int inletIndex, PdMessage *message) {
     switch (inletIndex) {
     case 0: {
@@ -35,6 +35,8 @@ class MessageFloat : public MessageObject {
 
     static const char *getObjectLabel();
     std::string toString();
+    std::string getState();
+    void setState(const char *state);
 
   private:
     void processMessage(int inletIndex, PdMessage *message);
@@ -43,9 +43,19 @@ MessageObject *MessageMessageBox::newObject(PdMessage *initString,
  */
 MessageMessageBox::MessageMessageBox(char *initString, PdGraph *graph)
     : MessageObject(1, 1, graph) {
+    setContent(initString);
+}
+
+MessageMessageBox::~MessageMessageBox() { clearContent(); }
+
+void MessageMessageBox::setContent(const char *initString) {
+    clearContent();
+
     // parse the entire initialisation string
     vector<string> messageInitListAll =
         StaticUtils::tokenizeString(initString, "\\;");
+    if (messageInitListAll.empty())
+        return;
 
     // parse the first "message" for individual messages that should be sent
     // from the outlet
@@ -91,11 +101,12 @@ MessageMessageBox::MessageMessageBox(char *initString, PdGraph *graph)
     }
 }
 
-MessageMessageBox::~MessageMessageBox() {
+void MessageMessageBox::clearContent() {
     // delete the message list and all of the messages in it
     for (int i = 0; i < localMessageList.size(); i++) {
         localMessageList.at(i)->freeMessage();
     }
+    localMessageList.clear();
 
     // delete the remote message list
     for (int i = 0; i < remoteMessageList.size(); i++) {
@@ -103,6 +114,38 @@ MessageMessageBox::~MessageMessageBox() {
         free(namedDestination.first);
         namedDestination.second->freeMessage();
     }
+    remoteMessageList.clear();
+}
+
+/*
+ * The state is the content of the message box in the form it's created with,
+ * e.g. "1 \, 2 \; foo bang".
+ */
+string MessageMessageBox::getState() {
+    string state;
+    for (int i = 0; i < localMessageList.size(); i++) {
+        if (i > 0)
+            state += " \\, ";
+        appendMessage(&state, localMessageList.at(i));
+    }
+    for (int i = 0; i < remoteMessageList.size(); i++) {
+        MessageNamedDestination namedDestination = remoteMessageList.at(i);
+        state += state.empty() ? "\\; " : " \\; ";
+        state += namedDestination.first;
+        state += " ";
+        appendMessage(&state, namedDestination.second);
+    }
+    return state;
+}
+
+void MessageMessageBox::setState(const char *state) { setContent(state); }
+
+void MessageMessageBox::appendMessage(string *str, PdMessage *message) {
+    if (message->getNumElements() > 0) {
+        char *messageString = message->toString();
+        *str += messageString;
+        free(messageString);
+    }
 }
 
 void MessageMessageBox::processMessage(int inletIndex, PdMessage *message) {
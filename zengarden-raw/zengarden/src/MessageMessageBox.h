@@ -37,9 +37,14 @@ class MessageMessageBox : public MessageObject {
 
     static const char *getObjectLabel();
     std::string toString();
+    std::string getState();
+    void setState(const char *state);
 
   private:
     void processMessage(int inletIndex, PdMessage *message);
+    void setContent(const char *initString);
+    void clearContent();
+    static void appendMessage(std::string *str, PdMessage *message);
 
     vector<PdMessage *> localMessageList;
     vector<MessageNamedDestination> remoteMessageList;
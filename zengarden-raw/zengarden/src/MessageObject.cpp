@@ -22,12 +22,15 @@
 
 #include "MessageObject.h"
 #include "PdGraph.h"
+#include <atomic>
 
 MessageObject::MessageObject(int numMessageInlets, int numMessageOutlets,
                              PdGraph *graph) {
     this->graph = graph;
     this->isOrdered = false;
     canvasX = canvasY = 0.0f;
+    static std::atomic<unsigned long long> nextSerial(0);
+    serial = nextSerial++;
 
     // initialise incoming connections list
     incomingMessageConnections = vector<list<ObjectLetPair>>(numMessageInlets);
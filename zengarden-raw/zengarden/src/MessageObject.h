@@ -125,6 +125,36 @@ class MessageObject {
     static const char *getObjectLabel() { return "obj"; }
     virtual string toString() { return string(getObjectLabel()); }
 
+    /**
+     * Returns the state of the object which isn't part of its creation
+     * arguments, e.g. the value stored in a [float], in the form accepted by
+     * setState(). Stateless objects return an empty string.
+     */
+    virtual string getState() { return string(); }
+
+    /** Restores a state returned by getState(). */
+    virtual void setState(const char *state) {}
+
+    /** Returns the name of a [value], or NULL for the other objects. */
+    virtual const char *getValueName() { return NULL; }
+
+    /**
+     * Returns the text the object was created from, e.g. "osc~ 440", with any
+     * $ arguments unresolved. The text is empty for objects which ZenGarden
+     * creates implicitly, such as the +~~ objects.
+     */
+    const char *getCreationText() { return creationText.c_str(); }
+
+    /** Sets the text returned by getCreationText(). */
+    void setCreationText(const string &text) { creationText = text; }
+
+    /**
+     * Returns a number which is unique among all objects created by the
+     * process, so an object can be told apart from an earlier object which
+     * was allocated at the same address.
+     */
+    unsigned long long getSerial() { return serial; }
+
     virtual ObjectType getObjectType();
 
     /** Returns <code>true</code> if this object processes audio,
@@ -191,6 +221,12 @@ class MessageObject {
     /** A flag indicating that this object has already been considered when
      * ordering the process tree. */
     bool isOrdered;
+
+    /** The text the object was created from. */
+    string creationText;
+
+    /** See getSerial(). */
+    unsigned long long serial;
 };
 
 #endif // _MESSAGE_OBJECT_H_
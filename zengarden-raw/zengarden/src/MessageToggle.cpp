@@ -39,6 +39,19 @@ MessageToggle::~MessageToggle() {
 
 const char *MessageToggle::getObjectLabel() { return "toggle"; }
 
+std::string MessageToggle::getState() {
+    char state[32];
+    snprintf(state, sizeof(state), "%.9g", isOn ? onOutput : 0.0f);
+    return string(state);
+}
+
+void MessageToggle::setState(const char *state) {
+    float value = atof(state);
+    isOn = (value != 0.0f);
+    if (isOn)
+        onOutput = value;
+}
+
 void MessageToggle::processMessage(int inletIndex, PdMessage *message) {
     switch (message->getType(0)) {
     case FLOAT: {
@@ -34,6 +34,8 @@ class MessageToggle : public MessageObject {
     ~MessageToggle();
 
     static const char *getObjectLabel();
+    std::string getState();
+    void setState(const char *state);
 
   private:
     void processMessage(int inletIndex, PdMessage *message);
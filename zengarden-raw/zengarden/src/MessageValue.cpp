@@ -39,6 +39,19 @@ MessageValue::MessageValue(PdMessage *initMessage, PdGraph *graph)
 
 MessageValue::~MessageValue() { free(name); }
 
+std::string MessageValue::getState() {
+    if (name == NULL)
+        return string();
+    char state[32];
+    snprintf(state, sizeof(state), "%.9g", graph->getValueForName(name));
+    return string(state);
+}
+
+void MessageValue::setState(const char *state) {
+    if (name != NULL)
+        graph->setValueForName(name, atof(state));
+}
+
 void MessageValue::processMessage(int inletIndex, PdMessage *message) {
     switch (message->getType(0)) {
     case FLOAT: {
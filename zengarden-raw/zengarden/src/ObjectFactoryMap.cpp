@@ -419,10 +419,12 @@ void ObjectFactoryMap::registerExternalObject(
     const char *objectLabel,
     MessageObject *(*newObject)(PdMessage *, PdGraph *)) {
     objectFactoryMap[string(objectLabel)] = newObject;
+    externalObjectLabels.insert(string(objectLabel));
 }
 
 void ObjectFactoryMap::unregisterExternalObject(const char *objectLabel) {
     objectFactoryMap.erase(string(objectLabel));
+    externalObjectLabels.erase(string(objectLabel));
 }
 
 MessageObject *ObjectFactoryMap::newObject(const char *objectLabel,
@@ -432,3 +434,20 @@ MessageObject *ObjectFactoryMap::newObject(const char *objectLabel,
         objectFactoryMap[string(objectLabel)];
     return (newObject != NULL) ? newObject(initMessage, graph) : NULL;
 }
+
+list<const char *> ObjectFactoryMap::getObjectLabels() {
+    list<const char *> objectLabels;
+    map<string, MessageObject *(*)(PdMessage *, PdGraph *)>::iterator it;
+    for (it = objectFactoryMap.begin(); it != objectFactoryMap.end(); it++) {
+        // newObject() leaves NULL entries behind for unknown labels
+        if (it->second != NULL) {
+            objectLabels.push_back(it->first.c_str());
+        }
+    }
+    return objectLabels;
+}
+
+bool ObjectFactoryMap::isExternalObject(const char *objectLabel) {
+    return externalObjectLabels.find(string(objectLabel)) !=
+           externalObjectLabels.end();
+}
@@ -23,7 +23,9 @@
 #ifndef _OBJECT_FACTORY_MAP_H_
 #define _OBJECT_FACTORY_MAP_H_
 
+#include <list>
 #include <map>
+#include <set>
 #include <string>
 using namespace std;
 
@@ -44,8 +46,15 @@ class ObjectFactoryMap {
     MessageObject *newObject(const char *objectLable, PdMessage *initMessage,
                              PdGraph *graph);
 
+    /** Returns the labels of all objects which can be instantiated. */
+    list<const char *> getObjectLabels();
+
+    /** Returns true if the label refers to a registered external object. */
+    bool isExternalObject(const char *objectLabel);
+
   private:
     map<string, MessageObject *(*)(PdMessage *, PdGraph *)> objectFactoryMap;
+    set<string> externalObjectLabels;
 };
 
 #endif // _OBJECT_FACTORY_MAP_H_
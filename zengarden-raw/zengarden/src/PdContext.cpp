@@ -118,6 +118,14 @@ void PdContext::unregisterExternalObject(const char *objectLabel) {
     objectFactoryMap->unregisterExternalObject(objectLabel);
 }
 
+list<const char *> PdContext::getObjectLabels() {
+    return objectFactoryMap->getObjectLabels();
+}
+
+bool PdContext::isExternalObject(const char *objectLabel) {
+    return objectFactoryMap->isExternalObject(objectLabel);
+}
+
 #pragma mark - Get Context Attributes
 
 int PdContext::getNumInputChannels() { return numInputChannels; }
@@ -196,6 +204,10 @@ void PdContext::process(float *inputBuffers, float *outputBuffers) {
     }
     }
 
+    for (auto graph : graphList) {
+        graph->advanceOutputFade(blockSize);
+    }
+
     blockStartTimestamp = nextBlockStartTimestamp;
 
     // copy the output audio to the given buffer
@@ -440,6 +452,23 @@ void PdContext::registerTable(MessageTable *table) {
     }
 }
 
+void PdContext::unregisterTable(MessageTable *table) {
+    // a table which failed to register because of a duplicate name isn't in
+    // the list, and the receivers refer to the registered one
+    if (getTable(table->getName()) != table)
+        return;
+    tableList.remove(table);
+
+    for (list<TableReceiverInterface *>::iterator it =
+             tableReceiverList.begin();
+         it != tableReceiverList.end(); it++) {
+        if ((*it)->getName() == NULL)
+            continue;
+        if (!strcmp((*it)->getName(), table->getName()))
+            (*it)->setTable(NULL);
+    }
+}
+
 MessageTable *PdContext::getTable(const char *name) {
     for (list<MessageTable *>::iterator it = tableList.begin();
          it != tableList.end(); it++) {
@@ -74,6 +74,9 @@ class PdContext {
     void attachGraph(PdGraph *graph);
     void unattachGraph(PdGraph *graph);
 
+    /** Returns the number of graphs attached to this context. */
+    unsigned int getNumGraphs() { return graphList.size(); }
+
     void process(float *inputBuffers, float *outputBuffers);
 
     void lock() {
@@ -131,6 +134,7 @@ class PdContext {
     void registerDspCatch(DspCatch *dspCatch);
 
     void registerTable(MessageTable *table);
+    void unregisterTable(MessageTable *table);
 
     void registerTableReceiver(TableReceiverInterface *tableReceiver);
     void unregisterTableReceiver(TableReceiverInterface *tableReceiver);
@@ -242,6 +246,12 @@ class PdContext {
     /** Unregister an object label. */
     void unregisterExternalObject(const char *objectLabel);
 
+    /** Returns the labels of all objects which the context can instantiate. */
+    list<const char *> getObjectLabels();
+
+    /** Returns true if the label refers to a registered external object. */
+    bool isExternalObject(const char *objectLabel);
+
     BufferPool *getBufferPool() { return bufferPool; }
 
     PdAbstractionDataBase *getAbstractionDataBase();
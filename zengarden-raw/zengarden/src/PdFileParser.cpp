@@ -118,6 +118,11 @@ PdGraph *PdFileParser::execute(PdContext *context) {
     return execute(NULL, NULL, context, true);
 }
 
+PdGraph *PdFileParser::executeAbstraction(PdMessage *initMsg, PdGraph *graph,
+                                          PdContext *context) {
+    return execute(initMsg, graph, context, false);
+}
+
 PdGraph *PdFileParser::execute(PdMessage *initMsg, PdGraph *graph,
                                PdContext *context, bool isSubPatch) {
 #define OBJECT_LABEL_RESOLUTION_BUFFER_LENGTH 32
@@ -169,6 +174,10 @@ PdGraph *PdFileParser::execute(PdMessage *initMsg, PdGraph *graph,
                         newGraph =
                             new PdGraph(graph->getArguments(), graph, context,
                                         graph->getGraphId(), canvasName);
+                        newGraph->setCreationText(
+                            string("pd") +
+                            (canvasName != NULL ? string(" ") + canvasName
+                                                : string()));
                     } else {
                         // a graph made as an abstraction
                         newGraph = new PdGraph(initMsg, graph, context,
@@ -211,6 +220,11 @@ PdGraph *PdFileParser::execute(PdMessage *initMsg, PdGraph *graph,
                 char *objectInitString =
                     strtok_r(NULL, ";\r",
                              &saveptr); // get the object initialisation string
+                // keep the text before the arguments are resolved in place
+                string creationText = objectLabel;
+                if (objectInitString != NULL) {
+                    creationText += string(" ") + objectInitString;
+                }
                 char resBuffer[RESOLUTION_BUFFER_LENGTH];
                 initMessage->initWithSARb(
                     INIT_MESSAGE_MAX_ELEMENTS, objectInitString,
@@ -264,6 +278,10 @@ PdGraph *PdFileParser::execute(PdMessage *initMsg, PdGraph *graph,
                     // registrations
                     graph->addObject(canvasX, canvasY, messageObject);
                 }
+                // a failed abstraction returns the current graph
+                if (messageObject != NULL && messageObject != graph) {
+                    messageObject->setCreationText(creationText);
+                }
             } else if (!strcmp(objectType, "msg")) {
                 float canvasX = (float)atoi(strtok_r(
                     NULL, " ", &saveptr)); // read the first canvas coordinate
@@ -272,9 +290,21 @@ PdGraph *PdFileParser::execute(PdMessage *initMsg, PdGraph *graph,
                 char *objectInitString = strtok_r(
                     NULL, "\n\r", &saveptr); // get the message initialisation
                                              // string (including trailing ';')
+                // the creation text doesn't include the terminating ';'
+                string content =
+                    (objectInitString != NULL) ? objectInitString : "";
+                size_t end = content.find_last_not_of(" ");
+                if (end != string::npos && content[end] == ';' &&
+                    (end == 0 || content[end - 1] != '\\')) {
+                    content = content.substr(0, end);
+                }
+                content = content.substr(0, content.find_last_not_of(" ") + 1);
                 initMessage->initWithTimestampAndSymbol(0.0, objectInitString);
                 MessageObject *messageObject = context->newObject(
                     MessageMessageBox::getObjectLabel(), initMessage, graph);
+                messageObject->setCreationText(
+                    string(MessageMessageBox::getObjectLabel()) +
+                    (content.empty() ? "" : " " + content));
                 graph->addObject(canvasX, canvasY, messageObject);
             } else if (!strcmp(objectType, "connect")) {
                 int fromObjectIndex = atoi(strtok_r(NULL, " ", &saveptr));
@@ -290,6 +320,7 @@ PdGraph *PdFileParser::execute(PdMessage *initMsg, PdGraph *graph,
                 MessageObject *messageObject = context->newObject(
                     MessageFloat::getObjectLabel(), initMessage,
                     graph); // defines a number box
+                messageObject->setCreationText(MessageFloat::getObjectLabel());
                 graph->addObject(canvasX, canvasY, messageObject);
             } else if (!strcmp(objectType, "symbolatom")) {
                 float canvasX = (float)atoi(strtok_r(NULL, " ", &saveptr));
@@ -297,6 +328,7 @@ PdGraph *PdFileParser::execute(PdMessage *initMsg, PdGraph *graph,
                 initMessage->initWithTimestampAndSymbol(0.0, NULL);
                 MessageObject *messageObject = context->newObject(
                     MessageSymbol::getObjectLabel(), initMessage, graph);
+                messageObject->setCreationText(MessageSymbol::getObjectLabel());
                 graph->addObject(canvasX, canvasY, messageObject);
             } else if (!strcmp(objectType, "restore")) {
                 // the graph is finished being defined
@@ -311,6 +343,9 @@ PdGraph *PdFileParser::execute(PdMessage *initMsg, PdGraph *graph,
                 initMessage->initWithTimestampAndSymbol(0.0, comment);
                 MessageObject *messageText = context->newObject(
                     MessageText::getObjectLabel(), initMessage, graph);
+                messageText->setCreationText(
+                    string(MessageText::getObjectLabel()) +
+                    (comment != NULL ? string(" ") + comment : string()));
                 graph->addObject(canvasX, canvasY, messageText);
             } else if (!strcmp(objectType, "declare")) {
                 // set environment for loading patch
@@ -339,6 +374,10 @@ PdGraph *PdFileParser::execute(PdMessage *initMsg, PdGraph *graph,
                                           RESOLUTION_BUFFER_LENGTH);
                 lastArrayCreated = reinterpret_cast<MessageTable *>(
                     context->newObject("table", initMessage, graph));
+                char arrayText[RESOLUTION_BUFFER_LENGTH];
+                snprintf(arrayText, sizeof(arrayText), "table %s %g",
+                         initMessage->getSymbol(0), initMessage->getFloat(1));
+                lastArrayCreated->setCreationText(arrayText);
                 lastArrayCreatedIndex = 0;
                 graph->addObject(0, 0, lastArrayCreated);
                 context->printStd(
@@ -58,6 +58,10 @@ PdGraph::PdGraph(PdMessage *initMessage, PdGraph *parentGraph,
     isAttachedToContext = false;
     switched = true; // graphs are switched on by default
     processFunction = &processGraph;
+    // the output is at full gain
+    fadePosition = 1.0f;
+    fadeIncrement = 0.0f;
+    fadingIn = true;
 
     // initialise the graph arguments
     this->graphId = graphId;
@@ -317,6 +321,12 @@ void PdGraph::unregisterObject(MessageObject *messageObject) {
             (RemoteMessageReceiver *)messageObject);
         break;
     }
+    case MESSAGE_TABLE: {
+        context->unregisterRemoteMessageReceiver(
+            reinterpret_cast<RemoteMessageReceiver *>(messageObject));
+        context->unregisterTable((MessageTable *)messageObject);
+        break;
+    }
     case MESSAGE_TABLE_READ: {
         context->unregisterTableReceiver((MessageTableRead *)messageObject);
         break;
@@ -843,6 +853,8 @@ int PdGraph::getBlockSize() { return blockSizeInt; }
 
 bool PdGraph::isRootGraph() { return (parentGraph == NULL); }
 
+bool PdGraph::isAttached() { return isAttachedToContext; }
+
 MessageTable *PdGraph::getTable(char *name) { return context->getTable(name); }
 
 ConnectionType PdGraph::getConnectionType(int outletIndex) {
@@ -875,6 +887,50 @@ void PdGraph::setSwitch(bool switched) { this->switched = switched; }
 
 bool PdGraph::isSwitchedOn() { return switched; }
 
+void PdGraph::fadeOutput(float numSamples, bool fadeIn) {
+    if (parentGraph != NULL) {
+        parentGraph->fadeOutput(numSamples, fadeIn);
+        return;
+    }
+    // find the position on the new curve which gives the current gain
+    float gain = fminf(1.0f, getOutputGain(0));
+    fadePosition = (fadeIn ? asinf(gain) : acosf(gain)) / (float)M_PI_2;
+    fadingIn = fadeIn;
+    if (numSamples > 0.0f && fadePosition < 1.0f) {
+        fadeIncrement = 1.0f / numSamples;
+    } else {
+        fadePosition = 1.0f;
+        fadeIncrement = 0.0f;
+    }
+}
+
+bool PdGraph::isFadingOutput() {
+    return (parentGraph != NULL) ? parentGraph->isFadingOutput()
+                                 : fadeIncrement > 0.0f;
+}
+
+float PdGraph::getOutputGain(int blockIndex) {
+    if (parentGraph != NULL) {
+        return parentGraph->getOutputGain(blockIndex);
+    }
+    float position = fadePosition + fadeIncrement * blockIndex;
+    if (position >= 1.0f) {
+        return fadingIn ? 1.0f : 0.0f;
+    }
+    return fadingIn ? sinf(position * (float)M_PI_2)
+                    : cosf(position * (float)M_PI_2);
+}
+
+void PdGraph::advanceOutputFade(int numSamples) {
+    if (fadeIncrement > 0.0f) {
+        fadePosition += fadeIncrement * numSamples;
+        if (fadePosition >= 1.0f) {
+            fadePosition = 1.0f;
+            fadeIncrement = 0.0f;
+        }
+    }
+}
+
 void PdGraph::setValueForName(const char *name, float constant) {
     context->setValueForName(name, constant);
 }
@@ -887,8 +943,18 @@ unsigned int PdGraph::getNumInlets() { return inletList.size(); }
 
 unsigned int PdGraph::getNumOutlets() { return outletList.size(); }
 
+ConnectionType PdGraph::getInletConnectionType(unsigned int inletIndex) {
+    if (inletIndex < inletList.size() &&
+        inletList[inletIndex]->getObjectType() == DSP_INLET) {
+        return DSP;
+    }
+    return MESSAGE;
+}
+
 PdContext *PdGraph::getContext() { return context; }
 
 list<MessageObject *> PdGraph::getNodeList() { return nodeList; }
 
+list<DspObject *> PdGraph::getDspNodeList() { return dspNodeList; }
+
 BufferPool *PdGraph::getBufferPool() { return context->getBufferPool(); }
@@ -23,12 +23,16 @@
 #if __APPLE__
 #include <Accelerate/Accelerate.h>
 #endif
+#include "MessageMessageBox.h"
 #include "MessageTable.h"
+#include "MessageText.h"
 #include "PdAbstractionDataBase.h"
 #include "PdContext.h"
 #include "PdFileParser.h"
 #include "PdGraph.h"
 #include "ZenGarden.h"
+#include <limits.h>
+#include <sndfile.h>
 #include <string.h>
 
 /*
@@ -39,25 +43,109 @@ void zg_remove_graph(PdContext *context, PdGraph *graph) {
 
 ZGObject *zg_graph_add_new_object(PdGraph *graph, const char *objectString,
                                   float canvasX, float canvasY) {
+    MessageObject *messageObject = zg_graph_new_object(graph, objectString);
+    if (messageObject != NULL) {
+        graph->addObject(canvasX, canvasY, messageObject);
+    }
+
+    return messageObject;
+}
+
+ZGObject *zg_graph_new_object(ZGGraph *graph, const char *objectString) {
     char *objectStringCopy = StaticUtils::copyString(objectString);
     char *saveptr;
     char *objectLabel = strtok_r(objectStringCopy, " ;", &saveptr);
-    char *initString = strtok_r(NULL, ";", &saveptr);
+    if (objectLabel != NULL && !strcmp(objectLabel, "pd")) {
+        // subgraphs aren't made by the object factory, so an empty one is
+        // created like with zg_graph_add_new_subgraph()
+        char *name = strtok_r(NULL, ";", &saveptr);
+        PdGraph *subgraph = new PdGraph(
+            graph->getArguments(), graph, graph->getContext(),
+            graph->getGraphId(), (name != NULL) ? name : "");
+        subgraph->setCreationText(objectString);
+        free(objectStringCopy);
+        return subgraph;
+    }
     char resolutionBuffer[256];
     PdMessage *initMessage = PD_MESSAGE_ON_STACK(32);
-    initMessage->initWithSARb(32, initString, graph->getArguments(),
-                              resolutionBuffer, 256);
+    if (objectLabel != NULL &&
+        (!strcmp(objectLabel, MessageMessageBox::getObjectLabel()) ||
+         !strcmp(objectLabel, MessageText::getObjectLabel()))) {
+        // message boxes and comments take the rest of the text, including any
+        // escaped separators, as a single symbol like in PdFileParser
+        initMessage->initWithTimestampAndSymbol(0.0, saveptr);
+    } else {
+        char *initString = strtok_r(NULL, ";", &saveptr);
+        initMessage->initWithSARb(32, initString, graph->getArguments(),
+                                  resolutionBuffer, 256);
+    }
     MessageObject *messageObject =
-        graph->getContext()->newObject(objectLabel, initMessage, graph);
+        (objectLabel != NULL)
+            ? graph->getContext()->newObject(objectLabel, initMessage, graph)
+            : NULL;
     free(objectStringCopy);
 
     if (messageObject != NULL) {
-        graph->addObject(canvasX, canvasY, messageObject);
+        messageObject->setCreationText(objectString);
     }
 
     return messageObject;
 }
 
+void zg_graph_add_object(ZGGraph *graph, ZGObject *object, float canvasX,
+                         float canvasY) {
+    graph->addObject(canvasX, canvasY, object);
+}
+
+ZGGraph *zg_graph_add_new_subgraph(ZGGraph *graph, const char *name,
+                                   float canvasX, float canvasY) {
+    PdGraph *subgraph = new PdGraph(graph->getArguments(), graph,
+                                    graph->getContext(), graph->getGraphId(),
+                                    name);
+    subgraph->setCreationText(string("pd ") + name);
+    graph->addObject(canvasX, canvasY, subgraph);
+    return subgraph;
+}
+
+ZGGraph *zg_graph_add_new_abstraction(ZGGraph *graph, const char *path,
+                                      const char *arguments, float canvasX,
+                                      float canvasY) {
+    char *fullPath = graph->resolveFullPath(path);
+    if (fullPath == NULL) {
+        return NULL;
+    }
+    string fullPathString = string(fullPath);
+    free(fullPath);
+    size_t separator = fullPathString.find_last_of('/');
+    string directory = fullPathString.substr(0, separator + 1);
+    string filename = fullPathString.substr(separator + 1);
+
+    char *argumentsCopy =
+        (arguments != NULL) ? StaticUtils::copyString(arguments) : NULL;
+    char resolutionBuffer[256];
+    PdMessage *initMessage = PD_MESSAGE_ON_STACK(32);
+    initMessage->initWithSARb(32, argumentsCopy, graph->getArguments(),
+                              resolutionBuffer, 256);
+
+    PdFileParser *parser = new PdFileParser(directory, filename);
+    PdGraph *abstraction =
+        parser->executeAbstraction(initMessage, graph, graph->getContext());
+    delete parser;
+    free(argumentsCopy);
+
+    if (abstraction == graph) {
+        // the file couldn't be parsed, so no graph was created
+        return NULL;
+    }
+    string text = path;
+    if (arguments != NULL && arguments[0] != '\0') {
+        text += string(" ") + arguments;
+    }
+    abstraction->setCreationText(text);
+    abstraction->setCanvasPosition(canvasX, canvasY);
+    return abstraction;
+}
+
 void zg_graph_delete(ZGGraph *graph) {
     if (graph != NULL) {
         /*
@@ -75,6 +163,8 @@ void zg_object_remove(MessageObject *object) {
     object->getGraph()->removeObject(object);
 }
 
+void zg_object_delete(ZGObject *object) { delete object; }
+
 ZGConnectionType zg_object_get_connection_type(ZGObject *object,
                                                unsigned int outletIndex) {
     switch (object->getConnectionType(outletIndex)) {
@@ -86,6 +176,24 @@ ZGConnectionType zg_object_get_connection_type(ZGObject *object,
     }
 }
 
+ZGObjectType zg_object_get_type(ZGObject *object) {
+    return (ZGObjectType)object->getObjectType();
+}
+
+ZGConnectionType zg_object_get_inlet_type(ZGObject *object,
+                                          unsigned int inletIndex) {
+    if (object->getObjectType() == OBJECT_PD) {
+        PdGraph *graph = (PdGraph *)object;
+        return (graph->getInletConnectionType(inletIndex) == DSP)
+                   ? ZG_CONNECTION_DSP
+                   : ZG_CONNECTION_MESSAGE;
+    } else if (object->doesProcessAudio() &&
+               inletIndex < ((DspObject *)object)->getNumDspInlets()) {
+        return ZG_CONNECTION_DSP;
+    }
+    return ZG_CONNECTION_MESSAGE;
+}
+
 unsigned int zg_object_get_num_inlets(ZGObject *object) {
     return (object != NULL) ? object->getNumInlets() : 0;
 }
@@ -130,6 +238,14 @@ const char *zg_object_get_label(ZGObject *object) {
     return object->getObjectLabel();
 }
 
+const char *zg_object_get_creation_text(ZGObject *object) {
+    return object->getCreationText();
+}
+
+unsigned long long zg_object_get_serial(ZGObject *object) {
+    return object->getSerial();
+}
+
 void zg_object_send_message(MessageObject *object, unsigned int inletIndex,
                             ZGMessage *message) {
     object->getGraph()->lockContextIfAttached();
@@ -153,6 +269,27 @@ char *zg_object_to_string(ZGObject *object) {
     return ret;
 }
 
+char *zg_object_get_state(ZGObject *object) {
+    object->getGraph()->lockContextIfAttached();
+    string state = object->getState();
+    object->getGraph()->unlockContextIfAttached();
+    if (state.empty())
+        return NULL;
+    char *ret = (char *)malloc((state.size() + 1) * sizeof(char));
+    strcpy(ret, state.c_str());
+    return ret;
+}
+
+void zg_object_set_state(ZGObject *object, const char *state) {
+    object->getGraph()->lockContextIfAttached();
+    object->setState(state);
+    object->getGraph()->unlockContextIfAttached();
+}
+
+const char *zg_object_get_value_name(ZGObject *object) {
+    return object->getValueName();
+}
+
 #pragma mark - Context
 
 ZGContext *zg_context_new(int numInputChannels, int numOutputChannels,
@@ -342,6 +479,13 @@ ZGGraph *zg_context_get_graphs(ZGContext *context, unsigned int *n) {
     return NULL;
 }
 
+unsigned int zg_context_get_num_graphs(ZGContext *context) {
+    context->lock();
+    unsigned int n = context->getNumGraphs();
+    context->unlock();
+    return n;
+}
+
 void zg_context_register_external_object(
     ZGContext *context, const char *objectLabel,
     ZGObject *(*factory)(ZGMessage *message, ZGGraph *graph)) {
@@ -353,10 +497,32 @@ void zg_context_unregister_external_object(ZGContext *context,
     context->unregisterExternalObject(objectLabel);
 }
 
+const char **zg_context_get_object_labels(ZGContext *context, unsigned int *n) {
+    context->lock();
+    list<const char *> objectLabels = context->getObjectLabels();
+    context->unlock();
+    list<const char *>::iterator it = objectLabels.begin();
+    *n = (unsigned int)objectLabels.size();
+    const char **labelArray =
+        (const char **)malloc(objectLabels.size() * sizeof(const char *));
+    for (unsigned int i = 0; i < *n; i++, it++) {
+        labelArray[i] = *it;
+    }
+    return labelArray;
+}
+
+bool zg_context_is_external_object(ZGContext *context,
+                                   const char *objectLabel) {
+    return context->isExternalObject(objectLabel);
+}
+
 #pragma mark - Objects from Context
 
-ZGObject *zg_context_get_table_for_name(ZGObject *table, const char *name) {
-    return NULL; // TODO(mhroth): implement this
+ZGObject *zg_context_get_table_for_name(ZGContext *context, const char *name) {
+    context->lock();
+    MessageTable *table = context->getTable(name);
+    context->unlock();
+    return table;
 }
 
 #pragma mark - Context Un/Register External Receivers
@@ -436,6 +602,44 @@ void zg_graph_unattach(ZGGraph *graph) {
     graph->getContext()->unattachGraph(graph);
 }
 
+bool zg_graph_is_attached(ZGGraph *graph) { return graph->isAttached(); }
+
+ZGGraph *zg_graph_get_root(ZGGraph *graph) {
+    while (graph->getParentGraph() != NULL) {
+        graph = graph->getParentGraph();
+    }
+    return graph;
+}
+
+void zg_graph_fade_output(ZGGraph *graph, float duration_ms, bool fade_in) {
+    PdContext *context = graph->getContext();
+    context->lock();
+    graph->fadeOutput(duration_ms * context->getSampleRate() / 1000.0f,
+                      fade_in);
+    context->unlock();
+}
+
+bool zg_graph_is_fading_output(ZGGraph *graph) {
+    PdContext *context = graph->getContext();
+    context->lock();
+    bool fading = graph->isFadingOutput();
+    context->unlock();
+    return fading;
+}
+
+void zg_graph_lock_context(ZGGraph *graph) { graph->getContext()->lock(); }
+
+void zg_graph_compute_dsp_order(ZGGraph *graph) {
+    PdGraph *root = zg_graph_get_root(graph);
+    root->getContext()->lock();
+    if (root->isAttached()) {
+        root->computeDeepLocalDspProcessOrder();
+    }
+    root->getContext()->unlock();
+}
+
+void zg_graph_unlock_context(ZGGraph *graph) { graph->getContext()->unlock(); }
+
 void zg_graph_add_connection(ZGGraph *graph, ZGObject *fromObject,
                              int outletIndex, ZGObject *toObject,
                              int inletIndex) {
@@ -466,6 +670,20 @@ ZGObject **zg_graph_get_objects(ZGGraph *graph, unsigned int *n) {
     return nodeArray;
 }
 
+ZGObject **zg_graph_get_dsp_order(ZGGraph *graph, unsigned int *n) {
+    graph->getContext()->lock();
+    list<DspObject *> dspNodeList = graph->getDspNodeList();
+    graph->getContext()->unlock();
+    list<DspObject *>::iterator it = dspNodeList.begin();
+    *n = (unsigned int)dspNodeList.size();
+    ZGObject **nodeArray =
+        (ZGObject **)malloc(dspNodeList.size() * sizeof(ZGObject *));
+    for (unsigned int i = 0; i < *n; i++, it++) {
+        nodeArray[i] = *it;
+    }
+    return nodeArray;
+}
+
 #pragma mark - Table
 
 float *zg_table_get_buffer(MessageObject *table, unsigned int *n) {
@@ -493,6 +711,169 @@ void zg_table_set_buffer(MessageObject *table, float *buffer, unsigned int n) {
     }
 }
 
+const char *zg_table_get_name(ZGObject *table) {
+    if (table != NULL && table->getObjectType() == MESSAGE_TABLE) {
+        return reinterpret_cast<MessageTable *>(table)->getName();
+    }
+    return NULL;
+}
+
+unsigned int zg_table_get_length(ZGObject *table) {
+    if (table != NULL && table->getObjectType() == MESSAGE_TABLE) {
+        MessageTable *messageTable = reinterpret_cast<MessageTable *>(table);
+        int n = 0;
+        messageTable->getBuffer(&n);
+        return (unsigned int)n;
+    }
+    return 0;
+}
+
+int zg_table_read(ZGObject *table, unsigned int offset, float *buffer,
+                  unsigned int n) {
+    if (table == NULL || table->getObjectType() != MESSAGE_TABLE) {
+        return -1;
+    }
+    MessageTable *messageTable = reinterpret_cast<MessageTable *>(table);
+    int result = -1;
+    messageTable->getGraph()->lockContextIfAttached();
+    int length = 0;
+    float *tableBuffer = messageTable->getBuffer(&length);
+    if ((unsigned long)offset + n <= (unsigned long)length) {
+        memcpy(buffer, tableBuffer + offset, n * sizeof(float));
+        result = 0;
+    }
+    messageTable->getGraph()->unlockContextIfAttached();
+    return result;
+}
+
+int zg_table_write(ZGObject *table, unsigned int offset, float *buffer,
+                   unsigned int n) {
+    if (table == NULL || table->getObjectType() != MESSAGE_TABLE) {
+        return -1;
+    }
+    MessageTable *messageTable = reinterpret_cast<MessageTable *>(table);
+    int result = -1;
+    messageTable->getGraph()->lockContextIfAttached();
+    int length = 0;
+    float *tableBuffer = messageTable->getBuffer(&length);
+    if ((unsigned long)offset + n <= (unsigned long)length) {
+        memcpy(tableBuffer + offset, buffer, n * sizeof(float));
+        result = 0;
+    }
+    messageTable->getGraph()->unlockContextIfAttached();
+    return result;
+}
+
+void zg_table_resize(ZGObject *table, unsigned int n) {
+    if (table != NULL && table->getObjectType() == MESSAGE_TABLE) {
+        MessageTable *messageTable = reinterpret_cast<MessageTable *>(table);
+        messageTable->getGraph()->lockContextIfAttached();
+        messageTable->resizeBuffer(n);
+        messageTable->getGraph()->unlockContextIfAttached();
+    }
+}
+
+int zg_table_load_audio_file(ZGObject *table, const char *path,
+                             unsigned int channel, int resize) {
+    if (table == NULL || table->getObjectType() != MESSAGE_TABLE) {
+        return -3;
+    }
+    MessageTable *messageTable = reinterpret_cast<MessageTable *>(table);
+
+    SF_INFO sfInfo;
+    memset(&sfInfo, 0, sizeof(SF_INFO));
+    SNDFILE *sndFile = sf_open(path, SFM_READ, &sfInfo);
+    if (sndFile == NULL) {
+        return -1;
+    }
+    if (sfInfo.channels <= 0 || channel >= (unsigned int)sfInfo.channels) {
+        sf_close(sndFile);
+        return -2;
+    }
+
+    // the frames are read as a whole, so they must fit into an int
+    if (sfInfo.frames <= 0 || sfInfo.frames > INT_MAX / sfInfo.channels) {
+        sf_close(sndFile);
+        return -4;
+    }
+
+    // read the file before locking the context, as this may take a while
+    int numFrames = (int)sfInfo.frames;
+    float *fileBuffer =
+        (float *)malloc((size_t)numFrames * sfInfo.channels * sizeof(float));
+    if (fileBuffer == NULL) {
+        sf_close(sndFile);
+        return -5;
+    }
+    numFrames = (int)sf_readf_float(sndFile, fileBuffer, numFrames);
+    sf_close(sndFile);
+
+    messageTable->getGraph()->lockContextIfAttached();
+    int tableLength = 0;
+    float *tableBuffer = resize && numFrames > 0
+                             ? messageTable->resizeBuffer(numFrames)
+                             : messageTable->getBuffer(&tableLength);
+    if (resize && numFrames > 0) {
+        tableLength = numFrames;
+    }
+    int numSamples = numFrames < tableLength ? numFrames : tableLength;
+    for (int i = 0; i < numSamples; i++) {
+        tableBuffer[i] = fileBuffer[i * sfInfo.channels + channel];
+    }
+    if (numSamples < tableLength) {
+        memset(tableBuffer + numSamples, 0,
+               (tableLength - numSamples) * sizeof(float));
+    }
+    messageTable->getGraph()->unlockContextIfAttached();
+
+    free(fileBuffer);
+    return numSamples;
+}
+
+int zg_table_save_audio_file(ZGObject *table, const char *path,
+                             ZGAudioFileFormat format) {
+    if (table == NULL || table->getObjectType() != MESSAGE_TABLE) {
+        return -3;
+    }
+    MessageTable *messageTable = reinterpret_cast<MessageTable *>(table);
+
+    SF_INFO sfInfo;
+    memset(&sfInfo, 0, sizeof(SF_INFO));
+    sfInfo.channels = 1;
+    sfInfo.samplerate = (int)messageTable->getGraph()->getSampleRate();
+    switch (format) {
+    case ZG_AUDIO_FILE_AIFF:
+        sfInfo.format = SF_FORMAT_AIFF | SF_FORMAT_FLOAT;
+        break;
+    case ZG_AUDIO_FILE_FLAC:
+        sfInfo.format = SF_FORMAT_FLAC | SF_FORMAT_PCM_24;
+        break;
+    case ZG_AUDIO_FILE_WAV:
+    default:
+        sfInfo.format = SF_FORMAT_WAV | SF_FORMAT_FLOAT;
+        break;
+    }
+
+    // copy the table, so the context isn't locked while writing the file
+    messageTable->getGraph()->lockContextIfAttached();
+    int tableLength = 0;
+    float *tableBuffer = messageTable->getBuffer(&tableLength);
+    float *buffer = (float *)malloc((size_t)tableLength * sizeof(float));
+    memcpy(buffer, tableBuffer, tableLength * sizeof(float));
+    messageTable->getGraph()->unlockContextIfAttached();
+
+    SNDFILE *sndFile = sf_open(path, SFM_WRITE, &sfInfo);
+    if (sndFile == NULL) {
+        free(buffer);
+        return -1;
+    }
+    int numFrames = (int)sf_writef_float(sndFile, buffer, tableLength);
+    sf_close(sndFile);
+
+    free(buffer);
+    return numFrames;
+}
+
 #pragma mark - Message
 
 ZGMessage *zg_message_new(double timetamp, unsigned int numElements) {
@@ -572,3 +953,7 @@ void zg_context_unregister_memorymapped_abstraction(ZGContext *context,
                                                     const char *objectLabel) {
     context->getAbstractionDataBase()->removeAbstraction(objectLabel);
 }
+
+#pragma mark - Memory
+
+void zg_mem_free(void *ptr) { free(ptr); }